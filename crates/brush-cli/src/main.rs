@@ -0,0 +1,384 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use brush_dataset::{
+    camera_export,
+    directory::DatasetDirectory,
+    fs::DatasetFs,
+    run_config::{run_file_path, RunConfig},
+    scene_loader::SceneLoader,
+    splat_export,
+    zip::DatasetZip,
+    Dataset, LoadDatasetArgs, LoadInitArgs,
+};
+use brush_render::{
+    gaussian_splats::{RandomSplatsConfig, Splats},
+    AutodiffBackend, Backend,
+};
+use brush_train::{
+    scene::Scene,
+    train::{SplatTrainer, TrainConfig},
+};
+use burn::{
+    backend::{ndarray::NdArrayDevice, wgpu::WgpuDevice, Autodiff, NdArray, Wgpu},
+    config::Config,
+    module::AutodiffModule,
+    tensor::ElementConversion,
+};
+use clap::Parser;
+use rand::SeedableRng;
+use tokio_stream::StreamExt;
+use web_time::Instant;
+
+/// Train gaussian splats on a dataset without a GUI, and export the result as a ply file.
+#[derive(Parser)]
+#[command(name = "brush", version, about)]
+struct Args {
+    /// Path to the dataset, either a directory or a zip file.
+    dataset: PathBuf,
+
+    /// Where to write the trained splats.
+    #[arg(short, long, default_value = "export.ply")]
+    output: PathBuf,
+
+    /// Number of training steps to run.
+    #[arg(long, default_value_t = 30000)]
+    total_steps: u32,
+
+    /// Number of views to train on in each step.
+    #[arg(long, default_value_t = 1)]
+    batch_size: usize,
+
+    /// JSON file with the training config. Uses the default config if not set.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Run file (TOML or JSON) with the training config, dataset options and number of steps, as
+    /// saved next to every export. When set, the options it covers are ignored. Training again
+    /// from a run file uses the same settings, but training on the GPU isn't deterministic, so
+    /// the splats won't be identical.
+    #[arg(long, conflicts_with = "config")]
+    run: Option<PathBuf>,
+
+    /// Seed for the initial splats and the order of training views.
+    #[arg(long, default_value_t = 42)]
+    seed: u64,
+
+    /// Print training progress every this many steps.
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    print_every: u32,
+
+    /// Write an intermediate ply every this many steps, next to the output file.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    export_every: Option<u32>,
+
+    /// Save a checkpoint every this many steps, next to the output file.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    checkpoint_every: Option<u32>,
+
+    /// Resume training from a checkpoint.
+    #[arg(long)]
+    resume: Option<PathBuf>,
+
+    /// Evaluate on the eval views every this many steps.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    eval_every: Option<u32>,
+
+    /// Fit a colour transform to each eval view before computing metrics. Use this when
+    /// training with per view appearance transforms, as eval views don't have one.
+    ///
+    /// The transform is fitted on one half of a checkerboard of the pixels and scored on the
+    /// other half, so it can only correct the colours, not the details.
+    #[arg(long)]
+    eval_fit_appearance: bool,
+
+    /// Train on the CPU instead of the GPU. This is very slow, but works on machines without a GPU.
+    #[arg(long)]
+    cpu: bool,
+
+    /// Spherical harmonics degree of the splats.
+    #[arg(long, default_value_t = 3)]
+    sh_degree: u32,
+
+    /// Max number of frames to load from the dataset.
+    #[arg(long)]
+    max_frames: Option<usize>,
+
+    /// Max resolution of the training images.
+    #[arg(long)]
+    max_resolution: Option<u32>,
+
+    /// Use every nth frame as an eval view.
+    #[arg(long)]
+    eval_split_every: Option<usize>,
+
+    /// Only load every nth frame.
+    #[arg(long)]
+    subsample_frames: Option<u32>,
+
+    /// Only load every nth point of the initial point cloud.
+    #[arg(long)]
+    subsample_points: Option<u32>,
+}
+
+/// A path next to the output file, eg. `export_checkpoint.safetensors` for `export.ply`.
+fn output_sibling(output: &Path, suffix: &str) -> PathBuf {
+    let stem = output
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or("export".into());
+    output.with_file_name(format!("{stem}_{suffix}"))
+}
+
+/// Export the splats, and the run file to reproduce them next to it.
+async fn export_splats<B: Backend>(
+    splats: Splats<B>,
+    run: &RunConfig,
+    path: &Path,
+) -> anyhow::Result<()> {
+    let data = splat_export::splat_to_ply(splats).await?;
+    std::fs::write(path, data).with_context(|| format!("Failed to write {}", path.display()))?;
+    let run_path = run_file_path(path);
+    std::fs::write(&run_path, run.to_toml()?)
+        .with_context(|| format!("Failed to write {}", run_path.display()))?;
+    Ok(())
+}
+
+/// The run description, either from a run file or from the command line options.
+fn run_config(args: &Args) -> anyhow::Result<RunConfig> {
+    if let Some(path) = &args.run {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read run file {}", path.display()))?;
+        return RunConfig::parse(&text)
+            .with_context(|| format!("Failed to parse run file {}", path.display()));
+    }
+
+    let train = if let Some(path) = &args.config {
+        TrainConfig::load(path)
+            .with_context(|| format!("Failed to load train config {}", path.display()))?
+    } else {
+        TrainConfig::default()
+    };
+
+    Ok(RunConfig::new()
+        .with_train(train.with_seed(args.seed))
+        .with_load_args(LoadDatasetArgs {
+            max_frames: args.max_frames,
+            max_resolution: args.max_resolution,
+            eval_split_every: args.eval_split_every,
+            subsample_frames: args.subsample_frames,
+            subsample_points: args.subsample_points,
+        })
+        .with_init_args(LoadInitArgs {
+            sh_degree: args.sh_degree,
+        })
+        .with_total_steps(Some(args.total_steps))
+        .with_batch_size(args.batch_size))
+}
+
+/// Write the refined training cameras as a nerfstudio transforms.json, and as a COLMAP
+/// text model, next to the output file.
+fn export_cameras(scene: &Scene, output: &Path) -> anyhow::Result<()> {
+    let path = output_sibling(output, "transforms.json");
+    let json = camera_export::scene_to_transforms_json(scene)?;
+    std::fs::write(&path, json).with_context(|| format!("Failed to write {}", path.display()))?;
+    println!("Exported refined cameras to {}", path.display());
+
+    let dir = output_sibling(output, "colmap");
+    let colmap = camera_export::scene_to_colmap_text(scene)?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    for (name, text) in [
+        ("cameras.txt", colmap.cameras),
+        ("images.txt", colmap.images),
+        ("points3D.txt", colmap.points3d),
+    ] {
+        let path = dir.join(name);
+        std::fs::write(&path, text)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    println!("Exported refined cameras to {}", dir.display());
+    Ok(())
+}
+
+async fn run(args: Args) -> anyhow::Result<()> {
+    if args.dataset.is_dir() {
+        let dataset = DatasetDirectory::new(&args.dataset)?;
+        run_on_device(dataset, args).await
+    } else {
+        let bytes = std::fs::read(&args.dataset)
+            .with_context(|| format!("Failed to read dataset {}", args.dataset.display()))?;
+        run_on_device(DatasetZip::from_data(bytes)?, args).await
+    }
+}
+
+async fn run_on_device(dataset_fs: impl DatasetFs, args: Args) -> anyhow::Result<()> {
+    if args.cpu {
+        train::<Autodiff<NdArray>>(dataset_fs, args, NdArrayDevice::Cpu).await
+    } else {
+        train::<Autodiff<Wgpu>>(dataset_fs, args, WgpuDevice::DefaultDevice).await
+    }
+}
+
+async fn train<B: AutodiffBackend>(
+    dataset_fs: impl DatasetFs,
+    args: Args,
+    device: B::Device,
+) -> anyhow::Result<()>
+where
+    B::InnerBackend: Backend,
+{
+    let run = run_config(&args)?;
+    let config = run.train.clone();
+    let total_steps = run.total_steps.unwrap_or(args.total_steps);
+
+    <B as burn::prelude::Backend>::seed(run.seed());
+    let mut rng = rand::rngs::StdRng::seed_from_u64(run.seed());
+
+    let (mut splat_stream, mut data_stream) =
+        brush_dataset::load_dataset(dataset_fs, &run.load_args, &device)?;
+
+    let mut initial_splats = None;
+    while let Some(message) = splat_stream.next().await {
+        let message = message?;
+        initial_splats = Some(message.splats.with_min_sh_degree(run.init_args.sh_degree));
+    }
+
+    let mut dataset = Dataset::empty();
+    while let Some(d) = data_stream.next().await {
+        dataset = d?;
+    }
+
+    println!(
+        "Loaded {} training views, {} eval views",
+        dataset.train.views.len(),
+        dataset.eval.as_ref().map_or(0, |e| e.views.len())
+    );
+
+    let (mut trainer, mut splats) = if let Some(path) = &args.resume {
+        let data = std::fs::read(path)
+            .with_context(|| format!("Failed to read checkpoint {}", path.display()))?;
+        let (trainer, splats) = SplatTrainer::load_checkpoint(&data, &config, &device)?;
+        let trainer = trainer.with_train_views(dataset.train.views.len(), &device);
+        println!("Resuming from step {}", trainer.iter);
+        (trainer, splats)
+    } else {
+        let splats: Splats<B> = if let Some(splats) = initial_splats {
+            splats
+        } else {
+            // Same as the viewer, spawn random splats in the area of interest.
+            let bounds = dataset.train.bounds(0.0, 0.0);
+            let bounds_extent = bounds.extent.length();
+            let adjusted_bounds = dataset.train.bounds(bounds_extent * 0.25, bounds_extent);
+            let config = RandomSplatsConfig::new().with_sh_degree(run.init_args.sh_degree);
+            Splats::from_random_config(config, adjusted_bounds, &mut rng, &device)
+        };
+        (
+            SplatTrainer::new(splats.num_splats(), &config, &device)
+                .with_train_views(dataset.train.views.len(), &device),
+            splats,
+        )
+    };
+
+    let mut dataloader = SceneLoader::new(
+        &dataset.train,
+        run.batch_size,
+        run.seed(),
+        trainer.iter,
+        &device,
+    );
+
+    let start = Instant::now();
+    let mut last_print = (start, trainer.iter);
+
+    while trainer.iter < total_steps {
+        let batch = dataloader.next_batch().await;
+        splats = trainer.update_filter_3d(splats, &dataset.train);
+        let (new_splats, stats) = trainer.step(batch, splats).await?;
+        splats = new_splats;
+
+        let iter = trainer.iter;
+
+        if iter % args.print_every == 0 || iter == total_steps {
+            let loss = stats.loss.into_scalar_async().await.elem::<f32>();
+            let now = Instant::now();
+            let steps_per_sec = (iter - last_print.1) as f32 / (now - last_print.0).as_secs_f32();
+            last_print = (now, iter);
+
+            println!(
+                "[{iter}/{}] loss: {loss:.5}, splats: {}, {steps_per_sec:.1} steps/s",
+                total_steps,
+                splats.num_splats(),
+            );
+        }
+
+        if let Some(refine) = stats.refine {
+            log::info!(
+                "Refine at {iter}: split {}, cloned {}, relocated {}, rejected {}, pruned {} transparent, {} large",
+                refine.num_split,
+                refine.num_cloned,
+                refine.num_relocated,
+                refine.num_rejected,
+                refine.num_transparent_pruned,
+                refine.num_scale_pruned
+            );
+        }
+
+        if let Some(eval_scene) = dataset.eval.as_ref() {
+            if args.eval_every.is_some_and(|every| iter % every == 0) {
+                let eval = brush_train::eval::eval_stats(
+                    splats.valid(),
+                    eval_scene,
+                    None,
+                    args.eval_fit_appearance,
+                    &mut rng,
+                    &device,
+                )
+                .await;
+                let count = eval.samples.len() as f32;
+                let psnr = eval.samples.iter().map(|s| s.psnr).sum::<f32>() / count;
+                let ssim = eval.samples.iter().map(|s| s.ssim).sum::<f32>() / count;
+                println!(
+                    "[{iter}/{}] eval psnr: {psnr:.3}, ssim: {ssim:.4}",
+                    total_steps
+                );
+            }
+        }
+
+        if args.export_every.is_some_and(|every| iter % every == 0) && iter != total_steps {
+            let path = output_sibling(&args.output, &format!("{iter}.ply"));
+            export_splats(splats.valid(), &run, &path).await?;
+            println!("Exported {}", path.display());
+        }
+
+        if args.checkpoint_every.is_some_and(|every| iter % every == 0) {
+            let path = output_sibling(&args.output, "checkpoint.safetensors");
+            let data = trainer.save_checkpoint(&splats).await?;
+            std::fs::write(&path, data)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            println!("Saved checkpoint at step {iter} to {}", path.display());
+        }
+    }
+
+    export_splats(splats.valid(), &run, &args.output).await?;
+    println!(
+        "Finished training in {:.1}s, exported {} splats to {}",
+        start.elapsed().as_secs_f32(),
+        splats.num_splats(),
+        args.output.display()
+    );
+
+    if let Some(scene) = trainer.refined_scene(&dataset.train).await {
+        // The splats are already written, don't fail the run over the cameras.
+        if let Err(err) = export_cameras(&scene, &args.output) {
+            log::warn!("Failed to export refined cameras: {err:#}");
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    run(Args::parse()).await
+}
@@ -0,0 +1,161 @@
+// Write the cameras of a scene back to the formats datasets are loaded from, eg. to use the
+// poses refined during training in other tools.
+//
+// Distorted images are undistorted when loading, so the cameras are written as ideal cameras for
+// the undistorted images, with the resolution they were trained at. Views with a projection the
+// format can't describe are skipped with a warning.
+use std::fmt::Write;
+use std::path::Path;
+
+use brush_render::camera::Projection;
+use brush_train::scene::{Scene, SceneView};
+
+#[derive(serde::Serialize)]
+struct JsonFrame {
+    file_path: String,
+    transform_matrix: [[f32; 4]; 4],
+    fl_x: f32,
+    fl_y: f32,
+    cx: f32,
+    cy: f32,
+    w: u32,
+    h: u32,
+}
+
+#[derive(serde::Serialize)]
+struct JsonScene {
+    camera_model: &'static str,
+    frames: Vec<JsonFrame>,
+}
+
+fn image_size(view: &SceneView) -> glam::UVec2 {
+    glam::uvec2(view.image.width(), view.image.height())
+}
+
+fn nerfstudio_model(projection: Projection) -> Option<&'static str> {
+    match projection {
+        Projection::Pinhole => Some("PINHOLE"),
+        // An undistorted fisheye, which loads back as an ideal fisheye.
+        Projection::Fisheye => Some("OPENCV_FISHEYE"),
+        Projection::Equirectangular => Some("EQUIRECTANGULAR"),
+        Projection::Orthographic { .. } => None,
+    }
+}
+
+/// The cameras of a scene as a nerfstudio transforms.json file.
+///
+/// Nerfstudio has a single camera model for the whole scene, views with a different model than
+/// the first exportable view are skipped.
+pub fn scene_to_transforms_json(scene: &Scene) -> anyhow::Result<String> {
+    let camera_model = scene
+        .views
+        .iter()
+        .find_map(|v| nerfstudio_model(v.camera.projection))
+        .unwrap_or("PINHOLE");
+
+    let frames = scene
+        .views
+        .iter()
+        .filter(|view| {
+            let model = nerfstudio_model(view.camera.projection);
+            if model != Some(camera_model) {
+                log::warn!(
+                    "Skipping {:?} camera of {} in transforms.json export",
+                    view.camera.projection,
+                    view.name
+                );
+            }
+            model == Some(camera_model)
+        })
+        .map(|view| {
+            let camera = &view.camera;
+            let img_size = image_size(view);
+            let focal = camera.focal(img_size);
+            let center = camera.center(img_size);
+
+            // Swap the basis back, see the nerfstudio loader.
+            let mut transform = camera.local_to_world();
+            transform.y_axis *= -1.0;
+            transform.z_axis *= -1.0;
+
+            JsonFrame {
+                file_path: view.name.clone(),
+                // Nerfstudio matrices are row major.
+                transform_matrix: transform.transpose().to_cols_array_2d(),
+                fl_x: focal.x,
+                fl_y: focal.y,
+                cx: center.x,
+                cy: center.y,
+                w: img_size.x,
+                h: img_size.y,
+            }
+        })
+        .collect();
+
+    Ok(serde_json::to_string_pretty(&JsonScene {
+        camera_model,
+        frames,
+    })?)
+}
+
+/// The cameras of a scene in the COLMAP text format.
+pub struct ColmapText {
+    pub cameras: String,
+    pub images: String,
+    /// Doesn't have any points, but COLMAP expects the file to be there.
+    pub points3d: String,
+}
+
+/// The cameras of a scene in the COLMAP text format, with a camera for every view. COLMAP only
+/// has pinhole and fisheye cameras, other views are skipped.
+pub fn scene_to_colmap_text(scene: &Scene) -> anyhow::Result<ColmapText> {
+    let mut cameras = String::from("# CAMERA_ID, MODEL, WIDTH, HEIGHT, PARAMS[]\n");
+    let mut images = String::from(
+        "# IMAGE_ID, QW, QX, QY, QZ, TX, TY, TZ, CAMERA_ID, NAME\n# POINTS2D[] as (X, Y, POINT3D_ID)\n",
+    );
+
+    for (i, view) in scene.views.iter().enumerate() {
+        let camera = &view.camera;
+        // Fisheye cameras are undistorted, so leave the distortion coefficients at zero.
+        let (model, distortion) = match camera.projection {
+            Projection::Pinhole => ("PINHOLE", ""),
+            Projection::Fisheye => ("OPENCV_FISHEYE", " 0 0 0 0"),
+            Projection::Equirectangular | Projection::Orthographic { .. } => {
+                log::warn!(
+                    "Skipping {:?} camera of {} in COLMAP export",
+                    camera.projection,
+                    view.name
+                );
+                continue;
+            }
+        };
+
+        let id = i + 1;
+        let img_size = image_size(view);
+        let focal = camera.focal(img_size);
+        let center = camera.center(img_size);
+        writeln!(
+            cameras,
+            "{id} {model} {} {} {} {} {} {}{distortion}",
+            img_size.x, img_size.y, focal.x, focal.y, center.x, center.y
+        )?;
+
+        let (_, quat, tvec) = camera.world_to_local().to_scale_rotation_translation();
+        // COLMAP images are relative to the images folder.
+        let name = Path::new(&view.name)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(&view.name);
+        writeln!(
+            images,
+            "{id} {} {} {} {} {} {} {} {id} {name}\n",
+            quat.w, quat.x, quat.y, quat.z, tvec.x, tvec.y, tvec.z
+        )?;
+    }
+
+    Ok(ColmapText {
+        cameras,
+        images,
+        points3d: String::from("# POINT3D_ID, X, Y, Z, R, G, B, ERROR, TRACK[]\n"),
+    })
+}
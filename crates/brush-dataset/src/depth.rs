@@ -0,0 +1,192 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use brush_train::scene::DepthImage;
+use image::DynamicImage;
+
+// Read a numpy .npy file with a single [h, w] or [h, w, 1] array.
+fn read_npy(data: &[u8], unit_scale: f32) -> Result<DepthImage> {
+    let rest = data
+        .strip_prefix(b"\x93NUMPY")
+        .context("Not a valid .npy file")?;
+    let major_version = *rest.first().context("Truncated .npy file")?;
+
+    // Version 1 stores the header length as u16, later versions as u32.
+    let truncated = || "Truncated .npy file";
+    let (header_len, rest) = if major_version == 1 {
+        let len = rest.get(2..4).with_context(truncated)?;
+        let len = u16::from_le_bytes(len.try_into()?) as usize;
+        (len, &rest[4..])
+    } else {
+        let len = rest.get(2..6).with_context(truncated)?;
+        let len = u32::from_le_bytes(len.try_into()?) as usize;
+        (len, &rest[6..])
+    };
+    let header = rest.get(..header_len).with_context(truncated)?;
+    let header = std::str::from_utf8(header)?;
+    let body = &rest[header_len..];
+
+    // The header is a python dict literal, eg.
+    // {'descr': '<f4', 'fortran_order': False, 'shape': (480, 640), }
+    let field = |name: &str| {
+        header
+            .split_once(&format!("'{name}':"))
+            .map(|(_, val)| val.trim_start())
+            .with_context(|| format!("Missing {name} in .npy header"))
+    };
+
+    let descr = field("descr")?
+        .split('\'')
+        .nth(1)
+        .context("Invalid .npy dtype")?;
+
+    if field("fortran_order")?.starts_with("True") {
+        bail!("Fortran ordered .npy files are not supported");
+    }
+
+    let shape = field("shape")?;
+    let shape = &shape[1..shape.find(')').context("Invalid .npy shape")?];
+    let shape = shape
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<u32>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (h, w) = match shape[..] {
+        [h, w] | [h, w, 1] => (h, w),
+        _ => bail!("Depth .npy must have shape [h, w], got {shape:?}"),
+    };
+
+    let values: Vec<f32> = match descr {
+        "<f4" => body
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+            .collect(),
+        "<f8" => body
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32)
+            .collect(),
+        "<u2" => body
+            .chunks_exact(2)
+            .map(|b| u16::from_le_bytes(b.try_into().unwrap()) as f32 * unit_scale)
+            .collect(),
+        _ => bail!("Unsupported .npy dtype {descr}"),
+    };
+
+    DepthImage::from_raw(w, h, values).context("Depth .npy has too little data")
+}
+
+/// Load a depth map from a 16 bit PNG, a float EXR, or a .npy file.
+///
+/// Integer depths are multiplied by `unit_scale` to convert them to world units,
+/// eg. 0.001 for depths in millimetres. Float depths are assumed to be in world units already.
+pub(crate) fn load_depth(data: &[u8], path: &Path, unit_scale: f32) -> Result<DepthImage> {
+    let is_npy = path.extension().is_some_and(|ext| ext == "npy");
+
+    let mut depth = if is_npy {
+        read_npy(data, unit_scale)?
+    } else {
+        match image::load_from_memory(data)? {
+            DynamicImage::ImageLuma16(img) => {
+                DepthImage::from_fn(img.width(), img.height(), |x, y| {
+                    image::Luma([img.get_pixel(x, y)[0] as f32 * unit_scale])
+                })
+            }
+            img @ (DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_)) => {
+                // Single channel EXRs are decoded as gray RGB, just take the first channel.
+                let img = img.to_rgb32f();
+                DepthImage::from_fn(img.width(), img.height(), |x, y| {
+                    image::Luma([img.get_pixel(x, y)[0]])
+                })
+            }
+            _ => bail!("Depth images must be a 16 bit grayscale PNG or a float EXR"),
+        }
+    };
+
+    // Treat invalid depths as unknown.
+    for d in depth.iter_mut() {
+        if !d.is_finite() || *d < 0.0 {
+            *d = 0.0;
+        }
+    }
+    Ok(depth)
+}
+
+/// Resize a depth map to match its image. Uses nearest neighbour sampling,
+/// as interpolating depths creates points floating between surfaces.
+pub(crate) fn resize_depth(depth: DepthImage, width: u32, height: u32) -> DepthImage {
+    if depth.width() == width && depth.height() == height {
+        return depth;
+    }
+    image::imageops::resize(&depth, width, height, image::imageops::FilterType::Nearest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_npy;
+
+    fn npy(major_version: u8, header: &str, body: &[u8]) -> Vec<u8> {
+        let mut data = b"\x93NUMPY".to_vec();
+        data.extend([major_version, 0]);
+        if major_version == 1 {
+            data.extend((header.len() as u16).to_le_bytes());
+        } else {
+            data.extend((header.len() as u32).to_le_bytes());
+        }
+        data.extend(header.as_bytes());
+        data.extend(body);
+        data
+    }
+
+    fn f32_body(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn reads_float_depths() {
+        let header = "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }";
+        let values = [0.5, 1.0, 1.5, 2.0, 2.5, 3.0];
+        let depth = read_npy(&npy(1, header, &f32_body(&values)), 1.0).unwrap();
+        assert_eq!((depth.width(), depth.height()), (3, 2));
+        assert_eq!(depth.get_pixel(2, 1)[0], 3.0);
+        assert_eq!(depth.into_raw(), values);
+    }
+
+    #[test]
+    fn reads_version_2_header() {
+        let header = "{'descr': '<f8', 'fortran_order': False, 'shape': (1, 2, 1), }";
+        let body: Vec<u8> = [1.0f64, 2.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let depth = read_npy(&npy(2, header, &body), 1.0).unwrap();
+        assert_eq!(depth.into_raw(), [1.0, 2.0]);
+    }
+
+    #[test]
+    fn scales_integer_depths() {
+        let header = "{'descr': '<u2', 'fortran_order': False, 'shape': (1, 2), }";
+        let body: Vec<u8> = [1000u16, 2500]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let depth = read_npy(&npy(1, header, &body), 0.5).unwrap();
+        assert_eq!(depth.into_raw(), [500.0, 1250.0]);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let header = "{'descr': '<f4', 'fortran_order': False, 'shape': (2, 3), }";
+        let data = npy(1, header, &f32_body(&[1.0; 6]));
+
+        // Truncated anywhere in the magic, the header length, the header or the body.
+        for len in [3, 8, 9, 20, data.len() - 1] {
+            assert!(read_npy(&data[..len], 1.0).is_err(), "Truncated at {len}");
+        }
+
+        let fortran = "{'descr': '<f4', 'fortran_order': True, 'shape': (2, 3), }";
+        assert!(read_npy(&npy(1, fortran, &f32_body(&[1.0; 6])), 1.0).is_err());
+        let color = "{'descr': '<f4', 'fortran_order': False, 'shape': (1, 2, 3), }";
+        assert!(read_npy(&npy(1, color, &f32_body(&[1.0; 6])), 1.0).is_err());
+        let int = "{'descr': '<i4', 'fortran_order': False, 'shape': (2, 3), }";
+        assert!(read_npy(&npy(1, int, &f32_body(&[1.0; 6])), 1.0).is_err());
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+
+use crate::fs::DatasetFs;
+
+/// A dataset stored as a regular folder on disk. Files are only read when they're needed,
+/// so this doesn't need to fit the dataset in memory.
+#[derive(Clone)]
+pub struct DatasetDirectory {
+    root: PathBuf,
+    file_names: Arc<Vec<String>>,
+}
+
+fn collect_files(
+    root: &Path,
+    dir: &Path,
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<String>,
+) -> std::io::Result<()> {
+    // Symlinked directories are followed, but every directory is only visited once, so a cycle
+    // of links doesn't recurse forever.
+    if !visited.insert(dir.canonicalize()?) {
+        return Ok(());
+    }
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            collect_files(root, &path, visited, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            // Always use forward slashes, the same as paths in a zip archive.
+            let components: Vec<_> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect();
+            files.push(components.join("/"));
+        }
+    }
+    Ok(())
+}
+
+impl DatasetDirectory {
+    pub fn new(root: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let root = root.into();
+        let mut file_names = vec![];
+        collect_files(&root, &root, &mut HashSet::new(), &mut file_names)
+            .with_context(|| format!("Failed to read directory {}", root.display()))?;
+        // Sort for a consistent order across platforms.
+        file_names.sort();
+
+        Ok(Self {
+            root,
+            file_names: Arc::new(file_names),
+        })
+    }
+}
+
+impl DatasetFs for DatasetDirectory {
+    fn file_names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.file_names.iter().map(|s| s.as_str())
+    }
+
+    fn file_at_path(&mut self, path: &Path) -> anyhow::Result<Box<dyn Read + '_>> {
+        let full_path = self.root.join(path);
+        let file = File::open(&full_path)
+            .with_context(|| format!("Failed to open {}", full_path.display()))?;
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::DatasetDirectory;
+    use crate::fs::DatasetFs;
+    use std::{io::Read, path::Path};
+
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("brush_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("images")).unwrap();
+        std::fs::write(dir.join("transforms.json"), "{}").unwrap();
+        std::fs::write(dir.join("images/0.png"), [1, 2, 3]).unwrap();
+        dir
+    }
+
+    #[test]
+    fn lists_and_reads_files() {
+        let dir = test_dir("directory_files");
+        let mut fs = DatasetDirectory::new(&dir).unwrap();
+
+        let names: Vec<_> = fs.file_names().map(|n| n.to_owned()).collect();
+        assert_eq!(names, ["images/0.png", "transforms.json"]);
+
+        let mut data = vec![];
+        fs.file_at_path(Path::new("images/0.png"))
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, [1, 2, 3]);
+        assert!(fs.file_at_path(Path::new("missing.png")).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycle_terminates() {
+        let dir = test_dir("directory_cycle");
+        std::os::unix::fs::symlink(&dir, dir.join("images/loop")).unwrap();
+
+        let fs = DatasetDirectory::new(&dir).unwrap();
+        let names: Vec<_> = fs.file_names().collect();
+        assert_eq!(names, ["images/0.png", "transforms.json"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,6 +1,9 @@
 use std::{future::Future, sync::Arc};
 
-use super::{DataStream, DatasetZip, LoadDatasetArgs};
+use super::{DataStream, LoadDatasetArgs};
+use crate::fs::DatasetFs;
+use crate::mask::{load_mask, MaskIndex};
+use crate::undistort::UndistortMap;
 use crate::{splat_import::SplatMessage, stream_fut_parallel, Dataset};
 use anyhow::{Context, Result};
 use async_fn_stream::try_fn_stream;
@@ -14,8 +17,8 @@ use brush_train::scene::SceneView;
 use glam::Vec3;
 use tokio_stream::StreamExt;
 
-fn read_views(
-    mut archive: DatasetZip,
+fn read_views<F: DatasetFs>(
+    mut archive: F,
     load_args: &LoadDatasetArgs,
 ) -> Result<Vec<impl Future<Output = Result<SceneView>>>> {
     log::info!("Loading colmap dataset");
@@ -61,6 +64,8 @@ fn read_views(
     // it is consistent
     img_info_list.sort_by_key(|key_img| key_img.0);
 
+    let masks = MaskIndex::new(&archive);
+
     let handles = img_info_list
         .into_iter()
         .take(load_args.max_frames.unwrap_or(usize::MAX))
@@ -69,13 +74,22 @@ fn read_views(
             let load_args = load_args.clone();
             let base_path = base_path.clone();
             let mut archive = archive.clone();
+            let masks = masks.clone();
 
             // Create a future to handle loading the image.
             async move {
                 let focal = cam_data.focal();
-
-                let fovx = camera::focal_to_fov(focal.0, cam_data.width as u32);
-                let fovy = camera::focal_to_fov(focal.1, cam_data.height as u32);
+                let distortion = cam_data.distortion();
+
+                // Fisheye images are undistorted to an ideal fisheye, see `UndistortMap`.
+                let (projection, focal_to_fov): (_, fn(f64, u32) -> f64) =
+                    if distortion.is_fisheye() {
+                        (Projection::Fisheye, camera::angular_focal_to_fov)
+                    } else {
+                        (Projection::Pinhole, camera::focal_to_fov)
+                    };
+                let fovx = focal_to_fov(focal.0, cam_data.width as u32);
+                let fovy = focal_to_fov(focal.1, cam_data.height as u32);
 
                 let center = cam_data.principal_point();
                 let center_uv = center / glam::vec2(cam_data.width as f32, cam_data.height as f32);
@@ -89,18 +103,47 @@ fn read_views(
                     img = crate::clamp_img_to_max_size(img, max);
                 }
 
+                let mut mask = if let Some(mask_path) = masks.find_mirrored_mask(&img_path) {
+                    let mask_bytes = archive.read_bytes_at_path(&mask_path)?;
+                    let mask = load_mask(&mask_bytes, img.width(), img.height())
+                        .with_context(|| format!("Failed to load mask {}", mask_path.display()))?;
+                    Some(mask)
+                } else {
+                    None
+                };
+
+                if !distortion.is_none() {
+                    // The image might have been downscaled, scale the intrinsics to match.
+                    let scale = glam::dvec2(
+                        img.width() as f64 / cam_data.width as f64,
+                        img.height() as f64 / cam_data.height as f64,
+                    );
+                    let map = UndistortMap::new(
+                        img.width(),
+                        img.height(),
+                        glam::dvec2(focal.0, focal.1) * scale,
+                        center.as_dvec2() * scale,
+                        &distortion,
+                    );
+                    img = map.undistort_image(&img);
+                    mask = map.undistort_mask(mask.as_ref());
+                }
+
                 // Convert w2c to c2w.
                 let world_to_cam =
                     glam::Affine3A::from_rotation_translation(img_info.quat, img_info.tvec);
                 let cam_to_world = world_to_cam.inverse();
                 let (_, quat, translation) = cam_to_world.to_scale_rotation_translation();
 
-                let camera = Camera::new(translation, quat, fovx, fovy, center_uv);
+                let camera = Camera::new(translation, quat, fovx, fovy, center_uv)
+                    .with_projection(projection);
 
                 let view = SceneView {
                     name: img_path.to_str().context("Invalid file name")?.to_owned(),
                     camera,
                     image: Arc::new(img),
+                    depth: None,
+                    mask: mask.map(Arc::new),
                 };
                 Ok(view)
             }
@@ -110,8 +153,8 @@ fn read_views(
     Ok(handles)
 }
 
-pub(crate) fn load_dataset<B: Backend>(
-    mut archive: DatasetZip,
+pub(crate) fn load_dataset<B: Backend, F: DatasetFs>(
+    mut archive: F,
     load_args: &LoadDatasetArgs,
     device: &B::Device,
 ) -> Result<(DataStream<SplatMessage<B>>, DataStream<Dataset>)> {
@@ -1,6 +1,6 @@
 use crate::{
+    fs::DatasetFs,
     splat_import::{load_splat_from_ply, SplatMessage},
-    zip::DatasetZip,
     Dataset, LoadDatasetArgs,
 };
 use anyhow::Result;
@@ -14,13 +14,13 @@ pub mod nerfstudio;
 // A dynamic stream of datasets
 type DataStream<T> = Pin<Box<dyn Stream<Item = Result<T>> + Send + 'static>>;
 
-pub fn load_dataset<B: Backend>(
-    mut archive: DatasetZip,
+pub fn load_dataset<B: Backend, F: DatasetFs>(
+    mut archive: F,
     load_args: &LoadDatasetArgs,
     device: &B::Device,
 ) -> anyhow::Result<(DataStream<SplatMessage<B>>, DataStream<Dataset>)> {
     let streams = nerfstudio::read_dataset(archive.clone(), load_args, device)
-        .or_else(|_| colmap::load_dataset::<B>(archive.clone(), load_args, device));
+        .or_else(|_| colmap::load_dataset::<B, F>(archive.clone(), load_args, device));
 
     let Ok(streams) = streams else {
         anyhow::bail!("Couldn't parse dataset as any format. Only some formats are supported.")
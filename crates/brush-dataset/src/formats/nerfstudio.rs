@@ -1,15 +1,19 @@
-use super::DatasetZip;
 use super::LoadDatasetArgs;
+use crate::depth::{load_depth, resize_depth};
+use crate::fs::DatasetFs;
+use crate::mask::{load_mask, MaskIndex};
 use crate::splat_import::load_splat_from_ply;
 use crate::splat_import::SplatMessage;
 use crate::stream_fut_parallel;
+use crate::undistort::UndistortMap;
 use crate::{clamp_img_to_max_size, DataStream, Dataset};
 use anyhow::Context;
 use anyhow::Result;
 use async_fn_stream::try_fn_stream;
-use brush_render::camera::{focal_to_fov, fov_to_focal, Camera};
+use brush_render::camera::{angular_focal_to_fov, focal_to_fov, fov_to_focal, Camera, Projection};
 use brush_render::Backend;
 use brush_train::scene::SceneView;
+use colmap_reader::Distortion;
 use std::future::Future;
 use std::io::Cursor;
 use std::path::PathBuf;
@@ -17,16 +21,18 @@ use std::sync::Arc;
 use tokio_stream::StreamExt;
 
 #[derive(serde::Deserialize, Clone)]
-#[allow(unused)] // not reading camera distortions yet.
 struct JsonScene {
     // Simple synthetic nerf camera model.
     camera_angle_x: Option<f64>,
-    // Not really used atm.
+    /// Camera model, used to interpret the distortion parameters.
     camera_model: Option<String>,
 
     // Nerfstudio doesn't mention this in their format? But fine to include really.
     ply_file_path: Option<String>,
 
+    /// Scale to convert integer depth images to world units. Defaults to millimetres.
+    depth_unit_scale_factor: Option<f64>,
+
     /// Focal length x
     fl_x: Option<f64>,
     /// Focal length y
@@ -57,7 +63,6 @@ struct JsonScene {
 }
 
 #[derive(serde::Deserialize, Clone)]
-#[allow(unused)] // not reading camera distortions yet.
 struct FrameData {
     // Nerfstudio format
     //
@@ -74,7 +79,6 @@ struct FrameData {
     /// Image height. Should be an integer but read as float, fine to truncate.
     h: Option<f64>,
 
-    // TODO: These are unused currently.
     /// First radial distortion parameter used by [OPENCV, OPENCV_FISHEYE]
     k1: Option<f64>,
     /// Second radial distortion parameter used by [OPENCV, OPENCV_FISHEYE]
@@ -90,22 +94,63 @@ struct FrameData {
 
     transform_matrix: Vec<Vec<f32>>,
     file_path: String,
+    /// Optional depth map for this frame. A 16 bit PNG in millimetres, or a float EXR or .npy
+    /// in world units. Only used when `depth_loss_weight` is set in the training config.
+    depth_file_path: Option<String>,
+    /// Optional mask for this frame. Pixels where the mask is 0 are ignored in training.
+    mask_path: Option<String>,
+}
+
+/// The lens distortion of a frame, falling back to the parameters of the whole scene.
+fn distortion(scene: &JsonScene, frame: &FrameData) -> Distortion {
+    let k1 = frame.k1.or(scene.k1).unwrap_or(0.0);
+    let k2 = frame.k2.or(scene.k2).unwrap_or(0.0);
+    let k3 = frame.k3.or(scene.k3).unwrap_or(0.0);
+    let k4 = frame.k4.or(scene.k4).unwrap_or(0.0);
+    let p1 = frame.p1.or(scene.p1).unwrap_or(0.0);
+    let p2 = frame.p2.or(scene.p2).unwrap_or(0.0);
+
+    match scene.camera_model.as_deref() {
+        Some("EQUIRECTANGULAR") => Distortion::None,
+        Some("OPENCV_FISHEYE") => Distortion::Fisheye {
+            radial: [k1, k2, k3, k4],
+            tangential: [0.0; 2],
+            thin_prism: [0.0; 2],
+        },
+        _ if [k1, k2, k3, k4, p1, p2].iter().all(|&k| k == 0.0) => Distortion::None,
+        _ => Distortion::OpenCv {
+            radial: [k1, k2, k3, k4],
+            radial_denominator: [0.0; 3],
+            tangential: [p1, p2],
+        },
+    }
 }
 
-fn read_transforms_file(
-    scene: JsonScene,
+fn read_transforms_file<F: DatasetFs>(
+    mut scene: JsonScene,
     transforms_path: PathBuf,
-    archive: DatasetZip,
+    archive: F,
     load_args: &LoadDatasetArgs,
 ) -> Result<Vec<impl Future<Output = anyhow::Result<SceneView>>>> {
-    let iter = scene
-        .frames
+    let frames = std::mem::take(&mut scene.frames);
+    let masks = MaskIndex::new(&archive);
+    let iter = frames
         .into_iter()
         .take(load_args.max_frames.unwrap_or(usize::MAX))
         .map(move |frame| {
             let mut archive = archive.clone();
+            let masks = masks.clone();
             let load_args = load_args.clone();
             let transforms_path = transforms_path.clone();
+            let distortion = distortion(&scene, &frame);
+            let projection = if scene.camera_model.as_deref() == Some("EQUIRECTANGULAR") {
+                Projection::Equirectangular
+            } else if distortion.is_fisheye() {
+                // Undistorted to an ideal fisheye, see `UndistortMap`.
+                Projection::Fisheye
+            } else {
+                Projection::Pinhole
+            };
 
             async move {
                 // NeRF 'transform_matrix' is a camera-to-world transform
@@ -142,27 +187,93 @@ fn read_transforms_file(
                     image = clamp_img_to_max_size(image, max_resolution);
                 }
 
-                let focal_x = frame
-                    .fl_x
-                    .or(scene.fl_x)
-                    .or(scene.camera_angle_x.map(|fx| fov_to_focal(fx, w)))
-                    .context("Must have a focal length of some kind.")?;
-
-                // Read fov y or derive it from the input.
-                let focal_y = frame.fl_y.or(scene.fl_y).unwrap_or(focal_x);
+                let camera = if projection == Projection::Equirectangular {
+                    // Panoramas always cover the full sphere, whatever intrinsics are given.
+                    Camera::new(
+                        translation,
+                        rotation,
+                        std::f64::consts::TAU,
+                        std::f64::consts::PI,
+                        glam::vec2(0.5, 0.5),
+                    )
+                    .with_projection(projection)
+                } else {
+                    let focal_x = frame
+                        .fl_x
+                        .or(scene.fl_x)
+                        .or(scene.camera_angle_x.map(|fx| fov_to_focal(fx, w)))
+                        .context("Must have a focal length of some kind.")?;
+
+                    // Read fov y or derive it from the input.
+                    let focal_y = frame.fl_y.or(scene.fl_y).unwrap_or(focal_x);
+
+                    let focal_to_fov = if projection == Projection::Fisheye {
+                        angular_focal_to_fov
+                    } else {
+                        focal_to_fov
+                    };
+                    let fovx = focal_to_fov(focal_x, w);
+                    let fovy = focal_to_fov(focal_y, h);
+
+                    let cx = frame.cx.or(scene.cx).unwrap_or(w as f64 / 2.0);
+                    let cy = frame.cy.or(scene.cy).unwrap_or(h as f64 / 2.0);
+
+                    let cuv = glam::vec2((cx / w as f64) as f32, (cy / h as f64) as f32);
+                    Camera::new(translation, rotation, fovx, fovy, cuv).with_projection(projection)
+                };
 
-                let fovx = focal_to_fov(focal_x, w);
-                let fovy = focal_to_fov(focal_y, h);
+                let depth = if let Some(depth_path) = &frame.depth_file_path {
+                    let depth_path = transforms_path.parent().unwrap().join(depth_path);
+                    let depth_buffer = archive.read_bytes_at_path(&depth_path)?;
+                    let unit_scale = scene.depth_unit_scale_factor.unwrap_or(1e-3) as f32;
+                    let depth =
+                        load_depth(&depth_buffer, &depth_path, unit_scale).with_context(|| {
+                            format!("Failed to load depth {}", depth_path.display())
+                        })?;
+                    Some(resize_depth(depth, image.width(), image.height()))
+                } else {
+                    None
+                };
 
-                let cx = frame.cx.or(scene.cx).unwrap_or(w as f64 / 2.0);
-                let cy = frame.cy.or(scene.cy).unwrap_or(h as f64 / 2.0);
+                let mask_path = if let Some(mask_path) = &frame.mask_path {
+                    Some(transforms_path.parent().unwrap().join(mask_path))
+                } else {
+                    masks.find_mirrored_mask(&path)
+                };
+                let mask = if let Some(mask_path) = mask_path {
+                    let mask_buffer = archive.read_bytes_at_path(&mask_path)?;
+                    let mask = load_mask(&mask_buffer, image.width(), image.height())
+                        .with_context(|| format!("Failed to load mask {}", mask_path.display()))?;
+                    Some(mask)
+                } else {
+                    None
+                };
 
-                let cuv = glam::vec2((cx / w as f64) as f32, (cy / h as f64) as f32);
+                let (image, depth, mask) = if distortion.is_none() {
+                    (image, depth, mask)
+                } else {
+                    // The image might have been downscaled, get the intrinsics at its size.
+                    let img_size = glam::uvec2(image.width(), image.height());
+                    let map = UndistortMap::new(
+                        image.width(),
+                        image.height(),
+                        camera.focal(img_size).as_dvec2(),
+                        camera.center(img_size).as_dvec2(),
+                        &distortion,
+                    );
+                    (
+                        map.undistort_image(&image),
+                        depth.map(|d| map.undistort_nearest(&d)),
+                        map.undistort_mask(mask.as_ref()),
+                    )
+                };
 
                 let view = SceneView {
                     name: frame.file_path.to_owned(),
-                    camera: Camera::new(translation, rotation, fovx, fovy, cuv),
+                    camera,
                     image: Arc::new(image),
+                    depth: depth.map(Arc::new),
+                    mask: mask.map(Arc::new),
                 };
                 anyhow::Result::<SceneView>::Ok(view)
             }
@@ -171,8 +282,8 @@ fn read_transforms_file(
     Ok(iter.collect())
 }
 
-pub fn read_dataset<B: Backend>(
-    mut archive: DatasetZip,
+pub fn read_dataset<B: Backend, F: DatasetFs>(
+    mut archive: F,
     load_args: &LoadDatasetArgs,
     device: &B::Device,
 ) -> Result<(DataStream<SplatMessage<B>>, DataStream<Dataset>)> {
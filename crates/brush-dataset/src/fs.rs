@@ -0,0 +1,63 @@
+// Datasets can come from different places (a zip archive, a folder on disk). This
+// trait abstracts over them, so the format loaders can treat them as a somewhat regular
+// filesystem.
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+pub(crate) fn normalized_path(path: &Path) -> PathBuf {
+    Path::new(path)
+        .components()
+        .skip_while(|c| matches!(c, std::path::Component::CurDir))
+        .collect::<PathBuf>()
+}
+
+pub trait DatasetFs: Clone + Send + 'static {
+    /// All file paths in the dataset, relative to the root of the dataset.
+    fn file_names(&self) -> impl Iterator<Item = &str> + '_;
+
+    /// Open the file at a path relative to the root of the dataset.
+    fn file_at_path(&mut self, path: &Path) -> anyhow::Result<Box<dyn Read + '_>>;
+
+    fn read_bytes_at_path(&mut self, path: &Path) -> anyhow::Result<Vec<u8>> {
+        let mut buffer = vec![];
+        self.file_at_path(path)?.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn find_with_extension(&self, extension: &str, contains: &str) -> anyhow::Result<PathBuf> {
+        let names: Vec<_> = self
+            .file_names()
+            .filter(|name| name.ends_with(extension))
+            .collect();
+
+        if names.len() == 1 {
+            return Ok(Path::new(names[0]).to_owned());
+        }
+
+        let names: Vec<_> = names
+            .iter()
+            .filter(|name| name.contains(contains))
+            .collect();
+
+        if names.len() == 1 {
+            return Ok(Path::new(names[0]).to_owned());
+        }
+
+        anyhow::bail!("Failed to find file ending in {extension} maybe containing {contains}.");
+    }
+
+    fn find_base_path(&self, search_path: &str) -> Option<PathBuf> {
+        for file in self.file_names() {
+            let path = normalized_path(Path::new(file));
+            if path.ends_with(search_path) {
+                return path
+                    .ancestors()
+                    .nth(Path::new(search_path).components().count())
+                    .map(|x| x.to_owned());
+            }
+        }
+        None
+    }
+}
@@ -1,7 +1,14 @@
+pub mod camera_export;
+mod depth;
+pub mod directory;
 mod formats;
+pub mod fs;
+mod mask;
 pub mod scene_loader;
+pub mod run_config;
 pub mod splat_export;
 pub mod splat_import;
+mod undistort;
 pub mod zip;
 
 pub use formats::load_dataset;
@@ -17,7 +24,8 @@ use std::pin::Pin;
 use tokio_stream::Stream;
 use tokio_with_wasm::alias as tokio;
 
-#[derive(Clone, Default)]
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct LoadDatasetArgs {
     pub max_frames: Option<usize>,
     pub max_resolution: Option<u32>,
@@ -26,7 +34,8 @@ pub struct LoadDatasetArgs {
     pub subsample_points: Option<u32>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct LoadInitArgs {
     pub sh_degree: u32,
 }
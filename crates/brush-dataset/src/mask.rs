@@ -0,0 +1,153 @@
+use std::{
+    collections::HashSet,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use image::GrayImage;
+
+use crate::fs::{normalized_path, DatasetFs};
+
+fn is_in_folder(path: &Path, folder: &str) -> bool {
+    path.components()
+        .any(|c| c == Component::Normal(folder.as_ref()))
+}
+
+/// The files in the `masks` folders of a dataset, collected once so finding the mask of each
+/// image doesn't have to go through all files of the dataset.
+#[derive(Clone)]
+pub(crate) struct MaskIndex {
+    files: Arc<HashSet<PathBuf>>,
+}
+
+impl MaskIndex {
+    pub(crate) fn new(archive: &impl DatasetFs) -> Self {
+        let files = archive
+            .file_names()
+            .map(|name| normalized_path(Path::new(name)))
+            .filter(|path| is_in_folder(path, "masks"))
+            .collect();
+        Self {
+            files: Arc::new(files),
+        }
+    }
+
+    /// Find the mask for an image in a `masks` folder next to the `images` folder.
+    ///
+    /// The mask can have the same name as the image, the image name with .png added, or the
+    /// image name with its extension replaced by .png.
+    pub(crate) fn find_mirrored_mask(&self, img_path: &Path) -> Option<PathBuf> {
+        let img_path = normalized_path(img_path);
+        if !is_in_folder(&img_path, "images") {
+            return None;
+        }
+
+        let mask_path: PathBuf = img_path
+            .components()
+            .map(|c| match c {
+                Component::Normal(name) if name == "images" => Component::Normal("masks".as_ref()),
+                c => c,
+            })
+            .collect();
+
+        let mut with_png = mask_path.clone().into_os_string();
+        with_png.push(".png");
+
+        let candidates = [
+            mask_path.clone(),
+            PathBuf::from(with_png),
+            mask_path.with_extension("png"),
+        ];
+
+        candidates
+            .into_iter()
+            .find(|candidate| self.files.contains(candidate))
+    }
+}
+
+/// Decode a mask, and resize it to the resolution of its image.
+pub(crate) fn load_mask(data: &[u8], width: u32, height: u32) -> Result<GrayImage> {
+    let mask = image::load_from_memory(data)?.into_luma8();
+    if mask.width() == width && mask.height() == height {
+        return Ok(mask);
+    }
+    Ok(image::imageops::resize(
+        &mask,
+        width,
+        height,
+        image::imageops::FilterType::Nearest,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_mask, MaskIndex};
+    use crate::fs::DatasetFs;
+    use std::{
+        io::Read,
+        path::{Path, PathBuf},
+    };
+
+    #[derive(Clone)]
+    struct FileList(Vec<&'static str>);
+
+    impl DatasetFs for FileList {
+        fn file_names(&self) -> impl Iterator<Item = &str> + '_ {
+            self.0.iter().copied()
+        }
+
+        fn file_at_path(&mut self, path: &Path) -> anyhow::Result<Box<dyn Read + '_>> {
+            anyhow::bail!("No data for {}", path.display())
+        }
+    }
+
+    #[test]
+    fn finds_mirrored_masks() {
+        let masks = MaskIndex::new(&FileList(vec![
+            "scene/images/a.jpg",
+            "scene/images/b.jpg",
+            "scene/images/c.jpg",
+            "scene/masks/a.jpg",
+            "scene/masks/b.jpg.png",
+            "./scene/masks/c.png",
+        ]));
+        let find = |path: &str| masks.find_mirrored_mask(Path::new(path));
+
+        assert_eq!(
+            find("scene/images/a.jpg"),
+            Some(PathBuf::from("scene/masks/a.jpg"))
+        );
+        assert_eq!(
+            find("scene/images/b.jpg"),
+            Some(PathBuf::from("scene/masks/b.jpg.png"))
+        );
+        assert_eq!(
+            find("./scene/images/c.jpg"),
+            Some(PathBuf::from("scene/masks/c.png"))
+        );
+        assert_eq!(find("scene/images/d.jpg"), None);
+        // Only images in an images folder have mirrored masks.
+        assert_eq!(find("scene/a.jpg"), None);
+    }
+
+    #[test]
+    fn resizes_masks_to_the_image() {
+        let mask =
+            image::GrayImage::from_fn(4, 2, |x, _| image::Luma([if x < 2 { 0 } else { 255 }]));
+        let mut png = vec![];
+        mask.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+
+        assert_eq!(load_mask(&png, 4, 2).unwrap(), mask);
+
+        let resized = load_mask(&png, 8, 4).unwrap();
+        assert_eq!(resized.dimensions(), (8, 4));
+        // Nearest neighbour sampling keeps masks binary.
+        assert!(resized.pixels().all(|p| p[0] == 0 || p[0] == 255));
+        assert_eq!(resized.get_pixel(0, 0)[0], 0);
+        assert_eq!(resized.get_pixel(7, 3)[0], 255);
+
+        assert!(load_mask(&[1, 2, 3], 4, 2).is_err());
+    }
+}
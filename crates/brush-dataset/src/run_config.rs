@@ -0,0 +1,109 @@
+// A run file describes a whole training run: the training config, how the dataset is loaded,
+// and the number of steps. Saving one next to the exported splats means a result can be
+// trained again without guessing which settings were used. Training on the GPU isn't
+// deterministic, so the splats won't be identical.
+use std::path::{Path, PathBuf};
+
+use brush_train::train::TrainConfig;
+use burn::config::Config;
+
+use crate::{LoadDatasetArgs, LoadInitArgs};
+
+#[derive(Config)]
+pub struct RunConfig {
+    #[config(default = "TrainConfig::default()")]
+    pub train: TrainConfig,
+    #[config(default = "LoadDatasetArgs::default()")]
+    pub load_args: LoadDatasetArgs,
+    #[config(default = "LoadInitArgs::default()")]
+    pub init_args: LoadInitArgs,
+    /// Number of steps to train for. When not set, the CLI uses its `--total-steps`, and the
+    /// viewer keeps training until it is closed.
+    pub total_steps: Option<u32>,
+    /// Number of views to train on in each step.
+    #[config(default = 1)]
+    pub batch_size: usize,
+}
+
+impl RunConfig {
+    /// Parse a run file, which can be either TOML or JSON.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        if text.trim_start().starts_with('{') {
+            Ok(serde_json::from_str(text)?)
+        } else {
+            Ok(toml::from_str(text)?)
+        }
+    }
+
+    pub fn to_toml(&self) -> anyhow::Result<String> {
+        Ok(toml::to_string_pretty(self)?)
+    }
+
+    /// Seed for the initial splats, the order of the training views, and the training itself.
+    pub fn seed(&self) -> u64 {
+        self.train.seed()
+    }
+}
+
+/// Where to save the run file for an export, eg. `export_run.toml` for `export.ply`.
+pub fn run_file_path(export: &Path) -> PathBuf {
+    let stem = export
+        .file_stem()
+        .map(|s| s.to_string_lossy())
+        .unwrap_or("export".into());
+    export.with_file_name(format!("{stem}_run.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run() -> RunConfig {
+        RunConfig::new()
+            .with_train(
+                TrainConfig::new()
+                    .with_seed(7)
+                    .with_refine_every(150)
+                    .with_max_splats(Some(100_000)),
+            )
+            .with_load_args(LoadDatasetArgs {
+                max_frames: Some(20),
+                max_resolution: Some(800),
+                eval_split_every: Some(8),
+                subsample_frames: None,
+                subsample_points: Some(2),
+            })
+            .with_init_args(LoadInitArgs { sh_degree: 3 })
+            .with_total_steps(Some(5000))
+            .with_batch_size(2)
+    }
+
+    fn assert_same(a: &RunConfig, b: &RunConfig) {
+        assert_eq!(
+            serde_json::to_value(a).unwrap(),
+            serde_json::to_value(b).unwrap()
+        );
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let run = run();
+        let parsed = RunConfig::parse(&run.to_toml().unwrap()).unwrap();
+        assert_same(&parsed, &run);
+        assert_eq!(parsed.seed(), 7);
+        assert_eq!(parsed.total_steps, Some(5000));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let run = run();
+        let parsed = RunConfig::parse(&serde_json::to_string_pretty(&run).unwrap()).unwrap();
+        assert_same(&parsed, &run);
+    }
+
+    #[test]
+    fn total_steps_is_optional() {
+        let parsed = RunConfig::parse(&RunConfig::new().to_toml().unwrap()).unwrap();
+        assert_eq!(parsed.total_steps, None);
+    }
+}
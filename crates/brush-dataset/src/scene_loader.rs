@@ -1,10 +1,9 @@
 use ::tokio::sync::mpsc;
 use ::tokio::sync::mpsc::Receiver;
 use brush_render::Backend;
-use brush_train::image::image_to_tensor;
+use brush_train::image::{depth_to_tensor, image_to_tensor, mask_to_tensor};
 use brush_train::scene::Scene;
 use brush_train::train::SceneBatch;
-use burn::tensor::Tensor;
 use rand::{seq::SliceRandom, SeedableRng};
 use tokio_with_wasm::alias as tokio;
 
@@ -13,7 +12,17 @@ pub struct SceneLoader<B: Backend> {
 }
 
 impl<B: Backend> SceneLoader<B> {
-    pub fn new(scene: &Scene, batch_size: usize, seed: u64, device: &B::Device) -> Self {
+    /// Create a loader which produces shuffled batches of views.
+    ///
+    /// The first `start_step` batches are skipped. This is useful to resume training, as
+    /// batches then continue in the same order as an uninterrupted run.
+    pub fn new(
+        scene: &Scene,
+        batch_size: usize,
+        seed: u64,
+        start_step: u32,
+        device: &B::Device,
+    ) -> Self {
         let scene = scene.clone();
         // The bounded size == number of batches to prefetch.
         let (tx, rx) = mpsc::channel(5);
@@ -24,25 +33,51 @@ impl<B: Backend> SceneLoader<B> {
 
         let fut = async move {
             let mut shuf_indices = vec![];
+            let mut step = 0;
 
             loop {
-                let (selected_tensors, gt_views) = (0..batch_size)
+                let indices: Vec<_> = (0..batch_size)
                     .map(|_| {
-                        let index = shuf_indices.pop().unwrap_or_else(|| {
+                        shuf_indices.pop().unwrap_or_else(|| {
                             shuf_indices = (0..scene.views.len()).collect();
                             shuf_indices.shuffle(&mut rng);
                             shuf_indices.pop().unwrap()
-                        });
-                        let view = scene.views[index].clone();
-                        (image_to_tensor(&view.image, &device), view)
+                        })
                     })
-                    .unzip();
+                    .collect();
 
-                let batch_tensor = Tensor::stack(selected_tensors, 0);
+                step += 1;
+                if step <= start_step {
+                    continue;
+                }
+
+                let gt_views: Vec<_> = indices
+                    .iter()
+                    .map(|&index| scene.views[index].clone())
+                    .collect();
+                let gt_images = gt_views
+                    .iter()
+                    .map(|view| image_to_tensor(&view.image, &device))
+                    .collect();
+                let gt_depths = gt_views
+                    .iter()
+                    .map(|view| {
+                        view.depth
+                            .as_ref()
+                            .map(|depth| depth_to_tensor(depth, &device))
+                    })
+                    .collect();
+                let gt_masks = gt_views
+                    .iter()
+                    .map(|view| view.mask.as_ref().map(|mask| mask_to_tensor(mask, &device)))
+                    .collect();
 
                 let scene_batch = SceneBatch {
-                    gt_images: batch_tensor,
+                    gt_images,
+                    gt_depths,
+                    gt_masks,
                     gt_views,
+                    gt_view_indices: indices,
                     scene_extent,
                 };
 
@@ -63,3 +98,145 @@ impl<B: Backend> SceneLoader<B> {
             .expect("Somehow lost data loading channel!")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use brush_render::{camera::Camera, gaussian_splats::Splats};
+    use brush_train::{
+        scene::{Scene, SceneView},
+        train::{RefineMode, SplatTrainer, TrainConfig},
+    };
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+    use glam::{vec2, vec3, Quat, Vec3};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use safetensors::SafeTensors;
+
+    use super::SceneLoader;
+
+    type Back = Autodiff<NdArray>;
+
+    const SEED: u64 = 42;
+
+    fn scene() -> Scene {
+        let views = [
+            vec3(0.0, 0.0, -3.0),
+            vec3(0.5, 0.2, -3.0),
+            vec3(-0.4, 0.3, -2.5),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, position)| {
+            let image = image::RgbImage::from_fn(16, 16, |x, y| {
+                image::Rgb([(x * 16) as u8, (y * 16) as u8, (i * 100) as u8])
+            });
+            SceneView {
+                name: format!("{i}.png"),
+                camera: Camera::new(position, Quat::IDENTITY, 1.0, 1.0, vec2(0.5, 0.5)),
+                image: Arc::new(image.into()),
+                depth: None,
+                mask: None,
+            }
+        })
+        .collect();
+        Scene::new(views)
+    }
+
+    async fn train(
+        trainer: &mut SplatTrainer<Back>,
+        mut splats: Splats<Back>,
+        scene: &Scene,
+        loader: &mut SceneLoader<Back>,
+        until: u32,
+    ) -> Splats<Back> {
+        while trainer.iter < until {
+            let batch = loader.next_batch().await;
+            splats = trainer.update_filter_3d(splats, scene);
+            (splats, _) = trainer.step(batch, splats).await.expect("Failed to step");
+        }
+        splats
+    }
+
+    fn assert_checkpoints_equal(a: &[u8], b: &[u8]) {
+        let (_, header_a) = SafeTensors::read_metadata(a).unwrap();
+        let (_, header_b) = SafeTensors::read_metadata(b).unwrap();
+        assert_eq!(header_a.metadata(), header_b.metadata());
+
+        let a = SafeTensors::deserialize(a).unwrap();
+        let b = SafeTensors::deserialize(b).unwrap();
+        let mut names = a.names();
+        names.sort();
+        let mut names_b = b.names();
+        names_b.sort();
+        assert_eq!(names, names_b);
+
+        for name in names {
+            let (tensor_a, tensor_b) = (a.tensor(name).unwrap(), b.tensor(name).unwrap());
+            assert_eq!(tensor_a.shape(), tensor_b.shape(), "{name}");
+            assert!(tensor_a.data() == tensor_b.data(), "{name} differs");
+        }
+    }
+
+    #[tokio::test]
+    async fn resume_matches_uninterrupted_training() {
+        let device = NdArrayDevice::Cpu;
+        let scene = scene();
+        let mut rng = StdRng::seed_from_u64(0);
+        let means: Vec<Vec3> = (0..32)
+            .map(|_| vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0)
+            .collect();
+        let log_scales = vec![Vec3::splat(-2.5); means.len()];
+
+        // Refine in both halves of training, so refinement, and the noise of MCMC, have to
+        // continue the same after resuming too.
+        let base = TrainConfig::new()
+            .with_warmup_steps(2)
+            .with_refine_every(3)
+            .with_densify_grad_thresh(0.0);
+
+        for config in [
+            base.clone(),
+            base.clone().with_mip_filter_3d(true),
+            base.clone().with_refine_mode(RefineMode::Mcmc),
+        ] {
+            let (resume_step, total_steps) = (5, 10);
+            let splats = Splats::<Back>::from_raw(
+                means.clone(),
+                None,
+                Some(log_scales.clone()),
+                None,
+                None,
+                &device,
+            );
+
+            let mut trainer = SplatTrainer::new(splats.num_splats(), &config, &device);
+            let mut loader = SceneLoader::new(&scene, 1, SEED, 0, &device);
+            let splats = train(&mut trainer, splats, &scene, &mut loader, resume_step).await;
+            let checkpoint = trainer.save_checkpoint(&splats).await.unwrap();
+            let splats = train(&mut trainer, splats, &scene, &mut loader, total_steps).await;
+
+            let (mut resumed, resumed_splats) =
+                SplatTrainer::<Back>::load_checkpoint(&checkpoint, &config, &device).unwrap();
+            assert_eq!(resumed.iter, resume_step);
+            let mut loader = SceneLoader::new(&scene, 1, SEED, resumed.iter, &device);
+            let resumed_splats = train(
+                &mut resumed,
+                resumed_splats,
+                &scene,
+                &mut loader,
+                total_steps,
+            )
+            .await;
+
+            assert_checkpoints_equal(
+                &trainer.save_checkpoint(&splats).await.unwrap(),
+                &resumed.save_checkpoint(&resumed_splats).await.unwrap(),
+            );
+
+            // Resuming with a different config wouldn't continue the same run.
+            let changed = config.clone().with_refine_every(4);
+            assert!(SplatTrainer::<Back>::load_checkpoint(&checkpoint, &changed, &device).is_err());
+        }
+    }
+}
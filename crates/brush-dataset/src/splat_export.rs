@@ -11,9 +11,11 @@ use crate::splat_import::GaussianData;
 
 async fn read_splat_data<B: Backend>(splats: Splats<B>) -> Result<Vec<GaussianData>, DataError> {
     let means = splats.means.val().into_data_async().await.to_vec()?;
-    let log_scales = splats.log_scales.val().into_data_async().await.to_vec()?;
+    // Bake in the 3D filter, so the exported splats look the same as when training.
+    let (log_scales, raw_opacity) = splats.filtered_params();
+    let log_scales = log_scales.into_data_async().await.to_vec()?;
     let rotations = splats.rotation.val().into_data_async().await.to_vec()?;
-    let opacities = splats.raw_opacity.val().into_data_async().await.to_vec()?;
+    let opacities = raw_opacity.into_data_async().await.to_vec()?;
 
     let sh_coeffs = splats
         .sh_coeffs
@@ -98,6 +100,9 @@ pub async fn splat_to_ply<B: Backend>(splats: Splats<B>) -> anyhow::Result<Vec<u
     ply.header.encoding = ply::Encoding::BinaryLittleEndian;
     ply.header.comments.push("Exported from Brush".to_string());
     ply.header.comments.push("Vertical axis: y".to_string());
+    if splats.mip_filter_2d {
+        ply.header.comments.push("Mip filter: 2d".to_string());
+    }
     ply.payload.insert("vertex".to_string(), data);
 
     let mut buf = vec![];
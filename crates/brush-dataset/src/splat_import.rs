@@ -201,6 +201,12 @@ pub fn load_splat_from_ply<T: AsyncRead + Unpin + 'static, B: Backend>(
             .last()
             .unwrap_or(Vec3::Y);
 
+        // Splats trained with the 2D Mip filter need it when rendering too.
+        let mip_filter_2d = header
+            .comments
+            .iter()
+            .any(|c| c.to_lowercase() == "mip filter: 2d");
+
         let frame_count = header
             .elements
             .iter()
@@ -261,7 +267,7 @@ pub fn load_splat_from_ply<T: AsyncRead + Unpin + 'static, B: Backend>(
 
                     // Occasionally send some updated splats.
                     if i % update_every == update_every - 1 {
-                        let splats = Splats::from_raw(
+                        let mut splats = Splats::from_raw(
                             means.clone(),
                             rotations.clone(),
                             log_scales.clone(),
@@ -269,6 +275,7 @@ pub fn load_splat_from_ply<T: AsyncRead + Unpin + 'static, B: Backend>(
                             opacity.clone(),
                             &device,
                         );
+                        splats.mip_filter_2d = mip_filter_2d;
 
                         emitter
                             .emit(SplatMessage {
@@ -311,8 +318,9 @@ pub fn load_splat_from_ply<T: AsyncRead + Unpin + 'static, B: Backend>(
                     }
                 }
 
-                let splats =
+                let mut splats =
                     Splats::from_raw(means, rotations, log_scales, sh_coeffs, opacity, &device);
+                splats.mip_filter_2d = mip_filter_2d;
                 final_splat = Some(splats.clone());
                 emitter
                     .emit(SplatMessage {
@@ -406,6 +414,7 @@ pub fn load_splat_from_ply<T: AsyncRead + Unpin + 'static, B: Backend>(
                     splats.raw_opacity.val(),
                 );
                 new_splat.norm_rotations();
+                new_splat.mip_filter_2d = mip_filter_2d;
 
                 // Emit newly animated splat.
                 emitter
@@ -0,0 +1,105 @@
+// The splat renderer only models ideal cameras. Images shot with a distorted lens are remapped to
+// an ideal camera with the same intrinsics when loading, so training doesn't need to know about
+// distortion at all. Fisheye lenses are remapped to an ideal equidistant fisheye, see
+// `Projection::Fisheye`, as a pinhole camera would have to crop away the edges of the image.
+use colmap_reader::Distortion;
+use glam::{dvec2, DVec2, Vec2};
+use image::{imageops::interpolate_bilinear, DynamicImage, GrayImage, ImageBuffer, Pixel};
+
+pub(crate) struct UndistortMap {
+    width: u32,
+    height: u32,
+    // For each undistorted pixel, where to sample the distorted image, or None if
+    // that falls outside of the image.
+    sources: Vec<Option<Vec2>>,
+}
+
+impl UndistortMap {
+    /// Create a map for an image of the given size. The focal length and principal point
+    /// are in pixels for this size.
+    pub(crate) fn new(
+        width: u32,
+        height: u32,
+        focal: DVec2,
+        center: DVec2,
+        distortion: &Distortion,
+    ) -> Self {
+        let max = dvec2(width as f64 - 1.0, height as f64 - 1.0);
+        let sources = (0..height)
+            .flat_map(|y| (0..width).map(move |x| dvec2(x as f64, y as f64)))
+            .map(|pixel| {
+                let undistorted = (pixel + 0.5 - center) / focal;
+                let distorted = if distortion.is_fisheye() {
+                    distortion.distort_equidistant(undistorted)
+                } else {
+                    distortion.distort(undistorted)
+                };
+                // Back to pixels, with pixel centers at integer coordinates.
+                let source = distorted * focal + center - 0.5;
+                (source.cmpge(DVec2::ZERO).all() && source.cmple(max).all())
+                    .then(|| source.as_vec2())
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            sources,
+        }
+    }
+
+    fn remap<P: Pixel>(
+        &self,
+        image: &ImageBuffer<P, Vec<P::Subpixel>>,
+        sample: impl Fn(&ImageBuffer<P, Vec<P::Subpixel>>, Vec2) -> Option<P>,
+    ) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        assert_eq!(
+            image.dimensions(),
+            (self.width, self.height),
+            "Image must have the size of the undistortion map"
+        );
+        let mut out = ImageBuffer::new(self.width, self.height);
+        for (pixel, source) in out.pixels_mut().zip(&self.sources) {
+            if let Some(value) = source.and_then(|source| sample(image, source)) {
+                *pixel = value;
+            }
+        }
+        out
+    }
+
+    pub(crate) fn undistort_image(&self, image: &DynamicImage) -> DynamicImage {
+        if image.color().has_alpha() {
+            DynamicImage::ImageRgba8(self.remap(&image.to_rgba8(), |img, p| {
+                interpolate_bilinear(img, p.x, p.y)
+            }))
+        } else {
+            DynamicImage::ImageRgb8(self.remap(&image.to_rgb8(), |img, p| {
+                interpolate_bilinear(img, p.x, p.y)
+            }))
+        }
+    }
+
+    /// Undistort without interpolating, for data like masks and depths where blending
+    /// neighbouring values doesn't make sense. Pixels outside of the image are zero.
+    pub(crate) fn undistort_nearest<P: Pixel>(
+        &self,
+        image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    ) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        self.remap(image, |img, p| {
+            Some(*img.get_pixel(p.x.round() as u32, p.y.round() as u32))
+        })
+    }
+
+    /// Undistort a mask, or create one if the undistorted image has pixels outside
+    /// of the original image, so those don't affect training.
+    pub(crate) fn undistort_mask(&self, mask: Option<&GrayImage>) -> Option<GrayImage> {
+        if let Some(mask) = mask {
+            Some(self.undistort_nearest(mask))
+        } else if self.sources.iter().any(|s| s.is_none()) {
+            let valid = self.sources.iter().map(|s| s.map_or(0, |_| 255)).collect();
+            GrayImage::from_raw(self.width, self.height, valid)
+        } else {
+            None
+        }
+    }
+}
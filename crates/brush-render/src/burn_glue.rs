@@ -19,8 +19,8 @@ use burn_wgpu::WgpuRuntime;
 use crate::{
     camera::Camera,
     render::{
-        calc_tile_bounds, max_intersections, render_backward, render_forward, sh_coeffs_for_degree,
-        sh_degree_from_coeffs,
+        calc_tile_bounds, max_intersections, render_backward, render_channels, render_forward,
+        sh_coeffs_for_degree, sh_degree_from_coeffs,
     },
     shaders, AutodiffBackend, Backend, GaussianBackwardState, InnerWgpu, RenderAux, SplatGrads,
 };
@@ -32,11 +32,16 @@ impl Backend for InnerWgpu {
         img_size: glam::UVec2,
         means: Self::FloatTensorPrimitive,
         _xy_dummy: Self::FloatTensorPrimitive,
+        _xy_abs_dummy: Self::FloatTensorPrimitive,
+        _camera_dummy: Self::FloatTensorPrimitive,
         log_scales: Self::FloatTensorPrimitive,
         quats: Self::FloatTensorPrimitive,
         sh_coeffs: Self::FloatTensorPrimitive,
         raw_opacity: Self::FloatTensorPrimitive,
+        background: glam::Vec3,
+        mip_filter: bool,
         render_u32_buffer: bool,
+        render_depth: bool,
     ) -> (Self::FloatTensorPrimitive, RenderAux<Self>) {
         render_forward(
             camera,
@@ -46,7 +51,10 @@ impl Backend for InnerWgpu {
             quats,
             sh_coeffs,
             raw_opacity,
+            background,
+            mip_filter,
             render_u32_buffer,
+            render_depth,
         )
     }
 
@@ -68,7 +76,11 @@ impl Backend for InnerWgpu {
             state.aux.global_from_compact_gid,
             state.aux.tile_bins,
             state.aux.final_index,
+            state.aux.median_index,
             state.sh_degree,
+            state.mip_filter,
+            state.abs_grad,
+            state.camera_grad,
         )
     }
 }
@@ -76,7 +88,7 @@ impl Backend for InnerWgpu {
 #[derive(Debug)]
 struct RenderBackwards;
 
-const NUM_ARGS: usize = 6;
+const NUM_ARGS: usize = 8;
 
 // Implement gradient registration when rendering backwards.
 impl<B: Backend> Backward<B, NUM_ARGS> for RenderBackwards {
@@ -90,15 +102,20 @@ impl<B: Backend> Backward<B, NUM_ARGS> for RenderBackwards {
     ) {
         let _span = tracing::trace_span!("render_gaussians backwards").entered();
 
-        let state = ops.state;
-
         let v_output = grads.consume::<B>(&ops.node);
 
         // Register gradients for parent nodes (This code is already skipped entirely
         // if no parent nodes require gradients).
-        let [mean_parent, xys_parent, log_scales_parent, quats_parent, coeffs_parent, raw_opacity_parent] =
+        let [mean_parent, xys_parent, xys_abs_parent, camera_parent, log_scales_parent, quats_parent, coeffs_parent, raw_opacity_parent] =
             ops.parents;
 
+        // Only do the extra work for the absolute and camera gradients when they're used.
+        let state = GaussianBackwardState {
+            abs_grad: xys_abs_parent.is_some(),
+            camera_grad: camera_parent.is_some(),
+            ..ops.state
+        };
+
         let v_tens = B::render_splats_bwd(state, v_output);
 
         if let Some(node) = mean_parent {
@@ -110,6 +127,14 @@ impl<B: Backend> Backward<B, NUM_ARGS> for RenderBackwards {
             grads.register::<B>(node.id, v_tens.v_xy);
         }
 
+        if let Some(node) = xys_abs_parent {
+            grads.register::<B>(node.id, v_tens.v_xy_abs);
+        }
+
+        if let Some(node) = camera_parent {
+            grads.register::<B>(node.id, v_tens.v_camera);
+        }
+
         if let Some(node) = log_scales_parent {
             grads.register::<B>(node.id, v_tens.v_scales);
         }
@@ -135,11 +160,16 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
         img_size: glam::UVec2,
         means: Self::FloatTensorPrimitive,
         xy_dummy: Self::FloatTensorPrimitive,
+        xy_abs_dummy: Self::FloatTensorPrimitive,
+        camera_dummy: Self::FloatTensorPrimitive,
         log_scales: Self::FloatTensorPrimitive,
         quats: Self::FloatTensorPrimitive,
         sh_coeffs: Self::FloatTensorPrimitive,
         raw_opacity: Self::FloatTensorPrimitive,
+        background: glam::Vec3,
+        mip_filter: bool,
         render_u32_buffer: bool,
+        render_depth: bool,
     ) -> (Self::FloatTensorPrimitive, RenderAux<Self>) {
         // Get backend tensors & dequantize if needed. Could try and support quantized inputs
         // in the future.
@@ -149,6 +179,8 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
             .prepare::<C>([
                 means.node.clone(),
                 xy_dummy.node.clone(),
+                xy_abs_dummy.node.clone(),
+                camera_dummy.node.clone(),
                 log_scales.node.clone(),
                 quats.node.clone(),
                 sh_coeffs.node.clone(),
@@ -163,11 +195,16 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
             img_size,
             means.clone().into_primitive(),
             xy_dummy.into_primitive(),
+            xy_abs_dummy.into_primitive(),
+            camera_dummy.into_primitive(),
             log_scales.clone().into_primitive(),
             quats.clone().into_primitive(),
             sh_coeffs.clone().into_primitive(),
             raw_opacity.clone().into_primitive(),
+            background,
+            mip_filter,
             render_u32_buffer,
+            render_depth,
         );
 
         // Not sure why going into the autodiff float tensor type is so verbose.
@@ -182,6 +219,7 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
             num_intersections: aux.num_intersections,
             num_visible: aux.num_visible,
             final_index: aux.final_index,
+            median_index: aux.median_index,
             cum_tiles_hit: aux.cum_tiles_hit,
             tile_bins: aux.tile_bins,
             compact_gid_from_isect: aux.compact_gid_from_isect,
@@ -201,6 +239,9 @@ impl<B: Backend, C: CheckpointStrategy> Backend for Autodiff<B, C> {
                         Tensor::<Self, 3>::from_primitive(TensorPrimitive::Float(sh_coeffs)).dims()
                             [1] as u32,
                     ),
+                    mip_filter,
+                    abs_grad: false,
+                    camera_grad: false,
                     aux: auxc,
                     out_img: out_img.clone(),
                 };
@@ -224,16 +265,24 @@ impl Backend for Fusion<InnerWgpu> {
         img_size: glam::UVec2,
         means: Self::FloatTensorPrimitive,
         _xy_grad_dummy: Self::FloatTensorPrimitive,
+        _xy_abs_grad_dummy: Self::FloatTensorPrimitive,
+        _camera_grad_dummy: Self::FloatTensorPrimitive,
         log_scales: Self::FloatTensorPrimitive,
         quats: Self::FloatTensorPrimitive,
         sh_coeffs: Self::FloatTensorPrimitive,
         raw_opacity: Self::FloatTensorPrimitive,
+        background: glam::Vec3,
+        mip_filter: bool,
         render_u32_buffer: bool,
+        render_depth: bool,
     ) -> (Self::FloatTensorPrimitive, RenderAux<Self>) {
         struct CustomOp {
             cam: Camera,
             img_size: glam::UVec2,
+            background: glam::Vec3,
+            mip_filter: bool,
             render_u32_buffer: bool,
+            render_depth: bool,
             desc: CustomOpDescription,
         }
 
@@ -241,7 +290,7 @@ impl Backend for Fusion<InnerWgpu> {
             fn execute(self: Box<Self>, h: &mut HandleContainer<JitFusionHandle<WgpuRuntime>>) {
                 let (
                     [means, log_scales, quats, sh_coeffs, raw_opacity],
-                    [projected_splats, uniforms_buffer, num_intersections, num_visible, final_index, cum_tiles_hit, tile_bins, compact_gid_from_isect, global_from_compact_gid, out_img],
+                    [projected_splats, uniforms_buffer, num_intersections, num_visible, final_index, median_index, cum_tiles_hit, tile_bins, compact_gid_from_isect, global_from_compact_gid, out_img],
                 ) = self.desc.consume();
 
                 let (img, aux) = render_forward(
@@ -252,7 +301,10 @@ impl Backend for Fusion<InnerWgpu> {
                     h.get_float_tensor::<InnerWgpu>(&quats),
                     h.get_float_tensor::<InnerWgpu>(&sh_coeffs),
                     h.get_float_tensor::<InnerWgpu>(&raw_opacity),
+                    self.background,
+                    self.mip_filter,
                     self.render_u32_buffer,
+                    self.render_depth,
                 );
 
                 // Register output.
@@ -262,6 +314,7 @@ impl Backend for Fusion<InnerWgpu> {
                 h.register_int_tensor::<InnerWgpu>(&num_intersections.id, aux.num_intersections);
                 h.register_int_tensor::<InnerWgpu>(&num_visible.id, aux.num_visible);
                 h.register_int_tensor::<InnerWgpu>(&final_index.id, aux.final_index);
+                h.register_int_tensor::<InnerWgpu>(&median_index.id, aux.median_index);
                 h.register_int_tensor::<InnerWgpu>(&cum_tiles_hit.id, aux.cum_tiles_hit);
                 h.register_int_tensor::<InnerWgpu>(&tile_bins.id, aux.tile_bins);
                 h.register_int_tensor::<InnerWgpu>(
@@ -286,8 +339,12 @@ impl Backend for Fusion<InnerWgpu> {
         let max_intersects = max_intersections(img_size, num_points as u32);
 
         // If render_u32_buffer is true, we render a packed buffer of u32 values, otherwise
-        // render RGBA f32 values.
-        let channels = if render_u32_buffer { 1 } else { 4 };
+        // render RGBA f32 values, and optionally depth.
+        let channels = if render_u32_buffer {
+            1
+        } else {
+            render_channels(render_depth)
+        };
 
         let out_img = client.tensor_uninitialized(
             vec![img_size.y as usize, img_size.x as usize, channels],
@@ -301,6 +358,8 @@ impl Backend for Fusion<InnerWgpu> {
             num_visible: client.tensor_uninitialized(vec![1], DType::I32),
             final_index: client
                 .tensor_uninitialized(vec![img_size.y as usize, img_size.x as usize], DType::I32),
+            median_index: client
+                .tensor_uninitialized(vec![img_size.y as usize, img_size.x as usize], DType::I32),
             cum_tiles_hit: client.tensor_uninitialized(vec![num_points], DType::I32),
             tile_bins: client.tensor_uninitialized(
                 vec![tile_bounds.y as usize, tile_bounds.x as usize, 2],
@@ -326,6 +385,7 @@ impl Backend for Fusion<InnerWgpu> {
                 aux.num_intersections.to_description_out(),
                 aux.num_visible.to_description_out(),
                 aux.final_index.to_description_out(),
+                aux.median_index.to_description_out(),
                 aux.cum_tiles_hit.to_description_out(),
                 aux.tile_bins.to_description_out(),
                 aux.compact_gid_from_isect.to_description_out(),
@@ -337,7 +397,10 @@ impl Backend for Fusion<InnerWgpu> {
         let op = CustomOp {
             cam: cam.clone(),
             img_size,
+            background,
+            mip_filter,
             render_u32_buffer,
+            render_depth,
             desc: desc.clone(),
         };
 
@@ -353,13 +416,16 @@ impl Backend for Fusion<InnerWgpu> {
         struct CustomOp {
             desc: CustomOpDescription,
             sh_degree: u32,
+            mip_filter: bool,
+            abs_grad: bool,
+            camera_grad: bool,
         }
 
         impl Operation<FusionJitRuntime<WgpuRuntime, u32>> for CustomOp {
             fn execute(self: Box<Self>, h: &mut HandleContainer<JitFusionHandle<WgpuRuntime>>) {
                 let (
-                    [v_output, means, log_scales, quats, raw_opac, out_img, projected_splats, num_visible, uniforms_buffer, compact_gid_from_isect, global_from_compact_gid, tile_bins, final_index],
-                    [v_means, v_quats, v_scales, v_coeffs, v_raw_opac, v_xy],
+                    [v_output, means, log_scales, quats, raw_opac, out_img, projected_splats, num_visible, uniforms_buffer, compact_gid_from_isect, global_from_compact_gid, tile_bins, final_index, median_index],
+                    [v_means, v_quats, v_scales, v_coeffs, v_raw_opac, v_xy, v_xy_abs, v_camera],
                 ) = self.desc.consume();
 
                 let grads = render_backward(
@@ -376,7 +442,11 @@ impl Backend for Fusion<InnerWgpu> {
                     h.get_int_tensor::<InnerWgpu>(&global_from_compact_gid),
                     h.get_int_tensor::<InnerWgpu>(&tile_bins),
                     h.get_int_tensor::<InnerWgpu>(&final_index),
+                    h.get_int_tensor::<InnerWgpu>(&median_index),
                     self.sh_degree,
+                    self.mip_filter,
+                    self.abs_grad,
+                    self.camera_grad,
                 );
 
                 // // Register output.
@@ -386,6 +456,8 @@ impl Backend for Fusion<InnerWgpu> {
                 h.register_float_tensor::<InnerWgpu>(&v_coeffs.id, grads.v_coeffs);
                 h.register_float_tensor::<InnerWgpu>(&v_raw_opac.id, grads.v_raw_opac);
                 h.register_float_tensor::<InnerWgpu>(&v_xy.id, grads.v_xy);
+                h.register_float_tensor::<InnerWgpu>(&v_xy_abs.id, grads.v_xy_abs);
+                h.register_float_tensor::<InnerWgpu>(&v_camera.id, grads.v_camera);
             }
         }
 
@@ -402,6 +474,8 @@ impl Backend for Fusion<InnerWgpu> {
             v_coeffs: client.tensor_uninitialized(vec![num_points, coeffs, 3], DType::F32),
             v_raw_opac: client.tensor_uninitialized(vec![num_points], DType::F32),
             v_xy: client.tensor_uninitialized(vec![num_points, 2], DType::F32),
+            v_xy_abs: client.tensor_uninitialized(vec![num_points, 2], DType::F32),
+            v_camera: client.tensor_uninitialized(vec![8], DType::F32),
         };
 
         let desc = CustomOpDescription::new(
@@ -420,6 +494,7 @@ impl Backend for Fusion<InnerWgpu> {
                 state.aux.global_from_compact_gid.into_description(),
                 state.aux.tile_bins.into_description(),
                 state.aux.final_index.into_description(),
+                state.aux.median_index.into_description(),
             ],
             &[
                 grads.v_means.to_description_out(),
@@ -428,11 +503,16 @@ impl Backend for Fusion<InnerWgpu> {
                 grads.v_coeffs.to_description_out(),
                 grads.v_raw_opac.to_description_out(),
                 grads.v_xy.to_description_out(),
+                grads.v_xy_abs.to_description_out(),
+                grads.v_camera.to_description_out(),
             ],
         );
 
         let op = CustomOp {
             sh_degree: state.sh_degree,
+            mip_filter: state.mip_filter,
+            abs_grad: state.abs_grad,
+            camera_grad: state.camera_grad,
             desc: desc.clone(),
         };
 
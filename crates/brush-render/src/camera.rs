@@ -1,3 +1,34 @@
+/// How camera space positions map to the image.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Regular perspective projection.
+    #[default]
+    Pinhole,
+    /// Equidistant fisheye, where the distance to the image center is proportional to the angle
+    /// with the optical axis. The field of view can be more than 180 degrees.
+    Fisheye,
+    /// Equirectangular panorama, with longitude along x and latitude along y. Use a field of view
+    /// of 2π by π for a full 360 degree panorama.
+    ///
+    /// Splats are binned into tiles without wrapping around the left and right edge, so a splat
+    /// straddling the seam of a full panorama is only drawn on the side its center is on.
+    Equirectangular,
+    /// Orthographic projection along the view direction. The field of view of the camera is
+    /// unused, `extent` is the width and height of the view in world units instead.
+    Orthographic { extent: glam::DVec2 },
+}
+
+impl Projection {
+    pub(crate) fn shader_id(&self) -> u32 {
+        match self {
+            Projection::Pinhole => crate::shaders::helpers::PROJECTION_PINHOLE,
+            Projection::Fisheye => crate::shaders::helpers::PROJECTION_FISHEYE,
+            Projection::Equirectangular => crate::shaders::helpers::PROJECTION_EQUIRECT,
+            Projection::Orthographic { .. } => crate::shaders::helpers::PROJECTION_ORTHO,
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct Camera {
     pub fov_x: f64,
@@ -5,6 +36,7 @@ pub struct Camera {
     pub center_uv: glam::Vec2,
     pub position: glam::Vec3,
     pub rotation: glam::Quat,
+    pub projection: Projection,
 }
 
 impl Camera {
@@ -21,13 +53,29 @@ impl Camera {
             center_uv,
             position,
             rotation,
+            projection: Projection::Pinhole,
         }
     }
 
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    /// The focal length in pixels. For the fisheye and equirectangular projections this is
+    /// the number of pixels per radian, and for the orthographic projection the number of
+    /// pixels per world unit.
     pub fn focal(&self, img_size: glam::UVec2) -> glam::Vec2 {
+        let to_focal: fn(f64, u32) -> f64 = match self.projection {
+            Projection::Pinhole => fov_to_focal,
+            Projection::Fisheye | Projection::Equirectangular => angular_fov_to_focal,
+            Projection::Orthographic { extent } => {
+                return (img_size.as_dvec2() / extent).as_vec2();
+            }
+        };
         glam::vec2(
-            fov_to_focal(self.fov_x, img_size.x) as f32,
-            fov_to_focal(self.fov_y, img_size.y) as f32,
+            to_focal(self.fov_x, img_size.x) as f32,
+            to_focal(self.fov_y, img_size.y) as f32,
         )
     }
 
@@ -45,6 +93,37 @@ impl Camera {
     pub fn world_to_local(&self) -> glam::Mat4 {
         self.local_to_world().inverse()
     }
+
+    /// Move the camera by a rotation (as a scaled axis) and a translation, both in camera space.
+    /// A point at `p` in camera space ends up at `rotation * p + translation`.
+    pub fn perturbed(&self, rotation: glam::Vec3, translation: glam::Vec3) -> Self {
+        let delta = glam::Mat4::from_rotation_translation(
+            glam::Quat::from_scaled_axis(rotation),
+            translation,
+        );
+        let (_, rotation, position) = (delta * self.world_to_local())
+            .inverse()
+            .to_scale_rotation_translation();
+        Camera {
+            position,
+            rotation: rotation.normalize(),
+            ..self.clone()
+        }
+    }
+
+    /// Scale the focal length of the camera, keeping the principal point in place.
+    pub fn with_focal_scale(mut self, scale: f64) -> Self {
+        if let Projection::Orthographic { extent } = &mut self.projection {
+            *extent /= scale;
+        }
+        let scale_fov = |fov: f64| match self.projection {
+            Projection::Pinhole => 2.0 * ((fov * 0.5).tan() / scale).atan(),
+            _ => fov / scale,
+        };
+        self.fov_x = scale_fov(self.fov_x);
+        self.fov_y = scale_fov(self.fov_y);
+        self
+    }
 }
 // Converts field of view to focal length
 pub fn fov_to_focal(fov_rad: f64, pixels: u32) -> f64 {
@@ -55,3 +134,13 @@ pub fn fov_to_focal(fov_rad: f64, pixels: u32) -> f64 {
 pub fn focal_to_fov(focal: f64, pixels: u32) -> f64 {
     2.0 * f64::atan((pixels as f64) / (2.0 * focal))
 }
+
+// Converts field of view to pixels per radian, for projections linear in the angle.
+pub fn angular_fov_to_focal(fov_rad: f64, pixels: u32) -> f64 {
+    (pixels as f64) / fov_rad
+}
+
+// Converts pixels per radian to field of view, for projections linear in the angle.
+pub fn angular_focal_to_fov(focal: f64, pixels: u32) -> f64 {
+    (pixels as f64) / focal
+}
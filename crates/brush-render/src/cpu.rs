@@ -0,0 +1,1525 @@
+// A pure Rust implementation of the splat renderer on burn's ndarray backend.
+//
+// This mirrors the wgsl kernels step by step: project_forward, the depth sort, project_visible,
+// map_gaussian_to_intersects, the tile sort, get_tile_bin_edges and rasterize for the forward pass,
+// and rasterize_backwards, gather_grads and project_backwards for the backward pass. The aux buffers
+// have the same layout as the GPU ones, so anything reading RenderAux works on both.
+//
+// It's slow, but doesn't need a GPU, which makes it useful for CI, as a reference to test the
+// kernels against, and as a fallback on machines without a GPU.
+use burn::{
+    backend::{ndarray::NdArrayDevice, NdArray},
+    tensor::{
+        ops::{FloatTensor, IntTensor},
+        Int, Tensor, TensorData, TensorPrimitive,
+    },
+};
+use glam::{uvec2, vec2, vec3, Mat2, Mat3, Mat4, UVec2, Vec2, Vec3, Vec4};
+
+use crate::{
+    camera::Camera,
+    render::{
+        calc_tile_bounds, render_channels, sh_coeffs_for_degree, sh_degree_from_coeffs, SH_C0,
+    },
+    shaders::helpers::{
+        ProjectedSplat, RenderUniforms, PROJECTION_EQUIRECT, PROJECTION_FISHEYE, PROJECTION_ORTHO,
+        PROJECTION_PINHOLE, TILE_WIDTH,
+    },
+    Backend, GaussianBackwardState, RenderAux, SplatGrads,
+};
+
+const COV_BLUR: f32 = 0.3;
+const PROJECTED_SIZE: usize = size_of::<ProjectedSplat>() / size_of::<f32>();
+
+fn read_floats<const D: usize>(tensor: FloatTensor<NdArray>) -> (Vec<f32>, [usize; D]) {
+    let tensor = Tensor::<NdArray, D>::from_primitive(TensorPrimitive::Float(tensor));
+    let dims = tensor.dims();
+    (tensor.into_data().iter::<f32>().collect(), dims)
+}
+
+fn read_ints<const D: usize>(tensor: IntTensor<NdArray>) -> Vec<u32> {
+    // Ints are stored as signed integers, so go through i64 to keep the bit pattern intact.
+    Tensor::<NdArray, D, Int>::from_primitive(tensor)
+        .into_data()
+        .iter::<i64>()
+        .map(|x| x as u32)
+        .collect()
+}
+
+fn float_tensor<const D: usize>(data: Vec<f32>, shape: [usize; D]) -> FloatTensor<NdArray> {
+    Tensor::<NdArray, D>::from_data(TensorData::new(data, shape), &NdArrayDevice::Cpu)
+        .into_primitive()
+        .tensor()
+}
+
+fn int_tensor<const D: usize>(data: Vec<u32>, shape: [usize; D]) -> IntTensor<NdArray> {
+    let data: Vec<i32> = data.into_iter().map(|x| x as i32).collect();
+    Tensor::<NdArray, D, Int>::from_data(TensorData::new(data, shape), &NdArrayDevice::Cpu)
+        .into_primitive()
+}
+
+fn vec3_at(data: &[f32], index: usize) -> Vec3 {
+    Vec3::from_slice(&data[index * 3..index * 3 + 3])
+}
+
+fn vec4_at(data: &[f32], index: usize) -> Vec4 {
+    Vec4::from_slice(&data[index * 4..index * 4 + 4])
+}
+
+fn sigmoid(x: f32) -> f32 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+fn v_sigmoid(x: f32) -> f32 {
+    sigmoid(x) * (1.0 - sigmoid(x))
+}
+
+fn quat_to_mat(quat: Vec4) -> Mat3 {
+    let (w, x, y, z) = (quat.x, quat.y, quat.z, quat.w);
+
+    let x2 = x * x;
+    let y2 = y * y;
+    let z2 = z * z;
+    let xy = x * y;
+    let xz = x * z;
+    let yz = y * z;
+    let wx = w * x;
+    let wy = w * y;
+    let wz = w * z;
+
+    Mat3::from_cols(
+        vec3(1.0 - 2.0 * (y2 + z2), 2.0 * (xy + wz), 2.0 * (xz - wy)),
+        vec3(2.0 * (xy - wz), 1.0 - 2.0 * (x2 + z2), 2.0 * (yz + wx)),
+        vec3(2.0 * (xz + wy), 2.0 * (yz - wx), 1.0 - 2.0 * (x2 + y2)),
+    )
+}
+
+fn calc_cov3d(scale: Vec3, quat: Vec4) -> Mat3 {
+    let m = quat_to_mat(quat) * Mat3::from_diagonal(scale);
+    m * m.transpose()
+}
+
+fn calc_cam_lims(focal: Vec2, img_size: UVec2, pixel_center: Vec2) -> (Vec2, Vec2) {
+    let tan_fov = 0.5 * img_size.as_vec2() / focal;
+    let lims_pos = (img_size.as_vec2() - pixel_center) / focal + 0.3 * tan_fov;
+    let lims_neg = pixel_center / focal + 0.3 * tan_fov;
+    (lims_pos, lims_neg)
+}
+
+// G = atan2(sqrt(q), z) / sqrt(q) of the fisheye, and its first and second derivative
+// with respect to q, see fisheye_g in helpers.wgsl.
+fn fisheye_g(q: f32, z: f32) -> Vec3 {
+    if z > 0.0 && q < 0.25 * z * z {
+        // The closed form cancels badly near the optical axis, use the series of atan instead.
+        let u = q / (z * z);
+        let (mut g, mut g_q, mut g_qq) = (0.0, 0.0, 0.0);
+        for n in (0..12).rev() {
+            let sign = if n % 2 == 1 { -1.0 } else { 1.0 };
+            let c = sign / (2 * n + 1) as f32;
+            g = g * u + c;
+            if n >= 1 {
+                g_q = g_q * u + n as f32 * c;
+            }
+            if n >= 2 {
+                g_qq = g_qq * u + (n * (n - 1)) as f32 * c;
+            }
+        }
+        let z2 = z * z;
+        return vec3(g / z, g_q / (z2 * z), g_qq / (z2 * z2 * z));
+    }
+
+    let r = q.sqrt();
+    let s = q + z * z;
+    let theta = r.atan2(z);
+    vec3(
+        theta / r,
+        (z * r - theta * s) / (2.0 * q * r * s),
+        (3.0 * theta * s * s - z * r * (5.0 * q + 3.0 * z * z)) / (4.0 * q * q * r * s * s),
+    )
+}
+
+fn can_project(mean_c: Vec3, projection: u32) -> bool {
+    if projection == PROJECTION_PINHOLE || projection == PROJECTION_ORTHO {
+        return (0.01..=1e12).contains(&mean_c.z);
+    }
+
+    let dist = mean_c.length();
+    if !(0.01..=1e12).contains(&dist) {
+        return false;
+    }
+
+    if projection == PROJECTION_FISHEYE {
+        // Directly behind the camera the fisheye is singular.
+        return mean_c.truncate().length().atan2(mean_c.z) < 0.9 * std::f32::consts::PI;
+    }
+
+    // The longitude is undefined at the poles.
+    vec2(mean_c.x, mean_c.z).length() > 0.01 * dist
+}
+
+fn project_mean(mean_c: Vec3, focal: Vec2, pixel_center: Vec2, projection: u32) -> Vec2 {
+    let xy = mean_c.truncate();
+    let uv = if projection == PROJECTION_FISHEYE {
+        xy * fisheye_g(xy.length_squared(), mean_c.z).x
+    } else if projection == PROJECTION_EQUIRECT {
+        vec2(
+            mean_c.x.atan2(mean_c.z),
+            mean_c.y.atan2(vec2(mean_c.x, mean_c.z).length()),
+        )
+    } else if projection == PROJECTION_ORTHO {
+        xy
+    } else {
+        xy / mean_c.z
+    };
+    focal * uv + pixel_center
+}
+
+fn projected_depth(mean_c: Vec3, projection: u32) -> f32 {
+    if projection == PROJECTION_PINHOLE || projection == PROJECTION_ORTHO {
+        mean_c.z
+    } else {
+        mean_c.length()
+    }
+}
+
+// The 2x3 projection jacobian, stored in the first two rows of a 3x3 matrix.
+fn calc_cam_j(
+    mean_c: Vec3,
+    focal: Vec2,
+    img_size: UVec2,
+    pixel_center: Vec2,
+    projection: u32,
+) -> Mat3 {
+    let (x, y, z) = (mean_c.x, mean_c.y, mean_c.z);
+
+    if projection == PROJECTION_ORTHO {
+        return Mat3::from_diagonal(focal.extend(0.0));
+    }
+
+    if projection == PROJECTION_FISHEYE {
+        let q = x * x + y * y;
+        let g = fisheye_g(q, z);
+        let g_z = -1.0 / (q + z * z);
+
+        return Mat3::from_cols(
+            (focal * vec2(g.x + 2.0 * x * x * g.y, 2.0 * x * y * g.y)).extend(0.0),
+            (focal * vec2(2.0 * x * y * g.y, g.x + 2.0 * y * y * g.y)).extend(0.0),
+            (focal * mean_c.truncate() * g_z).extend(0.0),
+        );
+    }
+
+    if projection == PROJECTION_EQUIRECT {
+        let rho2 = x * x + z * z;
+        let rho = rho2.sqrt();
+        let d2 = rho2 + y * y;
+
+        return Mat3::from_cols(
+            (focal * vec2(z / rho2, -x * y / (rho * d2))).extend(0.0),
+            (focal * vec2(0.0, rho / d2)).extend(0.0),
+            (focal * vec2(-x / rho2, -z * y / (rho * d2))).extend(0.0),
+        );
+    }
+
+    let (lims_pos, lims_neg) = calc_cam_lims(focal, img_size, pixel_center);
+
+    let rz = 1.0 / mean_c.z;
+    let rz2 = rz * rz;
+
+    // Get ndc coords +- clipped to the frustum.
+    let t = mean_c.z * (mean_c.truncate() * rz).max(-lims_neg).min(lims_pos);
+
+    Mat3::from_cols(
+        vec3(focal.x * rz, 0.0, 0.0),
+        vec3(0.0, focal.y * rz, 0.0),
+        (-focal * t * rz2).extend(0.0),
+    )
+}
+
+fn calc_cov2d(
+    cov3d: Mat3,
+    mean_c: Vec3,
+    focal: Vec2,
+    img_size: UVec2,
+    pixel_center: Vec2,
+    rot: Mat3,
+    projection: u32,
+) -> Vec3 {
+    let covar_cam = rot * cov3d * rot.transpose();
+    let j = calc_cam_j(mean_c, focal, img_size, pixel_center, projection);
+    let cov2d = j * covar_cam * j.transpose();
+
+    // Add a little blur along axes and save upper triangular elements.
+    vec3(
+        cov2d.x_axis.x + COV_BLUR,
+        cov2d.x_axis.y,
+        cov2d.y_axis.y + COV_BLUR,
+    )
+}
+
+fn inverse_symmetric(mat: Vec3) -> Vec3 {
+    let det = mat.x * mat.z - mat.y * mat.y;
+    vec3(mat.z, -mat.y, mat.x) * (1.0 / det)
+}
+
+fn cov_compensation(cov2d: Vec3) -> f32 {
+    let cov_orig = cov2d - vec3(COV_BLUR, 0.0, COV_BLUR);
+    let det_orig = cov_orig.x * cov_orig.z - cov_orig.y * cov_orig.y;
+    let det = cov2d.x * cov2d.z - cov2d.y * cov2d.y;
+    (det_orig / det).max(0.0).sqrt()
+}
+
+fn radius_from_cov(cov2d: Vec3) -> f32 {
+    let det = cov2d.x * cov2d.z - cov2d.y * cov2d.y;
+    let b = 0.5 * (cov2d.x + cov2d.z);
+    let v1 = b + (b * b - det).max(0.01).sqrt();
+    (3.0 * v1.sqrt()).ceil()
+}
+
+// Tile bounding box of a splat, inclusive min, exclusive max.
+fn get_tile_bbox(pix_center: Vec2, pix_radius: f32, tile_bounds: UVec2) -> (UVec2, UVec2) {
+    let center = pix_center / TILE_WIDTH as f32;
+    let dims = Vec2::splat(pix_radius / TILE_WIDTH as f32);
+    let bounds = tile_bounds.as_ivec2();
+    let min = (center - dims)
+        .as_ivec2()
+        .max(glam::IVec2::ZERO)
+        .min(bounds);
+    let max = (center + dims + 1.0)
+        .as_ivec2()
+        .max(glam::IVec2::ZERO)
+        .min(bounds);
+    (min.as_uvec2(), max.as_uvec2())
+}
+
+// Like wgsl sign(), which is zero at zero.
+fn sign(v: Vec2) -> Vec2 {
+    let sign = |x: f32| if x == 0.0 { 0.0 } else { x.signum() };
+    vec2(sign(v.x), sign(v.y))
+}
+
+fn check_edge(p1: Vec2, p2: Vec2, ellipse_center: Vec2, ellipse_conic: Mat2) -> bool {
+    let edge = p2 - p1;
+    let f = p1 - ellipse_center;
+    let a = (ellipse_conic * edge).dot(edge);
+    let b = 2.0 * (ellipse_conic * f).dot(edge);
+    let c = (ellipse_conic * f).dot(f) - 1.0;
+    let discriminant = b * b - 4.0 * a * c;
+
+    if discriminant < 0.0 {
+        return false;
+    }
+
+    let sqrt_discriminant = discriminant.sqrt();
+    let t1 = (-b - sqrt_discriminant) / (2.0 * a);
+    let t2 = (-b + sqrt_discriminant) / (2.0 * a);
+    (0.0..=1.0).contains(&t1) || (0.0..=1.0).contains(&t2)
+}
+
+fn ellipse_intersects_aabb(
+    box_pos: Vec2,
+    box_extent: Vec2,
+    ellipse_center: Vec2,
+    ellipse_conic: Mat2,
+) -> bool {
+    let d = ellipse_center - box_pos;
+
+    // Check if ellipse center is inside AABB.
+    if d.abs().cmple(box_extent).all() {
+        return true;
+    }
+
+    // Check if the nearest corner is inside the ellipse.
+    let corner_sign = sign(d);
+    let nearest_corner = box_pos + corner_sign * box_extent;
+    let cp = nearest_corner - ellipse_center;
+    if (ellipse_conic * cp).dot(cp) <= 1.0 {
+        return true;
+    }
+
+    // Check the two edges adjacent to the nearest corner.
+    let edge1_end = nearest_corner - vec2(corner_sign.x * 2.0 * box_extent.x, 0.0);
+    let edge2_end = nearest_corner - vec2(0.0, corner_sign.y * 2.0 * box_extent.y);
+
+    check_edge(nearest_corner, edge1_end, ellipse_center, ellipse_conic)
+        || check_edge(nearest_corner, edge2_end, ellipse_center, ellipse_conic)
+}
+
+fn can_be_visible(tile: UVec2, xy: Vec2, conic: Vec3, opac: f32) -> bool {
+    // Find the ellipse where opac * exp(-sigma) == 1.0 / 255.0.
+    let sigma = (opac * 255.0).ln();
+    if sigma <= 0.0 {
+        return false;
+    }
+    let conic_scaled = conic / (2.0 * sigma);
+    let tile_extent = Vec2::splat(TILE_WIDTH as f32 / 2.0);
+    let tile_center = (tile * TILE_WIDTH).as_vec2() + tile_extent;
+    let conic_mat = Mat2::from_cols(
+        vec2(conic_scaled.x, conic_scaled.y),
+        vec2(conic_scaled.y, conic_scaled.z),
+    );
+    ellipse_intersects_aabb(tile_center, tile_extent, xy, conic_mat)
+}
+
+// Spherical harmonics basis functions up to degree 4, see sh_coeffs_to_color in project_visible.wgsl.
+fn sh_basis(degree: u32, viewdir: Vec3) -> [f32; 25] {
+    let mut basis = [0.0; 25];
+    basis[0] = SH_C0;
+
+    if degree == 0 {
+        return basis;
+    }
+
+    let (x, y, z) = (viewdir.x, viewdir.y, viewdir.z);
+
+    let f_tmp0a = 0.488_602_5;
+    basis[1] = -f_tmp0a * y;
+    basis[2] = f_tmp0a * z;
+    basis[3] = -f_tmp0a * x;
+
+    if degree == 1 {
+        return basis;
+    }
+
+    let z2 = z * z;
+    let f_tmp0b = -1.092_548_4 * z;
+    let f_tmp1a = 0.546_274_2;
+    let f_c1 = x * x - y * y;
+    let f_s1 = 2.0 * x * y;
+    basis[4] = f_tmp1a * f_s1;
+    basis[5] = f_tmp0b * y;
+    basis[6] = 0.946_174_7 * z2 - 0.315_391_57;
+    basis[7] = f_tmp0b * x;
+    basis[8] = f_tmp1a * f_c1;
+
+    if degree == 2 {
+        return basis;
+    }
+
+    let f_tmp0c = -2.285_229 * z2 + 0.457_045_8;
+    let f_tmp1b = 1.445_305_7 * z;
+    let f_tmp2a = -0.590_043_6;
+    let f_c2 = x * f_c1 - y * f_s1;
+    let f_s2 = x * f_s1 + y * f_c1;
+    basis[9] = f_tmp2a * f_s2;
+    basis[10] = f_tmp1b * f_s1;
+    basis[11] = f_tmp0c * y;
+    basis[12] = z * (1.865_881_7 * z2 - 1.119_529);
+    basis[13] = f_tmp0c * x;
+    basis[14] = f_tmp1b * f_c1;
+    basis[15] = f_tmp2a * f_c2;
+
+    if degree == 3 {
+        return basis;
+    }
+
+    let f_tmp0d = z * (-4.683_326 * z2 + 2.007_139_6);
+    let f_tmp1c = 3.311_611_4 * z2 - 0.473_087_34;
+    let f_tmp2b = -1.770_130_8 * z;
+    let f_tmp3a = 0.625_835_7;
+    let f_c3 = x * f_c2 - y * f_s2;
+    let f_s3 = x * f_s2 + y * f_c2;
+    basis[16] = f_tmp3a * f_s3;
+    basis[17] = f_tmp2b * f_s2;
+    basis[18] = f_tmp1c * f_s1;
+    basis[19] = f_tmp0d * y;
+    basis[20] = 1.984_313_5 * z * basis[12] - 1.006_230_6 * basis[6];
+    basis[21] = f_tmp0d * x;
+    basis[22] = f_tmp1c * f_c1;
+    basis[23] = f_tmp2b * f_c2;
+    basis[24] = f_tmp3a * f_c3;
+    basis
+}
+
+// Packs the render settings exactly like the GPU uniforms buffer, so the aux data is interchangeable.
+fn render_uniforms(
+    camera: &Camera,
+    img_size: UVec2,
+    background: Vec3,
+    sh_degree: u32,
+    total_splats: u32,
+) -> RenderUniforms {
+    RenderUniforms {
+        viewmat: camera.world_to_local().to_cols_array_2d(),
+        camera_position: [camera.position.x, camera.position.y, camera.position.z, 0.0],
+        background: background.extend(0.0).to_array(),
+        focal: camera.focal(img_size).into(),
+        pixel_center: camera.center(img_size).into(),
+        img_size: img_size.into(),
+        tile_bounds: calc_tile_bounds(img_size).into(),
+        num_visible: 0,
+        sh_degree,
+        total_splats,
+        projection: camera.projection.shader_id(),
+    }
+}
+
+fn render_forward(
+    camera: &Camera,
+    img_size: UVec2,
+    means: FloatTensor<NdArray>,
+    log_scales: FloatTensor<NdArray>,
+    quats: FloatTensor<NdArray>,
+    sh_coeffs: FloatTensor<NdArray>,
+    raw_opacities: FloatTensor<NdArray>,
+    background: Vec3,
+    mip_filter: bool,
+    raster_u32: bool,
+    render_depth: bool,
+) -> (FloatTensor<NdArray>, RenderAux<NdArray>) {
+    assert!(
+        img_size[0] > 0 && img_size[1] > 0,
+        "Can't render 0 sized images"
+    );
+    assert!(
+        !(raster_u32 && render_depth),
+        "Can't render depth to a u32 buffer"
+    );
+
+    let _span = tracing::trace_span!("render_forward_cpu").entered();
+
+    let (means, [num_points, _]) = read_floats::<2>(means);
+    let (log_scales, _) = read_floats::<2>(log_scales);
+    let (quats, _) = read_floats::<2>(quats);
+    let (sh_coeffs, [_, num_coeffs, _]) = read_floats::<3>(sh_coeffs);
+    let (raw_opacities, _) = read_floats::<1>(raw_opacities);
+
+    let sh_degree = sh_degree_from_coeffs(num_coeffs as u32);
+    let mut uniforms = render_uniforms(camera, img_size, background, sh_degree, num_points as u32);
+
+    let viewmat = Mat4::from_cols_array_2d(&uniforms.viewmat);
+    let rot = Mat3::from_mat4(viewmat);
+    let focal = Vec2::from(uniforms.focal);
+    let pixel_center = Vec2::from(uniforms.pixel_center);
+    let tile_bounds = UVec2::from(uniforms.tile_bounds);
+    let projection = uniforms.projection;
+
+    // Project all splats and cull the ones that can't be visible, see project_forward.wgsl.
+    let mut visible = vec![];
+    for global_gid in 0..num_points {
+        let mean = vec3_at(&means, global_gid);
+        let mean_c = rot * mean + viewmat.w_axis.truncate();
+
+        if !can_project(mean_c, projection) {
+            continue;
+        }
+
+        let scale = vec3_at(&log_scales, global_gid).exp();
+        let quat = vec4_at(&quats, global_gid);
+
+        let cov3d = calc_cov3d(scale, quat);
+        let cov2d = calc_cov2d(
+            cov3d,
+            mean_c,
+            focal,
+            img_size,
+            pixel_center,
+            rot,
+            projection,
+        );
+        let det = cov2d.x * cov2d.z - cov2d.y * cov2d.y;
+
+        if det <= 0.0 {
+            continue;
+        }
+
+        let conic = inverse_symmetric(cov2d);
+        let mean2d = project_mean(mean_c, focal, pixel_center, projection);
+        let radius = radius_from_cov(inverse_symmetric(conic));
+
+        if radius <= 0.0 {
+            continue;
+        }
+
+        // Mask out gaussians outside the image region.
+        if mean2d.x + radius <= 0.0
+            || mean2d.x - radius >= img_size.x as f32
+            || mean2d.y + radius <= 0.0
+            || mean2d.y - radius >= img_size.y as f32
+        {
+            continue;
+        }
+
+        visible.push((global_gid, projected_depth(mean_c, projection)));
+    }
+
+    // Like the radix sort, this is a stable sort.
+    visible.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let num_visible = visible.len();
+    uniforms.num_visible = num_visible as u32;
+
+    let mut global_from_compact_gid = vec![0; num_points];
+    let mut projected_splats = vec![0.0; num_points * PROJECTED_SIZE];
+    let mut num_tiles_hit = vec![0; num_points];
+
+    // Calculate the screen space properties of the visible splats, see project_visible.wgsl.
+    for (compact_gid, &(global_gid, _)) in visible.iter().enumerate() {
+        global_from_compact_gid[compact_gid] = global_gid as u32;
+
+        let mean = vec3_at(&means, global_gid);
+        let scale = vec3_at(&log_scales, global_gid).exp();
+        let quat = vec4_at(&quats, global_gid);
+        let mut opac = sigmoid(raw_opacities[global_gid]);
+
+        let mean_c = rot * mean + viewmat.w_axis.truncate();
+        let cov3d = calc_cov3d(scale, quat);
+        let cov2d = calc_cov2d(
+            cov3d,
+            mean_c,
+            focal,
+            img_size,
+            pixel_center,
+            rot,
+            projection,
+        );
+        let conic = inverse_symmetric(cov2d);
+
+        if mip_filter {
+            opac *= cov_compensation(cov2d);
+        }
+
+        let mean2d = project_mean(mean_c, focal, pixel_center, projection);
+
+        let viewdir = (mean - camera.position).normalize();
+        let basis = sh_basis(sh_degree, viewdir);
+        let mut color = Vec3::splat(0.5);
+        for (i, b) in basis.iter().take(num_coeffs).enumerate() {
+            color += *b * vec3_at(&sh_coeffs, global_gid * num_coeffs + i);
+        }
+
+        let radius = radius_from_cov(inverse_symmetric(conic));
+        let (tile_min, tile_max) = get_tile_bbox(mean2d, radius, tile_bounds);
+
+        let mut tile_area = 0;
+        for ty in tile_min.y..tile_max.y {
+            for tx in tile_min.x..tile_max.x {
+                if can_be_visible(uvec2(tx, ty), mean2d, conic, opac) {
+                    tile_area += 1;
+                }
+            }
+        }
+
+        projected_splats[compact_gid * PROJECTED_SIZE..(compact_gid + 1) * PROJECTED_SIZE]
+            .copy_from_slice(&[
+                mean2d.x,
+                mean2d.y,
+                conic.x,
+                conic.y,
+                conic.z,
+                color.x,
+                color.y,
+                color.z,
+                opac,
+                projected_depth(mean_c, projection),
+            ]);
+        num_tiles_hit[compact_gid] = tile_area;
+    }
+
+    let cum_tiles_hit: Vec<u32> = num_tiles_hit
+        .iter()
+        .scan(0, |sum, &x| {
+            *sum += x;
+            Some(*sum)
+        })
+        .collect();
+    let num_intersections = cum_tiles_hit.last().copied().unwrap_or(0);
+
+    // Map each splat to the tiles it hits, see map_gaussian_to_intersects.wgsl.
+    let mut intersects = Vec::with_capacity(num_intersections as usize);
+    for compact_gid in 0..num_visible {
+        let projected = &projected_splats[compact_gid * PROJECTED_SIZE..];
+        let mean2d = vec2(projected[0], projected[1]);
+        let conic = vec3(projected[2], projected[3], projected[4]);
+        let opac = projected[8];
+        let radius = radius_from_cov(inverse_symmetric(conic));
+        let (tile_min, tile_max) = get_tile_bbox(mean2d, radius, tile_bounds);
+
+        for ty in tile_min.y..tile_max.y {
+            for tx in tile_min.x..tile_max.x {
+                if can_be_visible(uvec2(tx, ty), mean2d, conic, opac) {
+                    intersects.push((tx + ty * tile_bounds.x, compact_gid as u32));
+                }
+            }
+        }
+    }
+
+    // Sort by tile. As the sort is stable, splats within a tile stay sorted by depth.
+    intersects.sort_by_key(|&(tile_id, _)| tile_id);
+
+    let mut tile_bins = vec![0; (tile_bounds.x * tile_bounds.y * 2) as usize];
+    for (isect_id, &(tile_id, _)) in intersects.iter().enumerate() {
+        let tile_id = tile_id as usize;
+        if isect_id == 0 || intersects[isect_id - 1].0 as usize != tile_id {
+            tile_bins[tile_id * 2] = isect_id as u32;
+        }
+        tile_bins[tile_id * 2 + 1] = isect_id as u32 + 1;
+    }
+    let compact_gid_from_isect: Vec<u32> = intersects.iter().map(|&(_, gid)| gid).collect();
+
+    // Rasterize each pixel, see rasterize.wgsl.
+    let channels = if raster_u32 {
+        1
+    } else {
+        render_channels(render_depth)
+    };
+    let pixels = (img_size.x * img_size.y) as usize;
+    let mut out_img = vec![0.0; pixels * channels];
+    let mut final_index = vec![0; pixels];
+    let mut median_index = vec![u32::MAX; pixels];
+
+    for py in 0..img_size.y {
+        for px in 0..img_size.x {
+            let pix_id = (px + py * img_size.x) as usize;
+            let tile_id = (px / TILE_WIDTH + (py / TILE_WIDTH) * tile_bounds.x) as usize;
+            let pixel_coord = vec2(px as f32, py as f32) + 0.5;
+
+            let mut t = 1.0;
+            let mut pix_out = Vec3::ZERO;
+            let mut depth_out = 0.0;
+            let mut median_depth = 0.0;
+            let mut final_idx = 0;
+
+            for isect_id in tile_bins[tile_id * 2]..tile_bins[tile_id * 2 + 1] {
+                let compact_gid = compact_gid_from_isect[isect_id as usize] as usize;
+                let projected = &projected_splats[compact_gid * PROJECTED_SIZE..];
+
+                let xy = vec2(projected[0], projected[1]);
+                let conic = vec3(projected[2], projected[3], projected[4]);
+                let color = Vec4::from_slice(&projected[5..9]);
+                let depth = projected[9];
+
+                let delta = xy - pixel_coord;
+                let sigma = 0.5 * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y)
+                    + conic.y * delta.x * delta.y;
+                let vis = (-sigma).exp();
+                let alpha = (color.w * vis).min(0.999);
+
+                if sigma >= 0.0 && alpha >= 1.0 / 255.0 {
+                    let next_t = t * (1.0 - alpha);
+
+                    if next_t <= 1e-4 {
+                        break;
+                    }
+
+                    pix_out += color.truncate() * (alpha * t);
+                    depth_out += depth * (alpha * t);
+                    if t >= 0.5 && next_t < 0.5 {
+                        median_depth = depth;
+                        median_index[pix_id] = isect_id;
+                    }
+                    t = next_t;
+                    final_idx = isect_id;
+                }
+            }
+
+            let final_color = (pix_out + t * background).extend(1.0 - t);
+            if raster_u32 {
+                let colors_u = (final_color * 255.0).clamp(Vec4::ZERO, Vec4::splat(255.0));
+                let packed = colors_u.x as u32
+                    | ((colors_u.y as u32) << 8)
+                    | ((colors_u.z as u32) << 16)
+                    | ((colors_u.w as u32) << 24);
+                out_img[pix_id] = f32::from_bits(packed);
+            } else {
+                let base = pix_id * channels;
+                out_img[base..base + 4].copy_from_slice(&final_color.to_array());
+                if render_depth {
+                    out_img[base + 4] = depth_out;
+                    out_img[base + 5] = median_depth;
+                }
+                final_index[pix_id] = final_idx;
+            }
+        }
+    }
+
+    let uniforms_buffer: Vec<u32> = bytemuck::cast_slice(bytemuck::bytes_of(&uniforms)).to_vec();
+    let uniforms_size = uniforms_buffer.len();
+    let num_isects = compact_gid_from_isect.len();
+    let (w, h) = (img_size.x as usize, img_size.y as usize);
+
+    (
+        float_tensor(out_img, [h, w, channels]),
+        RenderAux {
+            projected_splats: float_tensor(projected_splats, [num_points, PROJECTED_SIZE]),
+            uniforms_buffer: int_tensor(uniforms_buffer, [uniforms_size]),
+            num_intersections: int_tensor(vec![num_intersections], [1]),
+            num_visible: int_tensor(vec![num_visible as u32], [1]),
+            final_index: int_tensor(final_index, [h, w]),
+            median_index: int_tensor(median_index, [h, w]),
+            cum_tiles_hit: int_tensor(cum_tiles_hit, [num_points]),
+            tile_bins: int_tensor(
+                tile_bins,
+                [tile_bounds.y as usize, tile_bounds.x as usize, 2],
+            ),
+            compact_gid_from_isect: int_tensor(compact_gid_from_isect, [num_isects]),
+            global_from_compact_gid: int_tensor(global_from_compact_gid, [num_points]),
+        },
+    )
+}
+
+fn quat_to_mat_vjp(quat: Vec4, v_r: Mat3) -> Vec4 {
+    let (w, x, y, z) = (quat.x, quat.y, quat.z, quat.w);
+    // Index like wgsl, v_R[col][row].
+    let r = |col: usize, row: usize| v_r.col(col)[row];
+
+    Vec4::new(
+        2.0 * (x * (r(1, 2) - r(2, 1)) + y * (r(2, 0) - r(0, 2)) + z * (r(0, 1) - r(1, 0))),
+        2.0 * (-2.0 * x * (r(1, 1) + r(2, 2))
+            + y * (r(0, 1) + r(1, 0))
+            + z * (r(0, 2) + r(2, 0))
+            + w * (r(1, 2) - r(2, 1))),
+        2.0 * (x * (r(0, 1) + r(1, 0)) - 2.0 * y * (r(0, 0) + r(2, 2))
+            + z * (r(1, 2) + r(2, 1))
+            + w * (r(2, 0) - r(0, 2))),
+        2.0 * (x * (r(0, 2) + r(2, 0)) + y * (r(1, 2) + r(2, 1)) - 2.0 * z * (r(0, 0) + r(1, 1))
+            + w * (r(0, 1) - r(1, 0))),
+    )
+}
+
+fn persp_proj_vjp(
+    j: Mat3,
+    mean3d: Vec3,
+    cov3d: Mat3,
+    focal: Vec2,
+    pixel_center: Vec2,
+    img_size: UVec2,
+    v_cov2d: Mat3,
+    v_mean2d: Vec2,
+    v_depth: f32,
+) -> Vec3 {
+    let (x, y, z) = (mean3d.x, mean3d.y, mean3d.z);
+
+    let rz = 1.0 / z;
+    let rz2 = rz * rz;
+    let rz3 = rz2 * rz;
+
+    let mut v_mean3d = vec3(
+        focal.x * rz * v_mean2d.x,
+        focal.y * rz * v_mean2d.y,
+        -(focal.x * x * v_mean2d.x + focal.y * y * v_mean2d.y) * rz2,
+    );
+
+    let v_j = v_cov2d * j * cov3d.transpose() + v_cov2d.transpose() * j * cov3d;
+
+    let (lims_pos, lims_neg) = calc_cam_lims(focal, img_size, pixel_center);
+    let t = z * (mean3d.truncate() * rz).max(-lims_neg).min(lims_pos);
+
+    // FOV clipping.
+    if x * rz <= lims_pos.x && x * rz >= -lims_neg.x {
+        v_mean3d.x += -focal.x * rz2 * v_j.z_axis.x;
+    } else {
+        v_mean3d.z += -focal.x * rz3 * v_j.z_axis.x * t.x;
+    }
+    if y * rz <= lims_pos.y && y * rz >= -lims_neg.y {
+        v_mean3d.y += -focal.y * rz2 * v_j.z_axis.y;
+    } else {
+        v_mean3d.z += -focal.y * rz3 * v_j.z_axis.y * t.y;
+    }
+    v_mean3d.z += -focal.x * rz2 * v_j.x_axis.x - focal.y * rz2 * v_j.y_axis.y
+        + 2.0 * focal.x * t.x * rz3 * v_j.z_axis.x
+        + 2.0 * focal.y * t.y * rz3 * v_j.z_axis.y;
+
+    v_mean3d.z += v_depth;
+
+    v_mean3d
+}
+
+// Gradient of the camera space mean through the fisheye jacobian, see project_backwards.wgsl.
+fn fisheye_j_vjp(mean3d: Vec3, focal: Vec2, v_j: Mat3) -> Vec3 {
+    let (x, y, z) = (mean3d.x, mean3d.y, mean3d.z);
+    let q = x * x + y * y;
+    let s = q + z * z;
+
+    let g = fisheye_g(q, z);
+    let g_z = -1.0 / s;
+    let g_qz = 1.0 / (s * s);
+    let g_zz = 2.0 * z / (s * s);
+
+    let d_g = vec3(2.0 * x * g.y, 2.0 * y * g.y, g_z);
+    let dd_g = Mat3::from_cols(
+        vec3(
+            2.0 * g.y + 4.0 * x * x * g.z,
+            4.0 * x * y * g.z,
+            2.0 * x * g_qz,
+        ),
+        vec3(
+            4.0 * x * y * g.z,
+            2.0 * g.y + 4.0 * y * y * g.z,
+            2.0 * y * g_qz,
+        ),
+        vec3(2.0 * x * g_qz, 2.0 * y * g_qz, g_zz),
+    );
+
+    let (w_x, w_y) = (v_j.row(0), v_j.row(1));
+    let h_x = vec3(d_g.dot(w_x), 0.0, 0.0) + d_g * w_x.x + x * (dd_g * w_x);
+    let h_y = vec3(0.0, d_g.dot(w_y), 0.0) + d_g * w_y.y + y * (dd_g * w_y);
+    focal.x * h_x + focal.y * h_y
+}
+
+// Gradient of the camera space mean through the equirectangular jacobian, see
+// project_backwards.wgsl.
+fn equirect_j_vjp(mean3d: Vec3, focal: Vec2, v_j: Mat3) -> Vec3 {
+    let (x, y, z) = (mean3d.x, mean3d.y, mean3d.z);
+    let rho2 = x * x + z * z;
+    let rho = rho2.sqrt();
+    let rho3 = rho2 * rho;
+    let rho4 = rho2 * rho2;
+    let d2 = rho2 + y * y;
+    let d4 = d2 * d2;
+
+    let h_lon = Mat3::from_cols(
+        vec3(-2.0 * x * z / rho4, 0.0, (x * x - z * z) / rho4),
+        Vec3::ZERO,
+        vec3((x * x - z * z) / rho4, 0.0, 2.0 * x * z / rho4),
+    );
+
+    let l_r = -y / d2;
+    let l_yy = -2.0 * y * rho / d4;
+    let l_yr = (y * y - rho2) / d4;
+    let l_rr = 2.0 * y * rho / d4;
+    let r_x = x / rho;
+    let r_z = z / rho;
+
+    let h_xx = l_rr * r_x * r_x + l_r * z * z / rho3;
+    let h_xy = l_yr * r_x;
+    let h_xz = l_rr * r_x * r_z - l_r * x * z / rho3;
+    let h_yz = l_yr * r_z;
+    let h_zz = l_rr * r_z * r_z + l_r * x * x / rho3;
+    let h_lat = Mat3::from_cols(
+        vec3(h_xx, h_xy, h_xz),
+        vec3(h_xy, l_yy, h_yz),
+        vec3(h_xz, h_yz, h_zz),
+    );
+
+    let (w_x, w_y) = (v_j.row(0), v_j.row(1));
+    focal.x * (h_lon * w_x) + focal.y * (h_lat * w_y)
+}
+
+fn proj_vjp(
+    j: Mat3,
+    mean3d: Vec3,
+    cov3d: Mat3,
+    focal: Vec2,
+    pixel_center: Vec2,
+    img_size: UVec2,
+    projection: u32,
+    v_cov2d: Mat3,
+    v_mean2d: Vec2,
+    v_depth: f32,
+) -> Vec3 {
+    if projection == PROJECTION_PINHOLE {
+        return persp_proj_vjp(
+            j,
+            mean3d,
+            cov3d,
+            focal,
+            pixel_center,
+            img_size,
+            v_cov2d,
+            v_mean2d,
+            v_depth,
+        );
+    }
+
+    if projection == PROJECTION_ORTHO {
+        // The jacobian is constant, so only the mean and depth have a gradient.
+        return j.transpose() * v_mean2d.extend(0.0) + vec3(0.0, 0.0, v_depth);
+    }
+
+    // J includes the focal length, so the mean gradient is just J^T * v_mean2d.
+    let mut v_mean3d = j.transpose() * v_mean2d.extend(0.0);
+
+    let v_j = v_cov2d * j * cov3d.transpose() + v_cov2d.transpose() * j * cov3d;
+    if projection == PROJECTION_FISHEYE {
+        v_mean3d += fisheye_j_vjp(mean3d, focal, v_j);
+    } else {
+        v_mean3d += equirect_j_vjp(mean3d, focal, v_j);
+    }
+
+    // The depth is the distance to the camera.
+    v_mean3d + v_depth * mean3d.normalize()
+}
+
+fn cov_compensation_vjp(cov2d: Vec3, compensation: f32, v_compensation: f32) -> Mat2 {
+    let inv_orig = inverse_symmetric(cov2d - vec3(COV_BLUR, 0.0, COV_BLUR));
+    let inv = inverse_symmetric(cov2d);
+    let v = 0.5 * compensation * v_compensation * (inv_orig - inv);
+    Mat2::from_cols(vec2(v.x, v.y), vec2(v.y, v.z))
+}
+
+fn render_backward(
+    state: GaussianBackwardState<NdArray>,
+    v_output: FloatTensor<NdArray>,
+) -> SplatGrads<NdArray> {
+    let _span = tracing::trace_span!("render_backward_cpu").entered();
+
+    let uniforms_buffer = read_ints::<1>(state.aux.uniforms_buffer);
+    let uniforms: RenderUniforms =
+        bytemuck::pod_read_unaligned(bytemuck::cast_slice(&uniforms_buffer));
+
+    let (means, [num_points, _]) = read_floats::<2>(state.means);
+    let (log_scales, _) = read_floats::<2>(state.log_scales);
+    let (quats, _) = read_floats::<2>(state.quats);
+    let (raw_opac, _) = read_floats::<1>(state.raw_opac);
+    let (out_img, [_, _, channels]) = read_floats::<3>(state.out_img);
+    let render_depth = channels == render_channels(true);
+    let (v_output, _) = read_floats::<3>(v_output);
+    let (projected_splats, _) = read_floats::<2>(state.aux.projected_splats);
+
+    let compact_gid_from_isect = read_ints::<1>(state.aux.compact_gid_from_isect);
+    let global_from_compact_gid = read_ints::<1>(state.aux.global_from_compact_gid);
+    let tile_bins = read_ints::<3>(state.aux.tile_bins);
+    let final_index = read_ints::<2>(state.aux.final_index);
+    let median_index = read_ints::<2>(state.aux.median_index);
+
+    let viewmat = Mat4::from_cols_array_2d(&uniforms.viewmat);
+    let rot = Mat3::from_mat4(viewmat);
+    let focal = Vec2::from(uniforms.focal);
+    let pixel_center = Vec2::from(uniforms.pixel_center);
+    let img_size = UVec2::from(uniforms.img_size);
+    let tile_bounds = UVec2::from(uniforms.tile_bounds);
+    let projection = uniforms.projection;
+    let camera_position = Vec4::from(uniforms.camera_position).truncate();
+    let background = Vec4::from(uniforms.background).truncate();
+    let num_visible = uniforms.num_visible as usize;
+    let sh_degree = state.sh_degree;
+
+    let mut v_xys_local = vec![Vec2::ZERO; num_points];
+    let mut v_xys_abs_local = vec![Vec2::ZERO; num_points];
+    let mut v_conics = vec![Vec3::ZERO; num_points];
+    let mut v_colors = vec![Vec4::ZERO; num_points];
+    let mut v_depths = vec![0.0; num_points];
+
+    // Walk back to front through the splats of each pixel, see rasterize_backwards.wgsl.
+    for py in 0..img_size.y {
+        for px in 0..img_size.x {
+            let pix_id = (px + py * img_size.x) as usize;
+            let tile_id = (px / TILE_WIDTH + (py / TILE_WIDTH) * tile_bounds.x) as usize;
+            let pixel_coord = vec2(px as f32, py as f32) + 0.5;
+
+            let base = pix_id * channels;
+
+            // This is the T after the last gaussian in this pixel.
+            let t_final = 1.0 - out_img[base + 3];
+            let mut t = t_final;
+            let mut buffer = Vec3::ZERO;
+            let mut depth_buffer = 0.0;
+
+            let final_isect = final_index[pix_id];
+            let median_isect = median_index[pix_id];
+            let v_out = Vec4::from_slice(&v_output[base..base + 4]);
+            let (v_depth_out, v_median_depth) = if render_depth {
+                (v_output[base + 4], v_output[base + 5])
+            } else {
+                (0.0, 0.0)
+            };
+
+            for isect_id in (tile_bins[tile_id * 2]..tile_bins[tile_id * 2 + 1]).rev() {
+                if isect_id > final_isect {
+                    continue;
+                }
+
+                let compact_gid = compact_gid_from_isect[isect_id as usize] as usize;
+                let projected = &projected_splats[compact_gid * PROJECTED_SIZE..];
+
+                let xy = vec2(projected[0], projected[1]);
+                let conic = vec3(projected[2], projected[3], projected[4]);
+                let color = Vec4::from_slice(&projected[5..9]);
+                let depth = projected[9];
+
+                let delta = xy - pixel_coord;
+                let sigma = 0.5 * (conic.x * delta.x * delta.x + conic.z * delta.y * delta.y)
+                    + conic.y * delta.x * delta.y;
+                let vis = (-sigma).exp();
+                // Nb: This clamps at 0.99 while the forward pass clamps at 0.999. Match the
+                // kernel exactly rather than fix this here.
+                let alpha = (color.w * vis).min(0.99);
+
+                if sigma >= 0.0 && alpha >= 1.0 / 255.0 {
+                    let ra = 1.0 / (1.0 - alpha);
+                    t *= ra;
+                    let fac = alpha * t;
+
+                    let mut v_alpha = (color.truncate() * t - buffer * ra).dot(v_out.truncate());
+                    // The alpha and the background both depend on the final transmittance.
+                    v_alpha += t_final * ra * (v_out.w - background.dot(v_out.truncate()));
+
+                    buffer += color.truncate() * fac;
+
+                    if render_depth {
+                        v_alpha += (depth * t - depth_buffer * ra) * v_depth_out;
+                        depth_buffer += depth * fac;
+                        v_depths[compact_gid] += fac * v_depth_out;
+
+                        // The median depth is the depth of a single splat, the one the forward
+                        // pass recorded.
+                        if isect_id == median_isect {
+                            v_depths[compact_gid] += v_median_depth;
+                        }
+                    }
+
+                    let v_sigma = -color.w * vis * v_alpha;
+
+                    let v_xy = v_sigma
+                        * vec2(
+                            conic.x * delta.x + conic.y * delta.y,
+                            conic.y * delta.x + conic.z * delta.y,
+                        );
+                    v_xys_local[compact_gid] += v_xy;
+                    if state.abs_grad {
+                        v_xys_abs_local[compact_gid] += v_xy.abs();
+                    }
+                    v_conics[compact_gid] += vec3(
+                        0.5 * v_sigma * delta.x * delta.x,
+                        v_sigma * delta.x * delta.y,
+                        0.5 * v_sigma * delta.y * delta.y,
+                    );
+                    v_colors[compact_gid] += (v_out.truncate() * fac).extend(vis * v_alpha);
+                }
+            }
+        }
+    }
+
+    let num_coeffs = sh_coeffs_for_degree(sh_degree) as usize;
+    let mut v_coeffs = vec![0.0; num_points * num_coeffs * 3];
+    let mut v_opacs = vec![0.0; num_points];
+    let mut v_xys_global = vec![0.0; num_points * 2];
+    let mut v_xys_abs_global = vec![0.0; num_points * 2];
+    let mut v_means = vec![0.0; num_points * 3];
+    let mut v_scales = vec![0.0; num_points * 3];
+    let mut v_quats = vec![0.0; num_points * 4];
+    let mut v_camera = [0.0; 8];
+
+    for (compact_gid, &global_gid) in global_from_compact_gid.iter().enumerate().take(num_visible) {
+        let global_gid = global_gid as usize;
+        let v_color = v_colors[compact_gid];
+
+        // Convert RGB to SH gradients, see gather_grads.wgsl.
+        let mean = vec3_at(&means, global_gid);
+        let viewdir = (mean - camera_position).normalize();
+        let basis = sh_basis(sh_degree, viewdir);
+        for (i, b) in basis.iter().take(num_coeffs).enumerate() {
+            let base = (global_gid * num_coeffs + i) * 3;
+            v_coeffs[base..base + 3].copy_from_slice(&(*b * v_color.truncate()).to_array());
+        }
+
+        v_opacs[global_gid] = v_color.w * v_sigmoid(raw_opac[global_gid]);
+        v_xys_global[global_gid * 2..global_gid * 2 + 2]
+            .copy_from_slice(&v_xys_local[compact_gid].to_array());
+        v_xys_abs_global[global_gid * 2..global_gid * 2 + 2]
+            .copy_from_slice(&v_xys_abs_local[compact_gid].to_array());
+
+        // Backpropagate through the projection, see project_backwards.wgsl.
+        let scale = vec3_at(&log_scales, global_gid).exp();
+        let quat = vec4_at(&quats, global_gid);
+        let v_conic = v_conics[compact_gid];
+        let v_mean2d = v_xys_local[compact_gid];
+
+        let mean_c = rot * mean + viewmat.w_axis.truncate();
+
+        let rotmat = quat_to_mat(quat);
+        let s = Mat3::from_diagonal(scale);
+        let m = rotmat * s;
+        let covar = m * m.transpose();
+        let cov2d = calc_cov2d(
+            covar,
+            mean_c,
+            focal,
+            img_size,
+            pixel_center,
+            rot,
+            projection,
+        );
+        let conics = inverse_symmetric(cov2d);
+
+        let covar2d_inv = Mat2::from_cols(vec2(conics.x, conics.y), vec2(conics.y, conics.z));
+        let v_covar2d_inv = Mat2::from_cols(
+            vec2(v_conic.x, v_conic.y * 0.5),
+            vec2(v_conic.y * 0.5, v_conic.z),
+        );
+        let mut v_covar2d = -covar2d_inv * v_covar2d_inv * covar2d_inv;
+
+        if state.mip_filter {
+            // The opacity was scaled by the compensation, which depends on the covariance too.
+            let compensation = cov_compensation(cov2d);
+            v_opacs[global_gid] *= compensation;
+
+            if compensation > 0.0 {
+                let v_compensation = v_color.w * sigmoid(raw_opac[global_gid]);
+                v_covar2d += cov_compensation_vjp(cov2d, compensation, v_compensation);
+            }
+        }
+        // Pad to 3x3 to match the jacobian layout.
+        let v_covar2d = Mat3::from_cols(
+            v_covar2d.x_axis.extend(0.0),
+            v_covar2d.y_axis.extend(0.0),
+            Vec3::ZERO,
+        );
+
+        let covar_c = rot * covar * rot.transpose();
+        let j = calc_cam_j(mean_c, focal, img_size, pixel_center, projection);
+        let v_mean_c = proj_vjp(
+            j,
+            mean_c,
+            covar_c,
+            focal,
+            pixel_center,
+            img_size,
+            projection,
+            v_covar2d,
+            v_mean2d,
+            v_depths[compact_gid],
+        );
+        let v_covar_c = j.transpose() * v_covar2d * j;
+
+        if state.camera_grad {
+            // See project_backwards.wgsl.
+            let v_cov_rot = v_covar_c * covar_c + v_covar_c.transpose() * covar_c;
+            let r = |col: usize, row: usize| v_cov_rot.col(col)[row];
+            let v_rot = mean_c.cross(v_mean_c)
+                + vec3(r(1, 2) - r(2, 1), r(2, 0) - r(0, 2), r(0, 1) - r(1, 0));
+
+            let uv = (project_mean(mean_c, focal, pixel_center, projection) - pixel_center) / focal;
+            let v_j = v_covar2d * j * covar_c.transpose() + v_covar2d.transpose() * j * covar_c;
+            let v_j_rows = v_j.x_axis * j.x_axis + v_j.y_axis * j.y_axis + v_j.z_axis * j.z_axis;
+            let v_focal = uv * v_mean2d + v_j_rows.truncate() / focal;
+
+            for (v, g) in v_camera.iter_mut().zip(
+                v_rot
+                    .to_array()
+                    .into_iter()
+                    .chain(v_mean_c.to_array())
+                    .chain(v_focal.to_array()),
+            ) {
+                *v += g;
+            }
+        }
+
+        let v_mean = rot.transpose() * v_mean_c;
+        let v_covar = rot.transpose() * v_covar_c * rot;
+        let v_m = (v_covar + v_covar.transpose()) * m;
+
+        let v_scale = vec3(
+            rotmat.x_axis.dot(v_m.x_axis),
+            rotmat.y_axis.dot(v_m.y_axis),
+            rotmat.z_axis.dot(v_m.z_axis),
+        );
+        let v_quat = quat_to_mat_vjp(quat, v_m * s);
+
+        v_means[global_gid * 3..global_gid * 3 + 3].copy_from_slice(&v_mean.to_array());
+        v_scales[global_gid * 3..global_gid * 3 + 3].copy_from_slice(&(v_scale * scale).to_array());
+        v_quats[global_gid * 4..global_gid * 4 + 4].copy_from_slice(&v_quat.to_array());
+    }
+
+    SplatGrads {
+        v_means: float_tensor(v_means, [num_points, 3]),
+        v_quats: float_tensor(v_quats, [num_points, 4]),
+        v_scales: float_tensor(v_scales, [num_points, 3]),
+        v_coeffs: float_tensor(v_coeffs, [num_points, num_coeffs, 3]),
+        v_raw_opac: float_tensor(v_opacs, [num_points]),
+        v_xy: float_tensor(v_xys_global, [num_points, 2]),
+        v_xy_abs: float_tensor(v_xys_abs_global, [num_points, 2]),
+        v_camera: float_tensor(v_camera.to_vec(), [8]),
+    }
+}
+
+impl Backend for NdArray {
+    fn render_splats(
+        camera: &Camera,
+        img_size: glam::UVec2,
+        means: Self::FloatTensorPrimitive,
+        _xy_dummy: Self::FloatTensorPrimitive,
+        _xy_abs_dummy: Self::FloatTensorPrimitive,
+        _camera_dummy: Self::FloatTensorPrimitive,
+        log_scales: Self::FloatTensorPrimitive,
+        quats: Self::FloatTensorPrimitive,
+        sh_coeffs: Self::FloatTensorPrimitive,
+        raw_opacity: Self::FloatTensorPrimitive,
+        background: glam::Vec3,
+        mip_filter: bool,
+        render_u32_buffer: bool,
+        render_depth: bool,
+    ) -> (Self::FloatTensorPrimitive, RenderAux<Self>) {
+        render_forward(
+            camera,
+            img_size,
+            means,
+            log_scales,
+            quats,
+            sh_coeffs,
+            raw_opacity,
+            background,
+            mip_filter,
+            render_u32_buffer,
+            render_depth,
+        )
+    }
+
+    fn render_splats_bwd(
+        state: GaussianBackwardState<Self>,
+        v_output: Self::FloatTensorPrimitive,
+    ) -> SplatGrads<Self> {
+        render_backward(state, v_output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Read;
+
+    use super::*;
+    use crate::{
+        camera::{focal_to_fov, fov_to_focal},
+        gaussian_splats::Splats,
+        safetensor_utils::safetensor_to_burn,
+    };
+    use anyhow::{Context, Result};
+    use assert_approx_eq::assert_approx_eq;
+    use burn::{
+        backend::Autodiff,
+        tensor::{Float, Tensor, TensorPrimitive},
+    };
+    use safetensors::SafeTensors;
+
+    type DiffBack = Autodiff<NdArray>;
+
+    #[tokio::test]
+    async fn renders_at_all() {
+        // These are some zero-sized gaussians, so we know what the result should look like.
+        let cam = Camera::new(
+            glam::vec3(0.0, 0.0, 0.0),
+            glam::Quat::IDENTITY,
+            0.5,
+            0.5,
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(32, 32);
+        let device = NdArrayDevice::Cpu;
+        let num_points = 8;
+        let means = Tensor::<DiffBack, 2>::zeros([num_points, 3], &device);
+        let xy_dummy = Tensor::<DiffBack, 2>::zeros([num_points, 2], &device);
+        let xy_abs_dummy = Tensor::<DiffBack, 2>::zeros([num_points, 2], &device);
+        let camera_dummy = Tensor::<DiffBack, 1>::zeros([8], &device);
+        let log_scales = Tensor::<DiffBack, 2>::ones([num_points, 3], &device) * 2.0;
+        let quats: Tensor<DiffBack, 2> =
+            Tensor::<DiffBack, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
+                .unsqueeze_dim(0)
+                .repeat_dim(0, num_points);
+        let sh_coeffs = Tensor::<DiffBack, 3>::ones([num_points, 1, 3], &device);
+        let raw_opacity = Tensor::<DiffBack, 1>::zeros([num_points], &device);
+        let (output, _) = DiffBack::render_splats(
+            &cam,
+            img_size,
+            means.into_primitive().tensor(),
+            xy_dummy.into_primitive().tensor(),
+            xy_abs_dummy.into_primitive().tensor(),
+            camera_dummy.into_primitive().tensor(),
+            log_scales.into_primitive().tensor(),
+            quats.into_primitive().tensor(),
+            sh_coeffs.into_primitive().tensor(),
+            raw_opacity.into_primitive().tensor(),
+            glam::Vec3::ZERO,
+            false,
+            false,
+            false,
+        );
+
+        let output: Tensor<DiffBack, 3> = Tensor::from_primitive(TensorPrimitive::Float(output));
+        let rgb = output.clone().slice([0..32, 0..32, 0..3]);
+        let alpha = output.slice([0..32, 0..32, 3..4]);
+        let rgb_mean = rgb.mean().to_data().as_slice::<f32>().unwrap()[0];
+        let alpha_mean = alpha.mean().to_data().as_slice::<f32>().unwrap()[0];
+        assert_approx_eq!(rgb_mean, 0.0, 1e-5);
+        assert_approx_eq!(alpha_mean, 0.0);
+    }
+
+    #[tokio::test]
+    async fn renders_depth() {
+        // Two large opaque splats straight ahead, so the center pixel is covered by the front one.
+        let cam = Camera::new(
+            glam::vec3(0.0, 0.0, 0.0),
+            glam::Quat::IDENTITY,
+            0.5,
+            0.5,
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(16, 16);
+        let splats = Splats::<DiffBack>::from_raw(
+            vec![glam::vec3(0.0, 0.0, 2.0), glam::vec3(0.0, 0.0, 4.0)],
+            None,
+            Some(vec![Vec3::splat(0.0); 2]),
+            None,
+            Some(vec![8.0; 2]),
+            &NdArrayDevice::Cpu,
+        );
+
+        let (img, _) = splats.render(&cam, img_size, glam::Vec3::ZERO, false);
+        let (rgba, expected_depth, median_depth, _) =
+            splats.render_with_depth(&cam, img_size, glam::Vec3::ZERO);
+        assert!(img.all_close(rgba, Some(1e-6), Some(1e-6)));
+
+        let expected = expected_depth.slice([8..9, 8..9]).into_scalar();
+        let median = median_depth.slice([8..9, 8..9]).into_scalar();
+        assert_approx_eq!(median, 2.0, 1e-5);
+        assert!((2.0..2.01).contains(&expected));
+    }
+
+    #[tokio::test]
+    async fn median_depth_gradient_goes_to_one_splat() {
+        // Two splats at the same depth, the second one takes the transmittance below 0.5.
+        let cam = Camera::new(
+            glam::vec3(0.0, 0.0, 0.0),
+            glam::Quat::IDENTITY,
+            0.5,
+            0.5,
+            glam::vec2(0.5, 0.5),
+        );
+        let img_size = glam::uvec2(16, 16);
+        let splats = Splats::<DiffBack>::from_raw(
+            vec![glam::vec3(0.0, 0.0, 2.0); 2],
+            None,
+            Some(vec![Vec3::splat(0.0); 2]),
+            None,
+            Some(vec![0.0; 2]),
+            &NdArrayDevice::Cpu,
+        );
+
+        let (_, _, median_depth, _) = splats.render_with_depth(&cam, img_size, glam::Vec3::ZERO);
+        let grads = median_depth.slice([8..9, 8..9]).sum().backward();
+        let v_means = splats.means.grad(&grads).unwrap();
+        let v_z = v_means
+            .slice([0..2, 2..3])
+            .into_data()
+            .to_vec::<f32>()
+            .unwrap();
+        assert_eq!(v_z.iter().filter(|&&v| v != 0.0).count(), 1, "{v_z:?}");
+    }
+
+    #[tokio::test]
+    async fn test_reference() -> Result<()> {
+        let device = NdArrayDevice::Cpu;
+
+        let crab_img = image::open("./test_cases/crab.png")?;
+        let raw_buffer = crab_img.to_rgb8().into_raw();
+        let crab_tens: Tensor<DiffBack, 3> = Tensor::<_, 1>::from_floats(
+            raw_buffer
+                .iter()
+                .map(|&b| b as f32 / 255.0)
+                .collect::<Vec<_>>()
+                .as_slice(),
+            &device,
+        )
+        .reshape([crab_img.height() as usize, crab_img.width() as usize, 3]);
+        let crab_tens = Tensor::cat(
+            vec![
+                crab_tens,
+                Tensor::zeros(
+                    [crab_img.height() as usize, crab_img.width() as usize, 1],
+                    &device,
+                ),
+            ],
+            2,
+        );
+
+        for path in ["tiny_case", "basic_case"] {
+            println!("Checking path {}", path);
+
+            let mut buffer = Vec::new();
+            let _ =
+                File::open(format!("./test_cases/{path}.safetensors"))?.read_to_end(&mut buffer)?;
+
+            let tensors = SafeTensors::deserialize(&buffer)?;
+            let splats = Splats::<DiffBack>::from_safetensors(&tensors, &device)?;
+
+            let img_ref = safetensor_to_burn::<DiffBack, 3>(tensors.tensor("out_img")?, &device);
+            let [h, w, _] = img_ref.dims();
+
+            let fov = std::f64::consts::PI * 0.5;
+            let focal = fov_to_focal(fov, w as u32);
+            let fov_x = focal_to_fov(focal, w as u32);
+            let fov_y = focal_to_fov(focal, h as u32);
+
+            let cam = Camera::new(
+                glam::vec3(0.123, -0.123, -8.0),
+                glam::Quat::IDENTITY,
+                fov_x,
+                fov_y,
+                glam::vec2(0.5, 0.5),
+            );
+
+            // Normalize the rotations before rendering like gsplat, see the GPU test.
+            let rotations = splats.rotation.val();
+            let norm_rot = rotations.clone() / Tensor::sum_dim(rotations.powi_scalar(2), 1).sqrt();
+
+            let (img, aux) = DiffBack::render_splats(
+                &cam,
+                glam::uvec2(w as u32, h as u32),
+                splats.means.val().into_primitive().tensor(),
+                splats.xys_dummy.clone().into_primitive().tensor(),
+                splats.xys_abs_dummy.clone().into_primitive().tensor(),
+                splats.camera_dummy.clone().into_primitive().tensor(),
+                splats.log_scales.val().into_primitive().tensor(),
+                norm_rot.into_primitive().tensor(),
+                splats.sh_coeffs.val().into_primitive().tensor(),
+                splats.raw_opacity.val().into_primitive().tensor(),
+                glam::Vec3::ZERO,
+                false,
+                false,
+                false,
+            );
+            let out: Tensor<DiffBack, 3> = Tensor::from_primitive(TensorPrimitive::Float(img));
+
+            assert!(out.clone().all_close(img_ref, Some(1e-5), Some(1e-6)));
+
+            let num_visible = aux.read_num_visible().await as usize;
+            let projected_splats: Tensor<DiffBack, 2, Float> =
+                Tensor::from_primitive(TensorPrimitive::Float(aux.projected_splats.clone()));
+            let gs_ids =
+                Tensor::<DiffBack, 1, Int>::from_primitive(aux.global_from_compact_gid.clone());
+
+            let xys = projected_splats.clone().slice([0..num_visible, 0..2]);
+            let xys_ref = safetensor_to_burn::<DiffBack, 2>(tensors.tensor("xys")?, &device);
+            let xys_ref = xys_ref.select(0, gs_ids.clone()).slice([0..num_visible]);
+            assert!(xys.all_close(xys_ref, Some(1e-1), Some(1e-6)));
+
+            let conics = projected_splats.slice([0..num_visible, 2..5]);
+            let conics_ref = safetensor_to_burn::<DiffBack, 2>(tensors.tensor("conics")?, &device);
+            let conics_ref = conics_ref.select(0, gs_ids).slice([0..num_visible]);
+            assert!(conics.all_close(conics_ref, Some(1e-3), Some(1e-6)));
+
+            let grads = (out - crab_tens.clone()).powi_scalar(2.0).mean().backward();
+
+            let v_xys_ref =
+                safetensor_to_burn::<DiffBack, 2>(tensors.tensor("v_xy")?, &device).inner();
+            let v_xys = splats.xys_dummy.grad(&grads).context("no xys grad")?;
+            assert!(v_xys.all_close(v_xys_ref, Some(1e-5), Some(1e-9)));
+
+            let v_opacities_ref =
+                safetensor_to_burn::<DiffBack, 1>(tensors.tensor("v_opacities")?, &device).inner();
+            let v_opacities = splats.raw_opacity.grad(&grads).context("opacities grad")?;
+            assert!(v_opacities.all_close(v_opacities_ref, Some(1e-5), Some(1e-10)));
+
+            let v_coeffs_ref =
+                safetensor_to_burn::<DiffBack, 3>(tensors.tensor("v_coeffs")?, &device).inner();
+            let v_coeffs = splats.sh_coeffs.grad(&grads).context("coeffs grad")?;
+            assert!(v_coeffs.all_close(v_coeffs_ref, Some(1e-4), Some(1e-9)));
+
+            let v_means_ref =
+                safetensor_to_burn::<DiffBack, 2>(tensors.tensor("v_means")?, &device).inner();
+            let v_means = splats.means.grad(&grads).context("means grad")?;
+            assert!(v_means.all_close(v_means_ref, Some(1e-4), Some(1e-9)));
+
+            let v_quats_ref =
+                safetensor_to_burn::<DiffBack, 2>(tensors.tensor("v_quats")?, &device).inner();
+            let v_quats = splats.rotation.grad(&grads).context("quats grad")?;
+            assert!(v_quats.all_close(v_quats_ref, Some(1e-4), Some(1e-9)));
+
+            let v_scales_ref =
+                safetensor_to_burn::<DiffBack, 2>(tensors.tensor("v_scales")?, &device).inner();
+            let v_scales = splats.log_scales.grad(&grads).context("scales grad")?;
+            assert!(v_scales.all_close(v_scales_ref, Some(1e-4), Some(1e-9)));
+        }
+        Ok(())
+    }
+}
@@ -30,8 +30,24 @@ pub struct Splats<B: Backend> {
 
     // Dummy input to track screenspace gradient.
     pub xys_dummy: Tensor<B, 2>,
+    // Dummy input to track the screenspace gradient with the absolute value of each pixel summed.
+    // These are only calculated when this requires a gradient, which it doesn't by default.
+    pub xys_abs_dummy: Tensor<B, 2>,
+    // Dummy input to track the gradient of the camera, see `Backend::render_splats` for the
+    // layout. Only calculated when this requires a gradient, which it doesn't by default.
+    pub camera_dummy: Tensor<B, 1>,
+
+    // Variance of the 3D smoothing filter of Mip-Splatting for each splat, see `with_filter_3d`.
+    pub filter_3d: Option<Tensor<B, 1>>,
+    // Whether to render with the 2D filter of Mip-Splatting.
+    pub mip_filter_2d: bool,
+    // Only render the SH bands up to this degree, see `render_sh_coeffs`.
+    pub render_sh_degree: Option<u32>,
 }
 
+// Variance of the 3D filter, in squared samples of the highest resolution view of a splat.
+const FILTER_3D_VARIANCE: f32 = 0.2;
+
 pub fn inverse_sigmoid(x: f32) -> f32 {
     (x / (1.0 - x)).ln()
 }
@@ -192,6 +208,11 @@ impl<B: Backend> Splats<B> {
             raw_opacity: Param::initialized(ParamId::new(), raw_opacity.detach().require_grad()),
             log_scales: Param::initialized(ParamId::new(), log_scales.detach().require_grad()),
             xys_dummy: Tensor::zeros([num_points, 2], &device).require_grad(),
+            xys_abs_dummy: Tensor::zeros([num_points, 2], &device),
+            camera_dummy: Tensor::zeros([8], &device),
+            filter_3d: None,
+            mip_filter_2d: false,
+            render_sh_degree: None,
         }
     }
 
@@ -202,27 +223,125 @@ impl<B: Backend> Splats<B> {
         *tensor = tensor.clone().map(|x| f(x).detach().require_grad());
     }
 
+    /// Set the 3D filter of Mip-Splatting from the highest sampling rate each splat is seen at,
+    /// in pixels per world unit. This blurs away details smaller than the training views
+    /// could resolve, which otherwise show up as aliasing when zooming in.
+    pub fn with_filter_3d(mut self, max_sampling_rate: Tensor<B, 1>) -> Self {
+        let variance = (max_sampling_rate.clone() * max_sampling_rate).recip() * FILTER_3D_VARIANCE;
+        self.filter_3d = Some(variance.detach());
+        self
+    }
+
+    /// The log scales and raw opacities with the 3D filter applied, as used for rendering.
+    ///
+    /// The filter adds its variance to each axis of the splat, and scales the opacity down
+    /// so the total density of the splat stays the same.
+    pub fn filtered_params(&self) -> (Tensor<B, 2>, Tensor<B, 1>) {
+        let Some(filter_3d) = &self.filter_3d else {
+            return (self.log_scales.val(), self.raw_opacity.val());
+        };
+
+        let variance = (self.log_scales.val() * 2.0).exp() + filter_3d.clone().unsqueeze_dim(1);
+        let log_scales = variance.log() * 0.5;
+
+        // Ratio of the volume of the splat before and after filtering.
+        let compensation = (self.log_scales.val() - log_scales.clone())
+            .sum_dim(1)
+            .squeeze(1)
+            .exp();
+        let opacity = (self.opacity() * compensation).clamp(1e-6, 1.0 - 1e-6);
+        let raw_opacity = (opacity.clone() / (opacity.neg() + 1.0)).log();
+
+        (log_scales, raw_opacity)
+    }
+
+    /// The SH coefficients used for rendering, without the bands above `render_sh_degree`.
+    ///
+    /// The coefficients are sliced rather than copied, so the bands that are left out stay
+    /// connected to the parameter and just get no gradient.
+    pub fn render_sh_coeffs(&self) -> Tensor<B, 3> {
+        let coeffs = self.sh_coeffs.val();
+        let [n, c, _] = coeffs.dims();
+        match self.render_sh_degree {
+            Some(degree) if (sh_coeffs_for_degree(degree) as usize) < c => {
+                coeffs.slice([0..n, 0..sh_coeffs_for_degree(degree) as usize])
+            }
+            _ => coeffs,
+        }
+    }
+
     pub fn render(
         &self,
         camera: &Camera,
         img_size: glam::UVec2,
+        background: glam::Vec3,
         render_u32_buffer: bool,
     ) -> (Tensor<B, 3>, crate::RenderAux<B>) {
+        let (log_scales, raw_opacity) = self.filtered_params();
         let (img, aux) = B::render_splats(
             camera,
             img_size,
             self.means.val().into_primitive().tensor(),
             self.xys_dummy.clone().into_primitive().tensor(),
-            self.log_scales.val().into_primitive().tensor(),
+            self.xys_abs_dummy.clone().into_primitive().tensor(),
+            self.camera_dummy.clone().into_primitive().tensor(),
+            log_scales.into_primitive().tensor(),
             self.rotation.val().into_primitive().tensor(),
-            self.sh_coeffs.val().into_primitive().tensor(),
-            self.raw_opacity.val().into_primitive().tensor(),
+            self.render_sh_coeffs().into_primitive().tensor(),
+            raw_opacity.into_primitive().tensor(),
+            background,
+            self.mip_filter_2d,
             render_u32_buffer,
+            false,
         );
 
         (Tensor::from_primitive(TensorPrimitive::Float(img)), aux)
     }
 
+    /// Render RGBA together with the expected and median depth of each pixel, all differentiable.
+    ///
+    /// The expected depth is normalized by the alpha of the pixel. Pixels where the
+    /// transmittance never drops below 0.5 have a median depth of 0.
+    pub fn render_with_depth(
+        &self,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        background: glam::Vec3,
+    ) -> (
+        Tensor<B, 3>,
+        Tensor<B, 2>,
+        Tensor<B, 2>,
+        crate::RenderAux<B>,
+    ) {
+        let (log_scales, raw_opacity) = self.filtered_params();
+        let (img, aux) = B::render_splats(
+            camera,
+            img_size,
+            self.means.val().into_primitive().tensor(),
+            self.xys_dummy.clone().into_primitive().tensor(),
+            self.xys_abs_dummy.clone().into_primitive().tensor(),
+            self.camera_dummy.clone().into_primitive().tensor(),
+            log_scales.into_primitive().tensor(),
+            self.rotation.val().into_primitive().tensor(),
+            self.render_sh_coeffs().into_primitive().tensor(),
+            raw_opacity.into_primitive().tensor(),
+            background,
+            self.mip_filter_2d,
+            false,
+            true,
+        );
+        let img: Tensor<B, 3> = Tensor::from_primitive(TensorPrimitive::Float(img));
+        let [h, w, _] = img.dims();
+
+        let rgba = img.clone().slice([0..h, 0..w, 0..4]);
+        let alpha = img.clone().slice([0..h, 0..w, 3..4]).reshape([h, w]);
+        let depth_sum = img.clone().slice([0..h, 0..w, 4..5]).reshape([h, w]);
+        let median_depth = img.slice([0..h, 0..w, 5..6]).reshape([h, w]);
+        let expected_depth = depth_sum / alpha.clamp_min(1e-6);
+
+        (rgba, expected_depth, median_depth, aux)
+    }
+
     pub fn opacity(&self) -> Tensor<B, 1> {
         sigmoid(self.raw_opacity.val())
     }
@@ -0,0 +1,723 @@
+// Compares the gradients of render_splats against finite differences.
+//
+// The loss is a fixed random weighting of the rendered image, so every pixel and channel gets a
+// different gradient. The numeric gradient of each parameter element is found by central
+// differences, rendering with the inner (non autodiff) backend, and summing the loss in f64 to
+// keep the rounding noise well below the perturbation.
+//
+// The renderer is not smooth everywhere: pixels crossing the 1/255 alpha cutoff or splats moving
+// to another tile make the loss jump. So a few elements can always disagree, and the report sorts
+// the worst offenders first rather than giving a single pass/fail.
+use std::fmt;
+
+use burn::{
+    config::Config,
+    module::{AutodiffModule, Param},
+    tensor::{Tensor, TensorData},
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{camera::Camera, gaussian_splats::Splats, AutodiffBackend, Backend};
+
+#[derive(Config)]
+pub struct GradCheckConfig {
+    /// Size of the perturbation for the central differences.
+    #[config(default = 1e-3)]
+    pub epsilon: f32,
+    /// Absolute tolerance of a gradient element.
+    #[config(default = 1e-2)]
+    pub atol: f32,
+    /// Tolerance relative to the size of the numeric gradient.
+    #[config(default = 5e-2)]
+    pub rtol: f32,
+    /// Seed for the random weights of the loss.
+    #[config(default = 0)]
+    pub seed: u64,
+    /// Background color to render over.
+    #[config(default = "[0.0, 0.0, 0.0]")]
+    pub background: [f32; 3],
+}
+
+#[derive(Debug, Clone)]
+pub struct GradCheckEntry {
+    /// Name of the splat parameter, eg. "means".
+    pub param: &'static str,
+    /// Index of the splat.
+    pub splat: usize,
+    /// Index of the element within the splat, eg. 2 for the z coordinate of the mean.
+    pub element: usize,
+    pub analytic: f32,
+    pub numeric: f32,
+    /// The difference between the gradients, relative to the tolerance. Elements above 1 fail.
+    pub error: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct GradCheckReport {
+    pub entries: Vec<GradCheckEntry>,
+}
+
+impl GradCheckReport {
+    pub fn failures(&self) -> impl Iterator<Item = &GradCheckEntry> {
+        self.entries.iter().filter(|e| e.error > 1.0)
+    }
+
+    /// The n entries with the largest error, worst first.
+    pub fn worst(&self, n: usize) -> Vec<&GradCheckEntry> {
+        let mut entries: Vec<_> = self.entries.iter().collect();
+        entries.sort_by(|a, b| b.error.total_cmp(&a.error));
+        entries.truncate(n);
+        entries
+    }
+
+    /// Fraction of the elements of a parameter that are within tolerance.
+    pub fn pass_ratio(&self, param: &str) -> f32 {
+        let entries: Vec<_> = self.entries.iter().filter(|e| e.param == param).collect();
+        if entries.is_empty() {
+            return 1.0;
+        }
+        let passed = entries.iter().filter(|e| e.error <= 1.0).count();
+        passed as f32 / entries.len() as f32
+    }
+
+    pub fn params(&self) -> Vec<&'static str> {
+        let mut params: Vec<_> = self.entries.iter().map(|e| e.param).collect();
+        params.dedup();
+        params
+    }
+}
+
+impl fmt::Display for GradCheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for param in self.params() {
+            writeln!(
+                f,
+                "{param}: {:.1}% within tolerance",
+                self.pass_ratio(param) * 100.0
+            )?;
+        }
+        writeln!(f, "Worst offenders:")?;
+        for e in self.worst(10) {
+            writeln!(
+                f,
+                "  {}[{}][{}]: analytic {:.6}, numeric {:.6}, error {:.2}",
+                e.param, e.splat, e.element, e.analytic, e.numeric, e.error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct ParamValues {
+    name: &'static str,
+    values: Vec<f32>,
+    shape: Vec<usize>,
+}
+
+async fn tensor_values<B: burn::prelude::Backend, const D: usize>(
+    tensor: Tensor<B, D>,
+) -> Vec<f32> {
+    tensor.into_data_async().await.iter::<f32>().collect()
+}
+
+async fn grad_values<B: AutodiffBackend, const D: usize>(
+    param: &Param<Tensor<B, D>>,
+    grads: &B::Gradients,
+) -> Vec<f32> {
+    // Parameters that don't influence the image have no gradient at all.
+    let grad = param
+        .grad(grads)
+        .unwrap_or_else(|| Tensor::zeros(param.dims(), &param.device()));
+    tensor_values(grad).await
+}
+
+async fn param_values<B: burn::prelude::Backend, const D: usize>(
+    name: &'static str,
+    tensor: Tensor<B, D>,
+) -> ParamValues {
+    ParamValues {
+        name,
+        shape: tensor.dims().to_vec(),
+        values: tensor_values(tensor).await,
+    }
+}
+
+fn to_tensor<B: burn::prelude::Backend, const D: usize>(
+    param: &ParamValues,
+    device: &B::Device,
+) -> Tensor<B, D> {
+    Tensor::from_data(
+        TensorData::new(param.values.clone(), param.shape.clone()),
+        device,
+    )
+}
+
+async fn weighted_loss<B: Backend>(
+    params: &[ParamValues],
+    filters: &Splats<B>,
+    camera: &Camera,
+    img_size: glam::UVec2,
+    background: glam::Vec3,
+    weights: &[f32],
+    device: &B::Device,
+) -> f64 {
+    let mut splats = Splats::<B>::from_tensor_data(
+        to_tensor(&params[0], device),
+        to_tensor(&params[1], device),
+        to_tensor(&params[2], device),
+        to_tensor(&params[3], device),
+        to_tensor(&params[4], device),
+    );
+    splats.filter_3d = filters.filter_3d.clone();
+    splats.mip_filter_2d = filters.mip_filter_2d;
+    let (img, _) = splats.render(camera, img_size, background, false);
+    let img = tensor_values(img).await;
+    img.iter()
+        .zip(weights)
+        .map(|(&x, &w)| x as f64 * w as f64)
+        .sum()
+}
+
+/// Check the gradients of all splat parameters for a render from the given camera.
+///
+/// This renders the image twice for every parameter element, so it's only meant for small scenes.
+/// The xy gradients aren't checked, as the xy dummy doesn't influence the forward pass.
+pub async fn check_gradients<B: AutodiffBackend>(
+    splats: &Splats<B>,
+    camera: &Camera,
+    img_size: glam::UVec2,
+    config: &GradCheckConfig,
+) -> GradCheckReport
+where
+    B::InnerBackend: Backend,
+{
+    let device = splats.means.device();
+    let background = glam::Vec3::from(config.background);
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let num_weights = (img_size.x * img_size.y * 4) as usize;
+    let weights: Vec<f32> = (0..num_weights).map(|_| rng.gen_range(-1.0..1.0)).collect();
+    let weights_tensor = Tensor::<B, 3>::from_data(
+        TensorData::new(
+            weights.clone(),
+            [img_size.y as usize, img_size.x as usize, 4],
+        ),
+        &device,
+    );
+
+    // Analytic gradients.
+    let (img, _) = splats.render(camera, img_size, background, false);
+    let grads = (img * weights_tensor).sum().backward();
+
+    let analytic = [
+        grad_values(&splats.means, &grads).await,
+        grad_values(&splats.rotation, &grads).await,
+        grad_values(&splats.log_scales, &grads).await,
+        grad_values(&splats.sh_coeffs, &grads).await,
+        grad_values(&splats.raw_opacity, &grads).await,
+    ];
+
+    // Numeric gradients.
+    let inner = splats.valid();
+    let mut params = vec![
+        param_values("means", inner.means.val()).await,
+        param_values("rotation", inner.rotation.val()).await,
+        param_values("log_scales", inner.log_scales.val()).await,
+        param_values("sh_coeffs", inner.sh_coeffs.val()).await,
+        param_values("raw_opacity", inner.raw_opacity.val()).await,
+    ];
+
+    let num_splats = splats.num_splats();
+    let mut entries = vec![];
+
+    for (p, analytic) in analytic.iter().enumerate() {
+        let per_splat = params[p].values.len() / num_splats.max(1);
+
+        for (i, &analytic) in analytic.iter().enumerate() {
+            let orig = params[p].values[i];
+            let (pos, neg) = (orig + config.epsilon, orig - config.epsilon);
+
+            params[p].values[i] = pos;
+            let loss_pos = weighted_loss::<B::InnerBackend>(
+                &params, &inner, camera, img_size, background, &weights, &device,
+            )
+            .await;
+            params[p].values[i] = neg;
+            let loss_neg = weighted_loss::<B::InnerBackend>(
+                &params, &inner, camera, img_size, background, &weights, &device,
+            )
+            .await;
+            params[p].values[i] = orig;
+
+            // Divide by the actual step, which isn't exactly 2 * epsilon in f32.
+            let numeric = ((loss_pos - loss_neg) / (pos as f64 - neg as f64)) as f32;
+            let error = (analytic - numeric).abs() / (config.atol + config.rtol * numeric.abs());
+
+            entries.push(GradCheckEntry {
+                param: params[p].name,
+                splat: i / per_splat,
+                element: i % per_splat,
+                analytic,
+                numeric,
+                error,
+            });
+        }
+    }
+
+    GradCheckReport { entries }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Projection;
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+    use burn::tensor::Distribution;
+    use burn_wgpu::{Wgpu, WgpuDevice};
+    use glam::{Quat, Vec3};
+
+    type DiffBack = Autodiff<NdArray>;
+    // The wgsl kernels that are used for training.
+    type GpuBack = Autodiff<Wgpu>;
+
+    const IMG_SIZE: glam::UVec2 = glam::uvec2(32, 32);
+
+    fn camera() -> Camera {
+        Camera::new(
+            Vec3::ZERO,
+            Quat::IDENTITY,
+            std::f64::consts::FRAC_PI_2,
+            std::f64::consts::FRAC_PI_2,
+            glam::vec2(0.5, 0.5),
+        )
+    }
+
+    fn random_splats<B: Backend>(
+        num_splats: usize,
+        log_scale: impl Fn(&mut StdRng) -> Vec3,
+        sh_degree: u32,
+        rng: &mut StdRng,
+        device: &B::Device,
+    ) -> Splats<B> {
+        let means = (0..num_splats)
+            .map(|_| {
+                glam::vec3(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(3.0..5.0),
+                )
+            })
+            .collect();
+        let rotations = (0..num_splats)
+            .map(|_| {
+                Quat::from_xyzw(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                )
+                .normalize()
+            })
+            .collect();
+        let log_scales = (0..num_splats).map(|_| log_scale(rng)).collect();
+        let num_coeffs = crate::render::sh_coeffs_for_degree(sh_degree) as usize;
+        let sh_coeffs = (0..num_splats * num_coeffs * 3)
+            .map(|_| rng.gen_range(-0.5..0.5))
+            .collect();
+        // Keep the opacity away from 1, where the alpha of a pixel gets clamped.
+        let raw_opacities = (0..num_splats).map(|_| rng.gen_range(-2.0..0.5)).collect();
+
+        Splats::from_raw(
+            means,
+            Some(rotations),
+            Some(log_scales),
+            Some(sh_coeffs),
+            Some(raw_opacities),
+            device,
+        )
+    }
+
+    fn regular_scale(rng: &mut StdRng) -> Vec3 {
+        Vec3::splat(rng.gen_range(-2.5..-1.5))
+    }
+
+    /// Multiply the means of the splats by a factor per splat, eg. to move them around the camera.
+    fn scale_means<B: Backend>(splats: &mut Splats<B>, factors: &[[f32; 3]]) {
+        let device = splats.means.device();
+        let factors = Tensor::<B, 2>::from_data(
+            TensorData::new(factors.concat(), [factors.len(), 3]),
+            &device,
+        );
+        Splats::map_param(&mut splats.means, |m| m * factors.clone());
+    }
+
+    async fn assert_grads_match<B: AutodiffBackend>(
+        splats: &Splats<B>,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        config: &GradCheckConfig,
+    ) -> GradCheckReport
+    where
+        B::InnerBackend: Backend,
+    {
+        let report = check_gradients(splats, camera, img_size, config).await;
+        for param in report.params() {
+            // A single element can cross a discontinuity of the renderer, anything more is a bug.
+            let failures = report.failures().filter(|e| e.param == param).count();
+            assert!(failures <= 1, "Gradients don't match:\n{report}");
+        }
+        report
+    }
+
+    /// Check the gradients of the same splats on the CPU and with the wgsl kernels against finite
+    /// differences, and check the analytic gradients of both against each other.
+    async fn assert_backends_match(
+        setup: impl Fn(&mut StdRng) -> (Splats<DiffBack>, Splats<GpuBack>),
+        seed: u64,
+        camera: &Camera,
+        img_size: glam::UVec2,
+        config: &GradCheckConfig,
+    ) {
+        let (cpu, gpu) = setup(&mut StdRng::seed_from_u64(seed));
+        let cpu_report = assert_grads_match(&cpu, camera, img_size, config).await;
+        let gpu_report = assert_grads_match(&gpu, camera, img_size, config).await;
+
+        // Both are analytic, so these should only differ by the order floats are summed in.
+        for (c, g) in cpu_report.entries.iter().zip(&gpu_report.entries) {
+            assert!(
+                (c.analytic - g.analytic).abs() <= 1e-4 + 1e-3 * c.analytic.abs(),
+                "{}[{}][{}]: cpu {}, wgpu {}",
+                c.param,
+                c.splat,
+                c.element,
+                c.analytic,
+                g.analytic
+            );
+        }
+    }
+
+    /// The same random splats on both backends.
+    fn both_backends(
+        num_splats: usize,
+        log_scale: impl Fn(&mut StdRng) -> Vec3 + Copy,
+        sh_degree: u32,
+    ) -> impl Fn(&mut StdRng) -> (Splats<DiffBack>, Splats<GpuBack>) {
+        move |rng| {
+            let gpu_rng = &mut rng.clone();
+            (
+                random_splats(num_splats, log_scale, sh_degree, rng, &NdArrayDevice::Cpu),
+                random_splats(
+                    num_splats,
+                    log_scale,
+                    sh_degree,
+                    gpu_rng,
+                    &WgpuDevice::DefaultDevice,
+                ),
+            )
+        }
+    }
+
+    #[tokio::test]
+    async fn grads_regular_splats() {
+        assert_backends_match(
+            both_backends(6, regular_scale, 0),
+            0,
+            &camera(),
+            IMG_SIZE,
+            &GradCheckConfig::new(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn grads_tiny_splats() {
+        assert_backends_match(
+            both_backends(6, |_| Vec3::splat(-7.0), 0),
+            1,
+            &camera(),
+            IMG_SIZE,
+            &GradCheckConfig::new(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn grads_anisotropic_splats() {
+        let anisotropic = |rng: &mut StdRng| {
+            glam::vec3(rng.gen_range(-1.5..-1.0), -4.0, rng.gen_range(-3.0..-2.5))
+        };
+        assert_backends_match(
+            both_backends(6, anisotropic, 0),
+            2,
+            &camera(),
+            IMG_SIZE,
+            &GradCheckConfig::new(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn grads_high_sh_degree() {
+        assert_backends_match(
+            both_backends(3, regular_scale, 3),
+            3,
+            &camera(),
+            IMG_SIZE,
+            &GradCheckConfig::new(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn grads_behind_camera() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let mut splats: Splats<DiffBack> =
+            random_splats(6, regular_scale, 0, &mut rng, &NdArrayDevice::Cpu);
+        // Mirror half of the splats behind the camera.
+        scale_means(
+            &mut splats,
+            &[
+                [1.0, 1.0, -1.0],
+                [1.0, 1.0, 1.0],
+                [1.0, 1.0, -1.0],
+                [1.0, 1.0, 1.0],
+                [1.0, 1.0, -1.0],
+                [1.0, 1.0, 1.0],
+            ],
+        );
+
+        let report =
+            assert_grads_match(&splats, &camera(), IMG_SIZE, &GradCheckConfig::new()).await;
+        for e in report.entries.iter().filter(|e| e.splat % 2 == 0) {
+            assert_eq!(e.analytic, 0.0, "Splat behind the camera has a gradient");
+            assert_eq!(e.numeric, 0.0, "Splat behind the camera changes the image");
+        }
+    }
+
+    #[tokio::test]
+    async fn grads_background() {
+        let config = GradCheckConfig::new().with_background([0.2, 0.5, 0.8]);
+        assert_backends_match(
+            both_backends(6, regular_scale, 0),
+            8,
+            &camera(),
+            IMG_SIZE,
+            &config,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn grads_mip_filter_2d() {
+        // Include tiny splats, where the compensation is far from 1.
+        let setup = |rng: &mut StdRng| {
+            let (mut cpu, mut gpu) =
+                both_backends(6, |rng| Vec3::splat(rng.gen_range(-6.0..-2.0)), 0)(rng);
+            cpu.mip_filter_2d = true;
+            gpu.mip_filter_2d = true;
+            (cpu, gpu)
+        };
+        assert_backends_match(setup, 9, &camera(), IMG_SIZE, &GradCheckConfig::new()).await;
+    }
+
+    #[tokio::test]
+    async fn grads_mip_filter_3d() {
+        // A filter with a similar size as the splats.
+        let rates = [20.0, 10.0, 5.0, 20.0, 10.0, 5.0];
+        let setup = |rng: &mut StdRng| {
+            let (cpu, gpu) = both_backends(6, |rng| Vec3::splat(rng.gen_range(-3.5..-1.5)), 0)(rng);
+            (
+                cpu.with_filter_3d(Tensor::from_floats(rates, &NdArrayDevice::Cpu)),
+                gpu.with_filter_3d(Tensor::from_floats(rates, &WgpuDevice::DefaultDevice)),
+            )
+        };
+        assert_backends_match(setup, 10, &camera(), IMG_SIZE, &GradCheckConfig::new()).await;
+    }
+
+    #[tokio::test]
+    async fn grads_fisheye() {
+        // Move the splats closer, to wide angles where the fisheye differs most from a pinhole.
+        let setup = |rng: &mut StdRng| {
+            let (mut cpu, mut gpu) = both_backends(6, regular_scale, 0)(rng);
+            scale_means(&mut cpu, &[[1.0, 1.0, 0.25]; 6]);
+            scale_means(&mut gpu, &[[1.0, 1.0, 0.25]; 6]);
+            (cpu, gpu)
+        };
+        let camera = Camera::new(Vec3::ZERO, Quat::IDENTITY, 3.5, 3.5, glam::vec2(0.5, 0.5))
+            .with_projection(Projection::Fisheye);
+        assert_backends_match(setup, 5, &camera, IMG_SIZE, &GradCheckConfig::new()).await;
+    }
+
+    #[tokio::test]
+    async fn grads_equirectangular() {
+        // Spread the splats around the camera, including to the sides and behind it.
+        let spread = [
+            [1.0, 1.0, 1.0],
+            [3.0, 1.0, 0.2],
+            [1.0, 1.0, -1.0],
+            [-3.0, 1.0, 0.2],
+            [1.0, 1.0, -1.0],
+            [1.0, 1.0, 1.0],
+        ];
+        let setup = |rng: &mut StdRng| {
+            let (mut cpu, mut gpu) =
+                both_backends(6, |rng| Vec3::splat(rng.gen_range(-1.5..-1.0)), 0)(rng);
+            scale_means(&mut cpu, &spread);
+            scale_means(&mut gpu, &spread);
+            (cpu, gpu)
+        };
+        let camera = Camera::new(
+            Vec3::ZERO,
+            Quat::IDENTITY,
+            std::f64::consts::TAU,
+            std::f64::consts::PI,
+            glam::vec2(0.5, 0.5),
+        )
+        .with_projection(Projection::Equirectangular);
+        assert_backends_match(
+            setup,
+            6,
+            &camera,
+            glam::uvec2(64, 32),
+            &GradCheckConfig::new(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn grads_orthographic() {
+        // A 3x3 view, so the splats cover a similar area as with the perspective camera.
+        let camera = Camera::new(Vec3::ZERO, Quat::IDENTITY, 0.0, 0.0, glam::vec2(0.5, 0.5))
+            .with_projection(Projection::Orthographic {
+                extent: glam::dvec2(3.0, 3.0),
+            });
+        assert_backends_match(
+            both_backends(6, regular_scale, 0),
+            7,
+            &camera,
+            IMG_SIZE,
+            &GradCheckConfig::new(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn abs_grads_bound_xy_grads() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut splats: Splats<DiffBack> =
+            random_splats(6, regular_scale, 0, &mut rng, &NdArrayDevice::Cpu);
+        splats.xys_abs_dummy = splats.xys_abs_dummy.require_grad();
+
+        let (img, _) = splats.render(&camera(), IMG_SIZE, Vec3::ZERO, false);
+        let weights = Tensor::random(img.dims(), Distribution::Uniform(-1.0, 1.0), &img.device());
+        let grads = (img * weights).sum().backward();
+
+        let v_xy = splats.xys_dummy.grad(&grads).expect("no xy grad");
+        let v_xy_abs = splats.xys_abs_dummy.grad(&grads).expect("no abs xy grad");
+        let v_xy: Vec<f32> = v_xy.into_data().to_vec().unwrap();
+        let v_xy_abs: Vec<f32> = v_xy_abs.into_data().to_vec().unwrap();
+
+        // Summing the absolute values of the pixels can't be smaller than the absolute sum.
+        for (v, v_abs) in v_xy.iter().zip(&v_xy_abs) {
+            assert!(*v_abs >= v.abs() - 1e-5, "{v_abs} < |{v}|");
+        }
+        assert!(v_xy_abs.iter().any(|&v| v > 0.0));
+    }
+
+    async fn camera_loss<B: Backend>(splats: &Splats<B>, camera: &Camera, weights: &[f32]) -> f64 {
+        let (img, _) = splats.render(camera, IMG_SIZE, Vec3::ZERO, false);
+        let img = tensor_values(img).await;
+        img.iter()
+            .zip(weights)
+            .map(|(&x, &w)| x as f64 * w as f64)
+            .sum()
+    }
+
+    /// Check the camera gradient against finite differences, and return it.
+    async fn assert_camera_grads_match<B: AutodiffBackend>(
+        splats: Splats<B>,
+        weights: &[f32],
+    ) -> Vec<f32>
+    where
+        B::InnerBackend: Backend,
+    {
+        let mut splats = splats;
+        splats.camera_dummy = splats.camera_dummy.require_grad();
+        let device = splats.means.device();
+
+        let camera = camera();
+        let weights_tensor = Tensor::<B, 3>::from_data(
+            TensorData::new(
+                weights.to_vec(),
+                [IMG_SIZE.y as usize, IMG_SIZE.x as usize, 4],
+            ),
+            &device,
+        );
+
+        let (img, _) = splats.render(&camera, IMG_SIZE, Vec3::ZERO, false);
+        let grads = (img * weights_tensor).sum().backward();
+        let v_camera =
+            tensor_values(splats.camera_dummy.grad(&grads).expect("no camera grad")).await;
+
+        let inner = splats.valid();
+        let config = GradCheckConfig::new();
+        let eps = config.epsilon;
+
+        let mut checks = vec![];
+        for i in 0..6 {
+            let mut delta = [0.0; 6];
+            delta[i] = eps;
+            let pos = Vec3::from_slice(&delta[0..3]);
+            let trans = Vec3::from_slice(&delta[3..6]);
+            let loss_pos = camera_loss(&inner, &camera.perturbed(pos, trans), weights).await;
+            let loss_neg = camera_loss(&inner, &camera.perturbed(-pos, -trans), weights).await;
+            checks.push((
+                v_camera[i],
+                ((loss_pos - loss_neg) / (2.0 * eps as f64)) as f32,
+            ));
+        }
+
+        // Scaling the focal length by s changes both focal lengths by a factor s.
+        let focal = camera.focal(IMG_SIZE);
+        let loss_pos = camera_loss(
+            &inner,
+            &camera.clone().with_focal_scale(1.0 + eps as f64),
+            weights,
+        )
+        .await;
+        let loss_neg = camera_loss(
+            &inner,
+            &camera.clone().with_focal_scale(1.0 - eps as f64),
+            weights,
+        )
+        .await;
+        checks.push((
+            v_camera[6] * focal.x + v_camera[7] * focal.y,
+            ((loss_pos - loss_neg) / (2.0 * eps as f64)) as f32,
+        ));
+
+        for (i, (analytic, numeric)) in checks.into_iter().enumerate() {
+            let error = (analytic - numeric).abs() / (config.atol + config.rtol * numeric.abs());
+            assert!(
+                error <= 1.0,
+                "Camera gradient {i} doesn't match: analytic {analytic}, numeric {numeric}"
+            );
+        }
+        v_camera
+    }
+
+    #[tokio::test]
+    async fn camera_grads_match() {
+        let mut rng = StdRng::seed_from_u64(12);
+        // View dependent colour isn't part of the camera gradient, so stick to degree 0.
+        let (cpu, gpu) = both_backends(6, regular_scale, 0)(&mut rng);
+        let num_weights = (IMG_SIZE.x * IMG_SIZE.y * 4) as usize;
+        let weights: Vec<f32> = (0..num_weights).map(|_| rng.gen_range(-1.0..1.0)).collect();
+
+        let cpu_grads = assert_camera_grads_match(cpu, &weights).await;
+        let gpu_grads = assert_camera_grads_match(gpu, &weights).await;
+        for (c, g) in cpu_grads.iter().zip(&gpu_grads) {
+            assert!((c - g).abs() <= 1e-4 + 1e-3 * c.abs(), "cpu {c}, wgpu {g}");
+        }
+    }
+}
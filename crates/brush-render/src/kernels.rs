@@ -6,10 +6,10 @@ use crate::shaders::gather_grads;
 use brush_kernel::kernel_source_gen;
 
 kernel_source_gen!(ProjectSplats {}, project_forward);
-kernel_source_gen!(ProjectVisible {}, project_visible);
+kernel_source_gen!(ProjectVisible { mip_filter }, project_visible);
 kernel_source_gen!(MapGaussiansToIntersect {}, map_gaussian_to_intersects);
 kernel_source_gen!(GetTileBinEdges {}, get_tile_bin_edges);
-kernel_source_gen!(Rasterize { raster_u32 }, rasterize);
-kernel_source_gen!(RasterizeBackwards { hard_float }, rasterize_backwards);
-kernel_source_gen!(GatherGrads {}, gather_grads);
-kernel_source_gen!(ProjectBackwards {}, project_backwards);
+kernel_source_gen!(Rasterize { raster_u32, render_depth }, rasterize);
+kernel_source_gen!(RasterizeBackwards { hard_float, render_depth, abs_grad }, rasterize_backwards);
+kernel_source_gen!(GatherGrads { abs_grad }, gather_grads);
+kernel_source_gen!(ProjectBackwards { mip_filter, camera_grad }, project_backwards);
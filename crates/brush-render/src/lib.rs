@@ -7,15 +7,18 @@ use burn_wgpu::WgpuRuntime;
 use camera::Camera;
 
 mod burn_glue;
+#[cfg(any(test, feature = "cpu"))]
+mod cpu;
 mod dim_check;
 mod kernels;
-mod safetensor_utils;
 mod shaders;
 
 pub mod bounding_box;
 pub mod camera;
 pub mod gaussian_splats;
+pub mod grad_check;
 pub mod render;
+pub mod safetensor_utils;
 
 #[derive(Debug, Clone)]
 pub struct RenderAux<B: Backend> {
@@ -25,6 +28,8 @@ pub struct RenderAux<B: Backend> {
     pub num_intersections: B::IntTensorPrimitive,
     pub num_visible: B::IntTensorPrimitive,
     pub final_index: B::IntTensorPrimitive,
+    /// The intersection the median depth of each pixel comes from, when rendering depth.
+    pub median_index: B::IntTensorPrimitive,
     pub cum_tiles_hit: B::IntTensorPrimitive,
     pub tile_bins: B::IntTensorPrimitive,
     pub compact_gid_from_isect: B::IntTensorPrimitive,
@@ -68,6 +73,8 @@ pub struct SplatGrads<B: Backend> {
     v_coeffs: B::FloatTensorPrimitive,
     v_raw_opac: B::FloatTensorPrimitive,
     v_xy: B::FloatTensorPrimitive,
+    v_xy_abs: B::FloatTensorPrimitive,
+    v_camera: B::FloatTensorPrimitive,
 }
 
 #[derive(Debug, Clone)]
@@ -78,6 +85,11 @@ pub struct GaussianBackwardState<B: Backend> {
     raw_opac: B::FloatTensorPrimitive,
     out_img: B::FloatTensorPrimitive,
     sh_degree: u32,
+    mip_filter: bool,
+    // Whether the absolute xy gradients are needed, which is only known when going backwards.
+    abs_grad: bool,
+    // Whether the camera gradient is needed, which is only known when going backwards.
+    camera_grad: bool,
     aux: RenderAux<B>,
 }
 
@@ -89,18 +101,37 @@ pub trait Backend: burn::tensor::backend::Backend {
     /// differentiable way.
     /// The arguments are all passed as raw tensors. See [`Splats`] for a convenient Module that wraps this fun
     /// The ['xy_dummy'] variable is only used to carry screenspace xy gradients.
+    /// The ['xy_abs_dummy'] variable carries the screenspace xy gradients with the absolute
+    /// value of each pixel summed instead, as used by AbsGS. These are only calculated when
+    /// this dummy requires a gradient.
+    /// The ['camera_dummy'] variable is an [8] tensor which carries the gradient of the camera,
+    /// when it requires a gradient. The first 3 elements are the gradient of a rotation of the
+    /// camera (as a scaled axis) and the next 3 of a translation, both applied in camera space,
+    /// see [`Camera::perturbed`]. The last 2 are the gradient of the focal length in pixels.
+    /// The view dependent color of the splats is not included.
     /// This function can optionally render a "u32" buffer, which is a packed RGBA (8 bits per channel)
     /// buffer. This is useful when the results need to be displayed immediatly.
+    /// With render_depth, the image has two more channels after RGBA: the alpha weighted sum of
+    /// the splat depths, and the depth where the transmittance drops below 0.5 (or 0 if it never does).
+    /// Both are differentiable. Depth can't be rendered to a u32 buffer.
+    /// The colors are composited over the background, which doesn't change the alpha channel.
+    /// With mip_filter, the opacity of each splat is scaled down to make up for the screenspace
+    /// blur added to it, which is the 2D filter of Mip-Splatting.
     fn render_splats(
         cam: &Camera,
         img_size: glam::UVec2,
         means: Self::FloatTensorPrimitive,
         xy_grad_dummy: Self::FloatTensorPrimitive,
+        xy_abs_grad_dummy: Self::FloatTensorPrimitive,
+        camera_dummy: Self::FloatTensorPrimitive,
         log_scales: Self::FloatTensorPrimitive,
         quats: Self::FloatTensorPrimitive,
         sh_coeffs: Self::FloatTensorPrimitive,
         raw_opacity: Self::FloatTensorPrimitive,
+        background: glam::Vec3,
+        mip_filter: bool,
         render_u32_buffer: bool,
+        render_depth: bool,
     ) -> (Self::FloatTensorPrimitive, RenderAux<Self>);
 
     /// Backward pass for render_splats.
@@ -57,6 +57,16 @@ pub(crate) fn calc_tile_bounds(img_size: glam::UVec2) -> glam::UVec2 {
     )
 }
 
+/// Number of channels of a rendered image. RGBA, followed by the expected and median depth
+/// when rendering depth.
+pub(crate) fn render_channels(render_depth: bool) -> usize {
+    if render_depth {
+        6
+    } else {
+        4
+    }
+}
+
 pub(crate) fn max_intersections(img_size: glam::UVec2, num_splats: u32) -> u32 {
     // Divide screen into tiles.
     let tile_bounds = calc_tile_bounds(img_size);
@@ -82,12 +92,19 @@ pub(crate) fn render_forward(
     quats: JitTensor<WgpuRuntime>,
     sh_coeffs: JitTensor<WgpuRuntime>,
     raw_opacities: JitTensor<WgpuRuntime>,
+    background: glam::Vec3,
+    mip_filter: bool,
     raster_u32: bool,
+    render_depth: bool,
 ) -> (JitTensor<WgpuRuntime>, RenderAux<InnerWgpu>) {
     assert!(
         img_size[0] > 0 && img_size[1] > 0,
         "Can't render 0 sized images"
     );
+    assert!(
+        !(raster_u32 && render_depth),
+        "Can't render depth to a packed u32 buffer"
+    );
 
     let device = &means.device.clone();
     let client = means.client.clone();
@@ -123,6 +140,10 @@ pub(crate) fn render_forward(
     // Then, various buffers map between these, which are named x_from_y_gid, eg.
     //  global_from_compact_gid.
 
+    // The kernels index the coefficients directly, but they can be a slice without the higher
+    // bands, see `Splats::render_sh_coeffs`.
+    let sh_coeffs = burn_jit::kernel::into_contiguous(sh_coeffs);
+
     // Tile rendering setup.
     let sh_degree = sh_degree_from_coeffs(sh_coeffs.shape.dims[1] as u32);
     let total_splats = means.shape.dims[0] as u32;
@@ -131,6 +152,7 @@ pub(crate) fn render_forward(
         shaders::helpers::RenderUniforms {
             viewmat: camera.world_to_local().to_cols_array_2d(),
             camera_position: [camera.position.x, camera.position.y, camera.position.z, 0.0],
+            background: background.extend(0.0).to_array(),
             focal: camera.focal(img_size).into(),
             pixel_center: camera.center(img_size).into(),
             img_size: img_size.into(),
@@ -138,7 +160,7 @@ pub(crate) fn render_forward(
             num_visible: 0,
             sh_degree,
             total_splats,
-            padding: 0,
+            projection: camera.projection.shader_id(),
         },
         device,
         &client,
@@ -197,7 +219,7 @@ pub(crate) fn render_forward(
 
     tracing::trace_span!("ProjectVisibile", sync_burn = true).in_scope(|| unsafe {
         client.execute_unchecked(
-            ProjectVisible::task(),
+            ProjectVisible::task(mip_filter),
             CubeCount::Dynamic(num_vis_wg.clone().handle.binding()),
             vec![
                 uniforms_buffer.clone().handle.binding(),
@@ -293,7 +315,7 @@ pub(crate) fn render_forward(
         // Channels are packed into 4 bytes aka one float.
         1
     } else {
-        4
+        render_channels(render_depth)
     };
 
     let out_img = create_tensor(
@@ -324,13 +346,24 @@ pub(crate) fn render_forward(
         DType::I32,
     );
 
+    // Record which splat the median depth comes from, so the backward pass can find it.
+    let median_index = create_tensor::<2, _>(
+        [img_size.y as usize, img_size.x as usize],
+        device,
+        client,
+        DType::I32,
+    );
+
     if !raster_u32 {
         handles.push(final_index.handle.clone().binding());
+        if render_depth {
+            handles.push(median_index.handle.clone().binding());
+        }
     }
 
     unsafe {
         client.execute_unchecked(
-            Rasterize::task(raster_u32),
+            Rasterize::task(raster_u32, render_depth),
             calc_cube_count([img_size.x, img_size.y], Rasterize::WORKGROUP_SIZE),
             handles,
         );
@@ -346,6 +379,7 @@ pub(crate) fn render_forward(
             cum_tiles_hit,
             projected_splats,
             final_index,
+            median_index,
             compact_gid_from_isect,
             global_from_compact_gid,
         },
@@ -367,18 +401,23 @@ pub(crate) fn render_backward(
     global_from_compact_gid: JitTensor<WgpuRuntime>,
     tile_bins: JitTensor<WgpuRuntime>,
     final_index: JitTensor<WgpuRuntime>,
+    median_index: JitTensor<WgpuRuntime>,
 
     sh_degree: u32,
+    mip_filter: bool,
+    abs_grad: bool,
+    camera_grad: bool,
 ) -> SplatGrads<InnerWgpu> {
     let device = &out_img.device;
     let img_dimgs = out_img.shape.dims;
     let img_size = glam::uvec2(img_dimgs[1] as u32, img_dimgs[0] as u32);
+    let render_depth = img_dimgs[2] == render_channels(true);
 
     let num_points = means.shape.dims[0];
 
     let client = &means.client;
 
-    let (v_xys_local, v_xys_global, v_conics, v_coeffs, v_raw_opac) = {
+    let (v_xys_local, v_xys_global, v_xys_abs, v_conics, v_colors, v_depths, v_coeffs, v_raw_opac) = {
         let tile_bounds = uvec2(
             img_size.x.div_ceil(shaders::helpers::TILE_WIDTH),
             img_size.y.div_ceil(shaders::helpers::TILE_WIDTH),
@@ -390,6 +429,8 @@ pub(crate) fn render_backward(
         let v_xys_local = InnerWgpu::float_zeros([num_points, 2].into(), device);
         let v_conics = InnerWgpu::float_zeros([num_points, 3].into(), device);
         let v_colors = InnerWgpu::float_zeros([num_points, 4].into(), device);
+        let v_depths = InnerWgpu::float_zeros([num_points].into(), device);
+        let v_xys_abs_local = InnerWgpu::float_zeros([num_points, 2].into(), device);
 
         // TODO: Properly register hardware atomic floats as a cube feature when
         // https://github.com/gfx-rs/wgpu/pull/6234 lands.
@@ -397,22 +438,29 @@ pub(crate) fn render_backward(
         // On mac, this is needed as our wgpu version doesn't support CAS on metal yet...
         let hard_floats = cfg!(target_os = "macos");
 
+        let mut bindings = vec![
+            uniforms_buffer.clone().handle.binding(),
+            compact_gid_from_isect.handle.binding(),
+            tile_bins.handle.binding(),
+            projected_splats.handle.binding(),
+            final_index.handle.binding(),
+            median_index.handle.binding(),
+            out_img.handle.binding(),
+            v_output.handle.binding(),
+            v_xys_local.clone().handle.binding(),
+            v_conics.clone().handle.binding(),
+            v_colors.clone().handle.binding(),
+            v_depths.clone().handle.binding(),
+        ];
+        if abs_grad {
+            bindings.push(v_xys_abs_local.clone().handle.binding());
+        }
+
         tracing::trace_span!("RasterizeBackwards", sync_burn = true).in_scope(|| unsafe {
             client.execute_unchecked(
-                RasterizeBackwards::task(hard_floats),
+                RasterizeBackwards::task(hard_floats, render_depth, abs_grad),
                 CubeCount::Static(invocations, 1, 1),
-                vec![
-                    uniforms_buffer.clone().handle.binding(),
-                    compact_gid_from_isect.handle.binding(),
-                    tile_bins.handle.binding(),
-                    projected_splats.handle.binding(),
-                    final_index.handle.binding(),
-                    out_img.handle.binding(),
-                    v_output.handle.binding(),
-                    v_xys_local.clone().handle.binding(),
-                    v_conics.clone().handle.binding(),
-                    v_colors.clone().handle.binding(),
-                ],
+                bindings,
             );
         });
 
@@ -425,25 +473,44 @@ pub(crate) fn render_backward(
         let num_vis_wg = create_dispatch_buffer(num_visible.clone(), GatherGrads::WORKGROUP_SIZE);
 
         let v_xys_global = InnerWgpu::float_zeros([num_points, 2].into(), device);
+        let v_xys_abs = InnerWgpu::float_zeros([num_points, 2].into(), device);
+
+        let mut bindings = vec![
+            uniforms_buffer.clone().handle.binding(),
+            global_from_compact_gid.clone().handle.binding(),
+            raw_opac.clone().handle.binding(),
+            means.clone().handle.binding(),
+            v_colors.clone().handle.binding(),
+            v_xys_local.clone().handle.binding(),
+            v_coeffs.handle.clone().binding(),
+            v_opacities.handle.clone().binding(),
+            v_xys_global.handle.clone().binding(),
+        ];
+        if abs_grad {
+            bindings.extend([
+                v_xys_abs_local.handle.binding(),
+                v_xys_abs.handle.clone().binding(),
+            ]);
+        }
+
         unsafe {
             client.execute_unchecked(
-                GatherGrads::task(),
+                GatherGrads::task(abs_grad),
                 CubeCount::Dynamic(num_vis_wg.handle.binding()),
-                vec![
-                    uniforms_buffer.clone().handle.binding(),
-                    global_from_compact_gid.clone().handle.binding(),
-                    raw_opac.clone().handle.binding(),
-                    means.clone().handle.binding(),
-                    v_colors.clone().handle.binding(),
-                    v_xys_local.clone().handle.binding(),
-                    v_coeffs.handle.clone().binding(),
-                    v_opacities.handle.clone().binding(),
-                    v_xys_global.handle.clone().binding(),
-                ],
+                bindings,
             );
         }
 
-        (v_xys_local, v_xys_global, v_conics, v_coeffs, v_opacities)
+        (
+            v_xys_local,
+            v_xys_global,
+            v_xys_abs,
+            v_conics,
+            v_colors,
+            v_depths,
+            v_coeffs,
+            v_opacities,
+        )
     };
 
     // Create tensors to hold gradients.
@@ -454,25 +521,51 @@ pub(crate) fn render_backward(
     let v_scales = InnerWgpu::float_zeros([num_points, 3].into(), device);
     let v_quats = InnerWgpu::float_zeros([num_points, 4].into(), device);
 
+    let mut bindings = vec![
+        uniforms_buffer.handle.binding(),
+        means.handle.binding(),
+        log_scales.handle.binding(),
+        quats.handle.binding(),
+        global_from_compact_gid.handle.binding(),
+        v_xys_local.handle.clone().binding(),
+        v_conics.handle.binding(),
+        v_means.handle.clone().binding(),
+        v_scales.handle.clone().binding(),
+        v_quats.handle.clone().binding(),
+        v_depths.handle.binding(),
+    ];
+
+    // The 2D filter also needs the opacities to backpropagate through the compensation.
+    if mip_filter {
+        bindings.extend([
+            raw_opac.handle.binding(),
+            v_colors.handle.binding(),
+            v_raw_opac.handle.clone().binding(),
+        ]);
+    }
+
+    // The camera gradient of each splat, summed up after. Has to be zerod, as only visible
+    // splats write to it.
+    let v_camera_splats =
+        camera_grad.then(|| InnerWgpu::float_zeros([num_points, 8].into(), device));
+    if let Some(v_camera_splats) = &v_camera_splats {
+        bindings.push(v_camera_splats.handle.clone().binding());
+    }
+
     tracing::trace_span!("ProjectBackwards", sync_burn = true).in_scope(|| unsafe {
         client.execute_unchecked(
-            ProjectBackwards::task(),
+            ProjectBackwards::task(mip_filter, camera_grad),
             calc_cube_count([num_points as u32], ProjectBackwards::WORKGROUP_SIZE),
-            vec![
-                uniforms_buffer.handle.binding(),
-                means.handle.binding(),
-                log_scales.handle.binding(),
-                quats.handle.binding(),
-                global_from_compact_gid.handle.binding(),
-                v_xys_local.handle.clone().binding(),
-                v_conics.handle.binding(),
-                v_means.handle.clone().binding(),
-                v_scales.handle.clone().binding(),
-                v_quats.handle.clone().binding(),
-            ],
+            bindings,
         );
     });
 
+    let v_camera = if let Some(v_camera_splats) = v_camera_splats {
+        InnerWgpu::float_reshape(InnerWgpu::float_sum_dim(v_camera_splats, 0), [8].into())
+    } else {
+        InnerWgpu::float_zeros([8].into(), device)
+    };
+
     SplatGrads {
         v_means,
         v_quats,
@@ -480,6 +573,8 @@ pub(crate) fn render_backward(
         v_coeffs,
         v_raw_opac,
         v_xy: v_xys_global,
+        v_xy_abs: v_xys_abs,
+        v_camera,
     }
 }
 
@@ -527,6 +622,8 @@ mod tests {
         let num_points = 8;
         let means = Tensor::<DiffBack, 2>::zeros([num_points, 3], &device);
         let xy_dummy = Tensor::<DiffBack, 2>::zeros([num_points, 2], &device);
+        let xy_abs_dummy = Tensor::<DiffBack, 2>::zeros([num_points, 2], &device);
+        let camera_dummy = Tensor::<DiffBack, 1>::zeros([8], &device);
         let log_scales = Tensor::<DiffBack, 2>::ones([num_points, 3], &device) * 2.0;
         let quats: Tensor<DiffBack, 2> =
             Tensor::<DiffBack, 1>::from_floats(glam::Quat::IDENTITY.to_array(), &device)
@@ -539,10 +636,15 @@ mod tests {
             img_size,
             means.into_primitive().tensor(),
             xy_dummy.into_primitive().tensor(),
+            xy_abs_dummy.into_primitive().tensor(),
+            camera_dummy.into_primitive().tensor(),
             log_scales.into_primitive().tensor(),
             quats.into_primitive().tensor(),
             sh_coeffs.into_primitive().tensor(),
             raw_opacity.into_primitive().tensor(),
+            glam::Vec3::ZERO,
+            false,
+            false,
             false,
         );
 
@@ -630,10 +732,15 @@ mod tests {
                 glam::uvec2(w as u32, h as u32),
                 splats.means.val().into_primitive().tensor(),
                 splats.xys_dummy.clone().into_primitive().tensor(),
+                splats.xys_abs_dummy.clone().into_primitive().tensor(),
+                splats.camera_dummy.clone().into_primitive().tensor(),
                 splats.log_scales.val().into_primitive().tensor(),
                 norm_rot.into_primitive().tensor(),
                 splats.sh_coeffs.val().into_primitive().tensor(),
                 splats.raw_opacity.val().into_primitive().tensor(),
+                glam::Vec3::ZERO,
+                false,
+                false,
                 false,
             );
 
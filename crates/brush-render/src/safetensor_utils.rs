@@ -8,7 +8,7 @@ fn float_from_u8(data: &[u8]) -> Vec<f32> {
     bytemuck::cast_slice(data).to_vec()
 }
 
-pub(crate) fn safetensor_to_burn<B: Backend, const D: usize>(
+pub fn safetensor_to_burn<B: Backend, const D: usize>(
     t: TensorView,
     device: &B::Device,
 ) -> Tensor<B, D, Float> {
@@ -12,6 +12,11 @@
 @group(0) @binding(7) var<storage, read_write> v_opacs: array<f32>;
 @group(0) @binding(8) var<storage, read_write> v_xy_global: array<vec2f>;
 
+#ifdef ABS_GRAD
+    @group(0) @binding(9) var<storage, read> v_xy_abs_local: array<vec2f>;
+    @group(0) @binding(10) var<storage, read_write> v_xy_abs_global: array<vec2f>;
+#endif
+
 const SH_C0: f32 = 0.2820947917738781f;
 
 fn sh_coeffs_to_color_fast_vjp(
@@ -227,4 +232,8 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     // Scatter the xy gradients, as later operations need them to be global.
     let v_xy_local = v_xy_local[compact_gid];
     v_xy_global[global_gid] = v_xy_local;
+
+    #ifdef ABS_GRAD
+        v_xy_abs_global[global_gid] = v_xy_abs_local[compact_gid];
+    #endif
 }
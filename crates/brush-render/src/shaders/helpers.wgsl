@@ -4,11 +4,21 @@ const TILE_SIZE: u32 = TILE_WIDTH * TILE_WIDTH;
 
 const MAIN_WG: u32 = 256u;
 
+// Camera projections, see Projection in camera.rs.
+const PROJECTION_PINHOLE: u32 = 0u;
+const PROJECTION_FISHEYE: u32 = 1u;
+const PROJECTION_EQUIRECT: u32 = 2u;
+const PROJECTION_ORTHO: u32 = 3u;
+
+const PI: f32 = 3.141592653589793;
+
 struct RenderUniforms {
     // View matrix transform world to view position.
     viewmat: mat4x4f,
     // Position of camera (xyz + pad)
     camera_position: vec4f,
+    // Background color the splats are composited over (rgb + pad)
+    background: vec4f,
     // Focal of camera (fx, fy)
     focal: vec2f,
     // Img resolution (w, h)
@@ -28,7 +38,8 @@ struct RenderUniforms {
     num_visible: u32,
 #endif
     total_splats: u32,
-    padding: u32,
+    // Camera projection, one of the PROJECTION_ constants.
+    projection: u32,
 }
 
 // nb: this struct has a bunch of padding but that's probably fine.
@@ -42,10 +53,12 @@ struct ProjectedSplat {
     color_g: f32,
     color_b: f32,
     color_a: f32,
+    // Camera space depth of the splat.
+    depth: f32,
 }
 
-fn create_projected_splat(xy: vec2f, conic: vec3f, color: vec4f) -> ProjectedSplat {
-    return ProjectedSplat(xy.x, xy.y, conic.x, conic.y, conic.z, color.r, color.g, color.b, color.a);
+fn create_projected_splat(xy: vec2f, conic: vec3f, color: vec4f, depth: f32) -> ProjectedSplat {
+    return ProjectedSplat(xy.x, xy.y, conic.x, conic.y, conic.z, color.r, color.g, color.b, color.a, depth);
 }
 
 struct PackedVec3 {
@@ -122,7 +135,116 @@ fn calc_cov3d(scale: vec3f, quat: vec4f) -> mat3x3f {
     return M * transpose(M);
 }
 
-fn calc_cam_J(mean_c: vec3f, focal: vec2f, img_size: vec2u, pixel_center: vec2f) -> mat3x2f {
+// The equidistant fisheye maps a camera space point to (x, y) * G, with
+// G = atan2(sqrt(q), z) / sqrt(q) and q = x^2 + y^2.
+// Returns G, and its first and second derivative with respect to q.
+fn fisheye_g(q: f32, z: f32) -> vec3f {
+    if z > 0.0 && q < 0.25 * z * z {
+        // The closed form cancels badly near the optical axis, use the series of atan instead.
+        let u = q / (z * z);
+        var g = 0.0;
+        var g_q = 0.0;
+        var g_qq = 0.0;
+        for (var n = 11; n >= 0; n--) {
+            let c = select(1.0, -1.0, n % 2 == 1) / f32(2 * n + 1);
+            g = g * u + c;
+            if n >= 1 {
+                g_q = g_q * u + f32(n) * c;
+            }
+            if n >= 2 {
+                g_qq = g_qq * u + f32(n * (n - 1)) * c;
+            }
+        }
+        let z2 = z * z;
+        return vec3f(g / z, g_q / (z2 * z), g_qq / (z2 * z2 * z));
+    }
+
+    let r = sqrt(q);
+    let s = q + z * z;
+    let theta = atan2(r, z);
+    let g = theta / r;
+    let g_q = (z * r - theta * s) / (2.0 * q * r * s);
+    let g_qq = (3.0 * theta * s * s - z * r * (5.0 * q + 3.0 * z * z)) / (4.0 * q * q * r * s * s);
+    return vec3f(g, g_q, g_qq);
+}
+
+// Whether a camera space position is in the range where the projection is well behaved.
+fn can_project(mean_c: vec3f, projection: u32) -> bool {
+    if projection == PROJECTION_PINHOLE || projection == PROJECTION_ORTHO {
+        return mean_c.z >= 0.01 && mean_c.z <= 1e12;
+    }
+
+    let dist = length(mean_c);
+    if dist < 0.01 || dist > 1e12 {
+        return false;
+    }
+
+    if projection == PROJECTION_FISHEYE {
+        // Directly behind the camera the fisheye is singular.
+        return atan2(length(mean_c.xy), mean_c.z) < 0.9 * PI;
+    }
+
+    // The longitude is undefined at the poles.
+    return length(mean_c.xz) > 0.01 * dist;
+}
+
+fn project_mean(mean_c: vec3f, focal: vec2f, pixel_center: vec2f, projection: u32) -> vec2f {
+    var uv: vec2f;
+    if projection == PROJECTION_FISHEYE {
+        uv = mean_c.xy * fisheye_g(dot(mean_c.xy, mean_c.xy), mean_c.z).x;
+    } else if projection == PROJECTION_EQUIRECT {
+        uv = vec2f(atan2(mean_c.x, mean_c.z), atan2(mean_c.y, length(mean_c.xz)));
+    } else if projection == PROJECTION_ORTHO {
+        uv = mean_c.xy;
+    } else {
+        uv = mean_c.xy / mean_c.z;
+    }
+    return focal * uv + pixel_center;
+}
+
+// Depth used to sort the splats, and for the depth render. The wide angle projections also see
+// splats next to and behind the camera, so these use the distance to the camera.
+fn projected_depth(mean_c: vec3f, projection: u32) -> f32 {
+    if projection == PROJECTION_PINHOLE || projection == PROJECTION_ORTHO {
+        return mean_c.z;
+    }
+    return length(mean_c);
+}
+
+fn calc_cam_J(mean_c: vec3f, focal: vec2f, img_size: vec2u, pixel_center: vec2f, projection: u32) -> mat3x2f {
+    if projection == PROJECTION_ORTHO {
+        return mat3x2f(vec2f(focal.x, 0.0), vec2f(0.0, focal.y), vec2f(0.0, 0.0));
+    }
+
+    if projection == PROJECTION_FISHEYE {
+        let x = mean_c.x;
+        let y = mean_c.y;
+        let q = x * x + y * y;
+        let g = fisheye_g(q, mean_c.z);
+        let g_z = -1.0 / (q + mean_c.z * mean_c.z);
+
+        return mat3x2f(
+            focal * vec2f(g.x + 2.0 * x * x * g.y, 2.0 * x * y * g.y),
+            focal * vec2f(2.0 * x * y * g.y, g.x + 2.0 * y * y * g.y),
+            focal * mean_c.xy * g_z,
+        );
+    }
+
+    if projection == PROJECTION_EQUIRECT {
+        let x = mean_c.x;
+        let y = mean_c.y;
+        let z = mean_c.z;
+        let rho2 = x * x + z * z;
+        let rho = sqrt(rho2);
+        let d2 = rho2 + y * y;
+
+        return mat3x2f(
+            focal * vec2f(z / rho2, -x * y / (rho * d2)),
+            focal * vec2f(0.0, rho / d2),
+            focal * vec2f(-x / rho2, -z * y / (rho * d2)),
+        );
+    }
+
     let tan_fov = 0.5 * vec2f(img_size.xy) / focal;
 
     let lims_pos = (vec2f(img_size.xy) - pixel_center) / focal + 0.3f * tan_fov;
@@ -143,11 +265,11 @@ fn calc_cam_J(mean_c: vec3f, focal: vec2f, img_size: vec2u, pixel_center: vec2f)
     return J;
 }
 
-fn calc_cov2d(cov3d: mat3x3f, mean_c: vec3f, focal: vec2f, img_size: vec2u, pixel_center: vec2f, viewmat: mat4x4f) -> vec3f {
+fn calc_cov2d(cov3d: mat3x3f, mean_c: vec3f, focal: vec2f, img_size: vec2u, pixel_center: vec2f, viewmat: mat4x4f, projection: u32) -> vec3f {
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let covar_cam = R * cov3d * transpose(R);
 
-    let J = calc_cam_J(mean_c, focal, img_size, pixel_center);
+    let J = calc_cam_J(mean_c, focal, img_size, pixel_center, projection);
 
     let cov2d = J * covar_cam * transpose(J);
 
@@ -166,6 +288,8 @@ fn inverse_symmetric(mat: vec3f) -> vec3f {
 
 const COV_BLUR: f32 = 0.3;
 
+// The 2D filter of Mip-Splatting scales the opacity of a splat by this factor, so the blur
+// added in calc_cov2d spreads the splat out without making it any more opaque overall.
 fn cov_compensation(cov2d: vec3f) -> f32 {
     let cov_orig = cov2d - vec3f(COV_BLUR, 0.0, COV_BLUR);
     let det_orig = cov_orig.x * cov_orig.z - cov_orig.y * cov_orig.y;
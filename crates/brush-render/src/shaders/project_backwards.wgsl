@@ -16,6 +16,24 @@
 @group(0) @binding(8) var<storage, read_write> v_scales: array<helpers::PackedVec3>;
 @group(0) @binding(9) var<storage, read_write> v_quats: array<vec4f>;
 
+@group(0) @binding(10) var<storage, read> v_depths: array<f32>;
+
+#ifdef MIP_FILTER
+    @group(0) @binding(11) var<storage, read> raw_opacities: array<f32>;
+    @group(0) @binding(12) var<storage, read> v_colors: array<vec4f>;
+    @group(0) @binding(13) var<storage, read_write> v_opacs: array<f32>;
+#endif
+
+#ifdef CAMERA_GRAD
+    // For each visible splat, the gradient of a rotation and translation of the camera in
+    // camera space, and of the focal length. These are summed over the splats afterwards.
+    #ifdef MIP_FILTER
+        @group(0) @binding(14) var<storage, read_write> v_camera: array<f32>;
+    #else
+        @group(0) @binding(11) var<storage, read_write> v_camera: array<f32>;
+    #endif
+#endif
+
 
 // TODO: Deal with unnomralized quats.
 fn quat_to_mat_vjp(quat: vec4f, v_R: mat3x3f) -> vec4f {
@@ -58,6 +76,16 @@ fn inverse_vjp(Minv: mat2x2f, v_Minv: mat2x2f) -> mat2x2f {
     return mat2x2f(-Minv[0], -Minv[1]) * v_Minv * Minv;
 }
 
+// Gradient of helpers::cov_compensation. The compensation is sqrt(det(cov_orig) / det(cov)),
+// and the gradient of det(M) is det(M) * M^-1 for a symmetric M.
+fn cov_compensation_vjp(cov2d: vec3f, compensation: f32, v_compensation: f32) -> mat2x2f {
+    let cov_orig = cov2d - vec3f(helpers::COV_BLUR, 0.0, helpers::COV_BLUR);
+    let inv_orig = helpers::inverse_symmetric(cov_orig);
+    let inv = helpers::inverse_symmetric(cov2d);
+    let v = 0.5 * compensation * v_compensation * (inv_orig - inv);
+    return mat2x2f(vec2f(v.x, v.y), vec2f(v.y, v.z));
+}
+
 fn outer_product(a: vec3<f32>, b: vec3<f32>) -> mat3x3<f32> {
     return mat3x3f(
         a.x * b.x, a.x * b.y, a.x * b.z,
@@ -77,6 +105,7 @@ fn persp_proj_vjp(
     // grad outputs
     v_cov2d: mat2x2f,
     v_mean2d: vec2f,
+    v_depth: f32,
 ) -> vec3f {
     let x = mean3d.x;
     let y = mean3d.y;
@@ -131,8 +160,120 @@ fn persp_proj_vjp(
                   2.f * focal.y * ty * rz3 * v_J[2][1];
 
     // add contribution from v_depths
-    // Disabled as there is no depth supervision currently.
-    // v_mean3d.z += v_depths[0];
+    v_mean3d.z += v_depth;
+
+    return v_mean3d;
+}
+
+// Gradient of the camera space mean through the projection jacobian of the fisheye, that is
+// sum_i focal_i * H_i * v_J_i, with H_i the hessian of image coordinate i and v_J_i row i of v_J.
+fn fisheye_J_vjp(mean3d: vec3f, focal: vec2f, v_J: mat3x2f) -> vec3f {
+    let x = mean3d.x;
+    let y = mean3d.y;
+    let z = mean3d.z;
+    let q = x * x + y * y;
+    let s = q + z * z;
+
+    // G and its derivatives, see helpers::fisheye_g.
+    let g = helpers::fisheye_g(q, z);
+    let g_z = -1.0 / s;
+    let g_qz = 1.0 / (s * s);
+    let g_zz = 2.0 * z / (s * s);
+
+    let dG = vec3f(2.0 * x * g.y, 2.0 * y * g.y, g_z);
+    let ddG = mat3x3f(
+        vec3f(2.0 * g.y + 4.0 * x * x * g.z, 4.0 * x * y * g.z, 2.0 * x * g_qz),
+        vec3f(4.0 * x * y * g.z, 2.0 * g.y + 4.0 * y * y * g.z, 2.0 * y * g_qz),
+        vec3f(2.0 * x * g_qz, 2.0 * y * g_qz, g_zz),
+    );
+
+    let w_x = vec3f(v_J[0][0], v_J[1][0], v_J[2][0]);
+    let w_y = vec3f(v_J[0][1], v_J[1][1], v_J[2][1]);
+
+    // The image coordinates are x * G and y * G, so eg. H_x = e_x dG^T + dG e_x^T + x * ddG.
+    let h_x = vec3f(dot(dG, w_x), 0.0, 0.0) + dG * w_x.x + x * (ddG * w_x);
+    let h_y = vec3f(0.0, dot(dG, w_y), 0.0) + dG * w_y.y + y * (ddG * w_y);
+    return focal.x * h_x + focal.y * h_y;
+}
+
+// Same as fisheye_J_vjp, for the equirectangular projection.
+fn equirect_J_vjp(mean3d: vec3f, focal: vec2f, v_J: mat3x2f) -> vec3f {
+    let x = mean3d.x;
+    let y = mean3d.y;
+    let z = mean3d.z;
+    let rho2 = x * x + z * z;
+    let rho = sqrt(rho2);
+    let rho3 = rho2 * rho;
+    let rho4 = rho2 * rho2;
+    let d2 = rho2 + y * y;
+    let d4 = d2 * d2;
+
+    // Hessian of the longitude atan2(x, z).
+    let h_lon = mat3x3f(
+        vec3f(-2.0 * x * z / rho4, 0.0, (x * x - z * z) / rho4),
+        vec3f(0.0, 0.0, 0.0),
+        vec3f((x * x - z * z) / rho4, 0.0, 2.0 * x * z / rho4),
+    );
+
+    // The latitude is atan2(y, rho), chain its derivatives through rho = length(xz).
+    let l_r = -y / d2;
+    let l_yy = -2.0 * y * rho / d4;
+    let l_yr = (y * y - rho2) / d4;
+    let l_rr = 2.0 * y * rho / d4;
+    let r_x = x / rho;
+    let r_z = z / rho;
+
+    let h_xx = l_rr * r_x * r_x + l_r * z * z / rho3;
+    let h_xy = l_yr * r_x;
+    let h_xz = l_rr * r_x * r_z - l_r * x * z / rho3;
+    let h_yz = l_yr * r_z;
+    let h_zz = l_rr * r_z * r_z + l_r * x * x / rho3;
+    let h_lat = mat3x3f(
+        vec3f(h_xx, h_xy, h_xz),
+        vec3f(h_xy, l_yy, h_yz),
+        vec3f(h_xz, h_yz, h_zz),
+    );
+
+    let w_x = vec3f(v_J[0][0], v_J[1][0], v_J[2][0]);
+    let w_y = vec3f(v_J[0][1], v_J[1][1], v_J[2][1]);
+    return focal.x * (h_lon * w_x) + focal.y * (h_lat * w_y);
+}
+
+fn proj_vjp(
+    J: mat3x2f,
+    // fwd inputs
+    mean3d: vec3f,
+    cov3d: mat3x3f,
+    focal: vec2f,
+    pixel_center: vec2f,
+    img_size: vec2u,
+    projection: u32,
+    // grad outputs
+    v_cov2d: mat2x2f,
+    v_mean2d: vec2f,
+    v_depth: f32,
+) -> vec3f {
+    if projection == helpers::PROJECTION_PINHOLE {
+        return persp_proj_vjp(J, mean3d, cov3d, focal, pixel_center, img_size, v_cov2d, v_mean2d, v_depth);
+    }
+
+    if projection == helpers::PROJECTION_ORTHO {
+        // The jacobian is constant, so only the mean and depth have a gradient.
+        return transpose(J) * v_mean2d + vec3f(0.0, 0.0, v_depth);
+    }
+
+    // J includes the focal length, so the mean gradient is just J^T * v_mean2d.
+    var v_mean3d = transpose(J) * v_mean2d;
+
+    let v_J = v_cov2d * J * transpose(cov3d) + transpose(v_cov2d) * J * cov3d;
+    if projection == helpers::PROJECTION_FISHEYE {
+        v_mean3d += fisheye_J_vjp(mean3d, focal, v_J);
+    } else {
+        v_mean3d += equirect_J_vjp(mean3d, focal, v_J);
+    }
+
+    // The depth is the distance to the camera.
+    v_mean3d += v_depth * normalize(mean3d);
 
     return v_mean3d;
 }
@@ -149,6 +290,7 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let focal = uniforms.focal;
     let img_size = uniforms.img_size;
     let pixel_center = uniforms.pixel_center;
+    let projection = uniforms.projection;
 
     let global_gid = global_from_compact_gid[compact_gid];
     let mean = helpers::as_vec(means[global_gid]);
@@ -157,6 +299,7 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
 
     let v_conics = helpers::as_vec(v_conics[compact_gid]);
     let v_mean2d = v_xys[compact_gid];
+    let v_depth = v_depths[compact_gid];
 
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let mean_c = R * mean + viewmat[3].xyz;
@@ -168,25 +311,65 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let M = rotmat * S;
 
     let covar = M * transpose(M);
-    let cov2d = helpers::calc_cov2d(covar, mean_c, focal, img_size, pixel_center, viewmat);
+    let cov2d = helpers::calc_cov2d(covar, mean_c, focal, img_size, pixel_center, viewmat, projection);
     let conics = helpers::inverse_symmetric(cov2d);
 
     let covar2d_inv = mat2x2f(vec2f(conics.x, conics.y), vec2f(conics.y, conics.z));
     let v_covar2d_inv = mat2x2f(vec2f(v_conics.x, v_conics.y * 0.5f), vec2f(v_conics.y * 0.5f, v_conics.z));
 
-    let v_covar2d = inverse_vjp(covar2d_inv, v_covar2d_inv);
+    var v_covar2d = inverse_vjp(covar2d_inv, v_covar2d_inv);
+
+#ifdef MIP_FILTER
+    // The opacity was scaled by the compensation in project_visible, which depends on the
+    // covariance too. The opacity gradient from gather_grads is still missing this factor.
+    let compensation = helpers::cov_compensation(cov2d);
+    v_opacs[global_gid] *= compensation;
+
+    if compensation > 0.0 {
+        let opac = 1.0 / (1.0 + exp(-raw_opacities[global_gid]));
+        let v_compensation = v_colors[compact_gid].w * opac;
+        v_covar2d += cov_compensation_vjp(cov2d, compensation, v_compensation);
+    }
+#endif
 
     // covar_world_to_cam
     let covar_c = R * covar * transpose(R);
 
-    // persp_proj_vjp
-    let J = helpers::calc_cam_J(mean_c, focal, img_size, pixel_center);
-    let v_mean_c = persp_proj_vjp(J, mean_c, covar_c, focal, pixel_center, img_size, v_covar2d, v_mean2d);
+    // proj_vjp
+    let J = helpers::calc_cam_J(mean_c, focal, img_size, pixel_center, projection);
+    let v_mean_c = proj_vjp(J, mean_c, covar_c, focal, pixel_center, img_size, projection, v_covar2d, v_mean2d, v_depth);
     // cov = J * V * Jt; G = df/dcov = v_cov
     // -> df/dV = Jt * G * J
     // -> df/dJ = G * J * Vt + Gt * J * V
     let v_covar_c = transpose(J) * v_covar2d * J;
 
+#ifdef CAMERA_GRAD
+    // Rotating the camera by a small omega and translating it by t moves the splat to
+    // mean_c + omega x mean_c + t, and rotates its covariance by the same rotation.
+    let v_cov_rot = v_covar_c * covar_c + transpose(v_covar_c) * covar_c;
+    let v_rot = cross(mean_c, v_mean_c) + vec3f(
+        v_cov_rot[1][2] - v_cov_rot[2][1],
+        v_cov_rot[2][0] - v_cov_rot[0][2],
+        v_cov_rot[0][1] - v_cov_rot[1][0],
+    );
+
+    // The projected mean is focal * uv + pixel_center, and each row of J is proportional
+    // to the focal length too.
+    let uv = (helpers::project_mean(mean_c, focal, pixel_center, projection) - pixel_center) / focal;
+    let v_J = v_covar2d * J * transpose(covar_c) + transpose(v_covar2d) * J * covar_c;
+    let v_focal = uv * v_mean2d + (v_J[0] * J[0] + v_J[1] * J[1] + v_J[2] * J[2]) / focal;
+
+    let base = compact_gid * 8u;
+    v_camera[base + 0u] = v_rot.x;
+    v_camera[base + 1u] = v_rot.y;
+    v_camera[base + 2u] = v_rot.z;
+    v_camera[base + 3u] = v_mean_c.x;
+    v_camera[base + 4u] = v_mean_c.y;
+    v_camera[base + 5u] = v_mean_c.z;
+    v_camera[base + 6u] = v_focal.x;
+    v_camera[base + 7u] = v_focal.y;
+#endif
+
     // df/dx = -fx * rz2 * df/dJ_02
     // df/dy = -fy * rz2 * df/dJ_12
     // df/dz = -fx * rz2 * df/dJ_00 - fy * rz2 * df/dJ_11
@@ -194,15 +377,10 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     // for D = W * X, G = df/dD
     // df/dW = G * XT, df/dX = WT * G
 
-    // TODO: Camera gradient is not done yet.
-    // var v_R = outer_product(v_mean_c, mean);
+    // The camera gradient is in camera space, see CAMERA_GRAD above.
     let v_mean = transpose(R) * v_mean_c;
 
     // covar_world_to_cam_vjp
-    // TODO: Camera gradient is not done yet.
-    // v_R += v_covar_c * R * transpose(covar) +
-    //        transpose(v_covar_c) * R * covar;
-
     let v_covar = transpose(R) * v_covar_c * R;
 
     // quat_scale_to_covar_vjp
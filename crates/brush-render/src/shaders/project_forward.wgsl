@@ -29,7 +29,7 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let mean_c = R * mean + viewmat[3].xyz;
 
-    if mean_c.z < 0.01 || mean_c.z > 1e12 {
+    if !helpers::can_project(mean_c, uniforms.projection) {
         return;
     }
 
@@ -38,7 +38,7 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     let quat = quats[global_gid];
 
     let cov3d = helpers::calc_cov3d(scale, quat);
-    let cov2d = helpers::calc_cov2d(cov3d, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat);
+    let cov2d = helpers::calc_cov2d(cov3d, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat, uniforms.projection);
     let det = cov2d.x * cov2d.z - cov2d.y * cov2d.y;
 
     if det <= 0.0 {
@@ -49,7 +49,7 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     let conic = helpers::inverse_symmetric(cov2d);
 
     // compute the projected mean
-    let mean2d = uniforms.focal * mean_c.xy * (1.0 / mean_c.z) + uniforms.pixel_center;
+    let mean2d = helpers::project_mean(mean_c, uniforms.focal, uniforms.pixel_center, uniforms.projection);
 
     // TODO: Include opacity here or is this ok?
     let radius = helpers::radius_from_cov(helpers::inverse_symmetric(conic), 1.0);
@@ -73,5 +73,5 @@ fn main(@builtin(global_invocation_id) global_id: vec3u) {
     // Now write all the data to the buffers.
     let write_id = atomicAdd(&uniforms.num_visible, 1u);
     global_from_compact_gid[write_id] = global_gid;
-    depths[write_id] = mean_c.z;
+    depths[write_id] = helpers::projected_depth(mean_c, uniforms.projection);
 }
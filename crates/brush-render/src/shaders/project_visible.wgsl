@@ -175,19 +175,22 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     let mean = helpers::as_vec(means[global_gid]);
     let scale = exp(helpers::as_vec(log_scales[global_gid]));
     let quat = quats[global_gid];
-    let opac = sigmoid(raw_opacities[global_gid]);
+    var opac = sigmoid(raw_opacities[global_gid]);
 
     let viewmat = uniforms.viewmat;
     let R = mat3x3f(viewmat[0].xyz, viewmat[1].xyz, viewmat[2].xyz);
     let mean_c = R * mean + viewmat[3].xyz;
 
     let covar = helpers::calc_cov3d(scale, quat);
-    let cov2d = helpers::calc_cov2d(covar, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat);
+    let cov2d = helpers::calc_cov2d(covar, mean_c, uniforms.focal, uniforms.img_size, uniforms.pixel_center, viewmat, uniforms.projection);
     let conic = helpers::inverse_symmetric(cov2d);
 
+#ifdef MIP_FILTER
+    opac *= helpers::cov_compensation(cov2d);
+#endif
+
     // compute the projected mean
-    let rz = 1.0 / mean_c.z;
-    let mean2d = uniforms.focal * mean_c.xy * rz + uniforms.pixel_center;
+    let mean2d = helpers::project_mean(mean_c, uniforms.focal, uniforms.pixel_center, uniforms.projection);
 
     let sh_degree = uniforms.sh_degree;
     let num_coeffs = num_sh_coeffs(sh_degree);
@@ -255,7 +258,8 @@ fn main(@builtin(global_invocation_id) gid: vec3u) {
     projected[compact_gid] = helpers::create_projected_splat(
         mean2d,
         conic,
-        vec4f(color, opac)
+        vec4f(color, opac),
+        helpers::projected_depth(mean_c, uniforms.projection)
     );
     num_tiles_hit[compact_gid] = tile_area;
 }
@@ -8,8 +8,19 @@
 #ifdef RASTER_U32
     @group(0) @binding(4) var<storage, read_write> out_img: array<u32>;
 #else
-    @group(0) @binding(4) var<storage, read_write> out_img: array<vec4f>;
+    // RGBA, followed by the expected and median depth when rendering depth.
+    @group(0) @binding(4) var<storage, read_write> out_img: array<f32>;
     @group(0) @binding(5) var<storage, read_write> final_index : array<u32>;
+    #ifdef RENDER_DEPTH
+        // The intersection the median depth comes from, or 0xffffffff if there's none.
+        @group(0) @binding(6) var<storage, read_write> median_index : array<u32>;
+    #endif
+#endif
+
+#ifdef RENDER_DEPTH
+    const CHANNELS: u32 = 6u;
+#else
+    const CHANNELS: u32 = 4u;
 #endif
 
 var<workgroup> local_batch: array<helpers::ProjectedSplat, helpers::TILE_SIZE>;
@@ -45,6 +56,11 @@ fn main(
     var T = 1.0;
 
     var pix_out = vec3f(0.0);
+    // Alpha weighted sum of depths.
+    var depth_out = 0.0;
+    // Depth of the splat where the transmittance drops below 0.5.
+    var median_depth = 0.0;
+    var median_idx = 0xffffffffu;
 
     // collect and process batches of gaussians
     // each thread loads one gaussian at a time before rasterizing its
@@ -92,9 +108,16 @@ fn main(
 
                 let fac = alpha * T;
                 pix_out += vec3f(color.r, color.g, color.b) * fac;
-                T = next_T;
 
                 let isect_id = batch_start + t;
+
+                depth_out += projected.depth * fac;
+                if T >= 0.5 && next_T < 0.5 {
+                    median_depth = projected.depth;
+                    median_idx = isect_id;
+                }
+
+                T = next_T;
                 final_idx = isect_id;
             }
         }
@@ -102,13 +125,22 @@ fn main(
 
     if inside {
         let img_alpha = (1.0 - T);
-        let final_color = vec4f(pix_out, img_alpha);
+        let final_color = vec4f(pix_out + T * uniforms.background.rgb, img_alpha);
         #ifdef RASTER_U32
             let colors_u = vec4u(clamp(final_color * 255.0, vec4f(0.0), vec4f(255.0)));
             let packed: u32 = colors_u.x | (colors_u.y << 8u) | (colors_u.z << 16u) | (colors_u.w << 24u);
             out_img[pix_id] = packed;
         #else
-            out_img[pix_id] = final_color;
+            let base = pix_id * CHANNELS;
+            out_img[base + 0] = final_color.r;
+            out_img[base + 1] = final_color.g;
+            out_img[base + 2] = final_color.b;
+            out_img[base + 3] = final_color.a;
+            #ifdef RENDER_DEPTH
+                out_img[base + 4] = depth_out;
+                out_img[base + 5] = median_depth;
+                median_index[pix_id] = median_idx;
+            #endif
             final_index[pix_id] = final_idx;
         #endif
     }
@@ -8,17 +8,37 @@
 @group(0) @binding(3) var<storage, read> projected_splats: array<helpers::ProjectedSplat>;
 
 @group(0) @binding(4) var<storage, read> final_index: array<u32>;
-@group(0) @binding(5) var<storage, read> output: array<vec4f>;
-@group(0) @binding(6) var<storage, read> v_output: array<vec4f>;
+// The intersection the median depth comes from, only written when rendering depth.
+@group(0) @binding(5) var<storage, read> median_index: array<u32>;
+// RGBA, followed by the expected and median depth when rendering depth.
+@group(0) @binding(6) var<storage, read> output: array<f32>;
+@group(0) @binding(7) var<storage, read> v_output: array<f32>;
 
 #ifdef HARD_FLOAT
-    @group(0) @binding(7) var<storage, read_write> v_xy: array<atomic<f32>>;
-    @group(0) @binding(8) var<storage, read_write> v_conics: array<atomic<f32>>;
-    @group(0) @binding(9) var<storage, read_write> v_colors: array<atomic<f32>>;
+    @group(0) @binding(8) var<storage, read_write> v_xy: array<atomic<f32>>;
+    @group(0) @binding(9) var<storage, read_write> v_conics: array<atomic<f32>>;
+    @group(0) @binding(10) var<storage, read_write> v_colors: array<atomic<f32>>;
+    @group(0) @binding(11) var<storage, read_write> v_depths: array<atomic<f32>>;
 #else
-    @group(0) @binding(7) var<storage, read_write> v_xy: array<atomic<u32>>;
-    @group(0) @binding(8) var<storage, read_write> v_conics: array<atomic<u32>>;
-    @group(0) @binding(9) var<storage, read_write> v_colors: array<atomic<u32>>;
+    @group(0) @binding(8) var<storage, read_write> v_xy: array<atomic<u32>>;
+    @group(0) @binding(9) var<storage, read_write> v_conics: array<atomic<u32>>;
+    @group(0) @binding(10) var<storage, read_write> v_colors: array<atomic<u32>>;
+    @group(0) @binding(11) var<storage, read_write> v_depths: array<atomic<u32>>;
+#endif
+
+// Sum of the absolute xy gradient of each pixel, used as the densification criterion of AbsGS.
+#ifdef ABS_GRAD
+    #ifdef HARD_FLOAT
+        @group(0) @binding(12) var<storage, read_write> v_xy_abs: array<atomic<f32>>;
+    #else
+        @group(0) @binding(12) var<storage, read_write> v_xy_abs: array<atomic<u32>>;
+    #endif
+#endif
+
+#ifdef RENDER_DEPTH
+    const CHANNELS: u32 = 6u;
+#else
+    const CHANNELS: u32 = 4u;
 #endif
 
 
@@ -39,6 +59,9 @@ var<workgroup> local_id: array<u32, BATCH_SIZE>;
 var<workgroup> grad_count: atomic<i32>;
 var<workgroup> gather_grads: array<helpers::ProjectedSplat, BATCH_SIZE>;
 var<workgroup> gather_grad_id: array<u32, BATCH_SIZE>;
+#ifdef ABS_GRAD
+    var<workgroup> gather_abs_grads: array<vec2f, BATCH_SIZE>;
+#endif
 
 fn add_bitcast(cur: u32, add: f32) -> u32 {
     return bitcast<u32>(bitcast<f32>(cur) + add);
@@ -57,6 +80,10 @@ fn write_grads_atomic(grads: helpers::ProjectedSplat, id: u32) {
     atomicAdd(&v_colors[id * 4 + 1], grads.color_g);
     atomicAdd(&v_colors[id * 4 + 2], grads.color_b);
     atomicAdd(&v_colors[id * 4 + 3], grads.color_a);
+
+    #ifdef RENDER_DEPTH
+        atomicAdd(&v_depths[id], grads.depth);
+    #endif
 #else
     // Alternatively can run without any atomics and just race:
     // v_xy[id * 2 + 0] = add_bitcast(v_xy[id * 2 + 0], grads.xy.x);
@@ -131,9 +158,38 @@ fn write_grads_atomic(grads: helpers::ProjectedSplat, id: u32) {
         let cas = atomicCompareExchangeWeak(&v_colors[id * 4 + 3], old_value, add_bitcast(old_value, color.a));
         if cas.exchanged { break; } else { old_value = cas.old_value; }
     }
+
+    #ifdef RENDER_DEPTH
+        // v_depth
+        old_value = atomicLoad(&v_depths[id]);
+        loop {
+            let cas = atomicCompareExchangeWeak(&v_depths[id], old_value, add_bitcast(old_value, grads.depth));
+            if cas.exchanged { break; } else { old_value = cas.old_value; }
+        }
+    #endif
 #endif
 }
 
+#ifdef ABS_GRAD
+fn write_abs_grads_atomic(grads: vec2f, id: u32) {
+#ifdef HARD_FLOAT
+    atomicAdd(&v_xy_abs[id * 2 + 0], grads.x);
+    atomicAdd(&v_xy_abs[id * 2 + 1], grads.y);
+#else
+    var old_value = atomicLoad(&v_xy_abs[id * 2 + 0]);
+    loop {
+        let cas = atomicCompareExchangeWeak(&v_xy_abs[id * 2 + 0], old_value, add_bitcast(old_value, grads.x));
+        if cas.exchanged { break; } else { old_value = cas.old_value; }
+    }
+    old_value = atomicLoad(&v_xy_abs[id * 2 + 1]);
+    loop {
+        let cas = atomicCompareExchangeWeak(&v_xy_abs[id * 2 + 1], old_value, add_bitcast(old_value, grads.y));
+        if cas.exchanged { break; } else { old_value = cas.old_value; }
+    }
+#endif
+}
+#endif
+
 // kernel function for rasterizing each tile
 // each thread treats a single pixel
 // each thread group uses the same gaussian data in a tile
@@ -160,7 +216,7 @@ fn main(
     let inside = pixel_coordi.x < img_size.x && pixel_coordi.y < img_size.y;
 
     // this is the T AFTER the last gaussian in this pixel
-    let T_final = 1.0 - output[pix_id].w;
+    let T_final = 1.0 - output[pix_id * CHANNELS + 3];
 
     // Have all threads in tile process the same gaussians in batches
     // first collect gaussians between bin_start and bin_final in batches
@@ -173,16 +229,27 @@ fn main(
     var T = T_final;
 
     var final_isect = 0u;
+    var median_isect = 0u;
     var buffer = vec3f(0.0);
+    var depth_buffer = 0.0;
 
     if inside {
         final_isect = final_index[pix_id];
+        median_isect = median_index[pix_id];
     }
 
     // df/d_out for this pixel
     var v_out = vec4f(0.0);
+    var v_depth_out = 0.0;
+    var v_median_depth = 0.0;
     if inside {
-        v_out = v_output[pix_id];
+        let base = pix_id * CHANNELS;
+        v_out = vec4f(v_output[base + 0], v_output[base + 1], v_output[base + 2], v_output[base + 3]);
+
+        #ifdef RENDER_DEPTH
+            v_depth_out = v_output[base + 4];
+            v_median_depth = v_output[base + 5];
+        #endif
     }
 
     // Make sure all groups start with empty gradient queue.
@@ -223,6 +290,7 @@ fn main(
                 var v_xy = vec2f(0.0);
                 var v_conic = vec3f(0.0);
                 var v_colors = vec4f(0.0);
+                var v_depth = 0.0;
 
                 var splat_active = false;
 
@@ -251,11 +319,25 @@ fn main(
 
                         // contribution from this pixel
                         var v_alpha = dot(color.rgb * T - buffer * ra, v_out.rgb);
-                        v_alpha += T_final * ra * v_out.a;
+                        // The alpha and the background both depend on the final transmittance.
+                        v_alpha += T_final * ra * (v_out.a - dot(uniforms.background.rgb, v_out.rgb));
 
                         // update the running sum
                         buffer += color.xyz * fac;
 
+                        #ifdef RENDER_DEPTH
+                            // The expected depth is blended just like the colors.
+                            v_alpha += (projected.depth * T - depth_buffer * ra) * v_depth_out;
+                            depth_buffer += projected.depth * fac;
+                            v_depth = fac * v_depth_out;
+
+                            // The median depth is the depth of a single splat, the one the forward
+                            // pass recorded.
+                            if isect_id == median_isect {
+                                v_depth += v_median_depth;
+                            }
+                        #endif
+
                         let v_sigma = -color.a * vis * v_alpha;
 
                         v_xy = v_sigma * vec2f(
@@ -277,6 +359,12 @@ fn main(
                     var v_xy_sum = subgroupAdd(v_xy);
                     var v_conic_sum = subgroupAdd(v_conic);
                     var v_colors_sum = subgroupAdd(v_colors);
+                    var v_depth_sum = subgroupAdd(v_depth);
+                    #ifdef ABS_GRAD
+                        // Sum the absolute gradients of the pixels, so opposing gradients
+                        // don't cancel out.
+                        let v_xy_abs_sum = subgroupAdd(abs(v_xy));
+                    #endif
 
                     // First thread of subgroup writes the gradient. This should be a
                     // subgroupBallot() when it's supported.
@@ -285,9 +373,14 @@ fn main(
                         gather_grads[grad_idx] = helpers::create_projected_splat(
                             v_xy_sum,
                             v_conic_sum,
-                            v_colors_sum
+                            v_colors_sum,
+                            v_depth_sum
                         );
                         gather_grad_id[grad_idx] = local_id[t];
+
+                        #ifdef ABS_GRAD
+                            gather_abs_grads[grad_idx] = v_xy_abs_sum;
+                        #endif
                     }
                 }
             }
@@ -296,6 +389,9 @@ fn main(
             workgroupBarrier();
             if local_idx < u32(grad_count) {
                 write_grads_atomic(gather_grads[local_idx], gather_grad_id[local_idx]);
+                #ifdef ABS_GRAD
+                    write_abs_grads_atomic(gather_abs_grads[local_idx], gather_grad_id[local_idx]);
+                #endif
             }
             workgroupBarrier();
             atomicStore(&grad_count, 0);
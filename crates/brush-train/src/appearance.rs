@@ -0,0 +1,118 @@
+// Per view colour transforms, for captures where the exposure and white balance change between
+// images. Without these, the splats have to explain the colour changes, which they mostly do
+// with floaters in front of the cameras.
+//
+// The transforms only exist for training views. They're applied to the renders before the
+// loss, but never to evaluation renders or exported splats.
+use burn::{
+    module::{Module, Param, ParamId},
+    prelude::Backend,
+    tensor::Tensor,
+};
+use glam::{DMat4, DVec4};
+
+/// Apply an affine colour transform, a [3, 4] matrix, to the RGB channels of an image.
+pub fn apply_affine<B: Backend>(image: Tensor<B, 3>, transform: Tensor<B, 2>) -> Tensor<B, 3> {
+    let [h, w, c] = image.dims();
+    let rgb = image.clone().slice([0..h, 0..w, 0..3]).reshape([h * w, 3]);
+    let matrix = transform.clone().slice([0..3, 0..3]);
+    let offset = transform.slice([0..3, 3..4]).reshape([1, 3]);
+    let rgb = (rgb.matmul(matrix.transpose()) + offset).reshape([h, w, 3]);
+
+    if c > 3 {
+        Tensor::cat(vec![rgb, image.slice([0..h, 0..w, 3..c])], 2)
+    } else {
+        rgb
+    }
+}
+
+/// Fit the affine colour transform which maps the rendered image closest to the ground truth,
+/// in the least squares sense. Both images are [h, w, 3], and only the pixels where the
+/// [h, w, 1] mask is one are used.
+pub async fn fit_affine<B: Backend>(
+    rendered: Tensor<B, 3>,
+    gt: Tensor<B, 3>,
+    mask: Tensor<B, 3>,
+) -> Tensor<B, 2> {
+    let device = rendered.device();
+    let [h, w, _] = rendered.dims();
+    let n = h * w;
+
+    // Masked out pixels are zero rows, which don't contribute to the normal equations.
+    let mask = mask.reshape([n, 1]);
+    let x = Tensor::cat(
+        vec![rendered.reshape([n, 3]), Tensor::ones([n, 1], &device)],
+        1,
+    ) * mask.clone();
+    let xtx: Vec<f32> = x
+        .clone()
+        .transpose()
+        .matmul(x.clone())
+        .into_data_async()
+        .await
+        .to_vec()
+        .expect("Normal matrix must be f32");
+    let xty: Vec<f32> = x
+        .transpose()
+        .matmul(gt.reshape([n, 3]) * mask)
+        .into_data_async()
+        .await
+        .to_vec()
+        .expect("Normal matrix must be f32");
+
+    // A bit of regularization keeps this solvable for flat images.
+    let xtx = DMat4::from_cols_array(&std::array::from_fn(|i| xtx[i] as f64))
+        + DMat4::from_diagonal(DVec4::splat(1e-6));
+    let inv = xtx.inverse();
+
+    // Solve for each output channel separately, xty is [4, 3].
+    let rows: Vec<[f32; 4]> = (0..3)
+        .map(|c| {
+            let rhs = DVec4::from_array(std::array::from_fn(|i| xty[i * 3 + c] as f64));
+            (inv * rhs).as_vec4().to_array()
+        })
+        .collect();
+
+    Tensor::from_floats([rows[0], rows[1], rows[2]], &device)
+}
+
+/// A learned affine colour transform for each training view.
+#[derive(Module, Debug)]
+pub struct ViewAppearance<B: Backend> {
+    /// The transform of each view as a [num_views, 3, 4] matrix, starting out as the identity.
+    pub transforms: Param<Tensor<B, 3>>,
+}
+
+impl<B: Backend> ViewAppearance<B> {
+    pub fn new(num_views: usize, device: &B::Device) -> Self {
+        let identity = Tensor::<B, 2>::from_floats(
+            [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+            ],
+            device,
+        );
+        Self::from_transforms(identity.unsqueeze().repeat_dim(0, num_views))
+    }
+
+    pub fn from_transforms(transforms: Tensor<B, 3>) -> Self {
+        Self {
+            transforms: Param::initialized(ParamId::new(), transforms.detach().require_grad()),
+        }
+    }
+
+    pub fn num_views(&self) -> usize {
+        self.transforms.dims()[0]
+    }
+
+    /// Apply the transform of a view to a render of it.
+    pub fn apply(&self, view: usize, image: Tensor<B, 3>) -> Tensor<B, 3> {
+        let transform = self
+            .transforms
+            .val()
+            .slice([view..view + 1, 0..3, 0..4])
+            .squeeze(0);
+        apply_affine(image, transform)
+    }
+}
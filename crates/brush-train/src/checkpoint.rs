@@ -0,0 +1,344 @@
+// Checkpoints store the splats, together with all the state needed to continue training
+// exactly where it left off: the step count, the Adam moments of every parameter,
+// the accumulated gradient statistics used for refinement, the splat count the budget grows
+// from and the Mip-Splatting 3D filter. The training config is stored too, as resuming with a
+// different config wouldn't continue the same run.
+//
+// The format is a safetensors file. The splats use the same names as Splats::from_safetensors,
+// so a checkpoint can also be loaded as a plain set of splats.
+use std::collections::HashMap;
+
+use anyhow::Context;
+use brush_render::{
+    gaussian_splats::Splats, safetensor_utils::safetensor_to_burn, AutodiffBackend, Backend,
+};
+use burn::{
+    module::ParamId,
+    optim::{record::AdaptorRecord, AdamState, AdaptiveMomentumState, Optimizer},
+    tensor::{Tensor, TensorData},
+};
+use safetensors::{tensor::TensorView, Dtype, SafeTensors};
+
+use crate::{
+    appearance::ViewAppearance,
+    pose::ViewPoses,
+    train::{OptimRecord, SplatTrainer, TrainConfig},
+};
+
+struct CheckpointWriter {
+    tensors: Vec<(String, TensorData)>,
+    metadata: HashMap<String, String>,
+}
+
+impl CheckpointWriter {
+    async fn add<B: burn::prelude::Backend, const D: usize>(
+        &mut self,
+        name: &str,
+        tensor: Tensor<B, D>,
+    ) {
+        self.tensors
+            .push((name.to_owned(), tensor.into_data_async().await));
+    }
+
+    async fn add_adam_state<B: AutodiffBackend, const D: usize>(
+        &mut self,
+        record: &mut OptimRecord<B>,
+        name: &str,
+        id: ParamId,
+    ) {
+        // Parameters without any gradients yet don't have any state.
+        let Some(state) = record.remove(&id) else {
+            return;
+        };
+        let state: AdamState<B::InnerBackend, D> = state.into_state();
+        let momentum = state.momentum;
+        self.metadata
+            .insert(format!("adam.{name}.time"), momentum.time.to_string());
+        self.add(&format!("adam.{name}.moment_1"), momentum.moment_1)
+            .await;
+        self.add(&format!("adam.{name}.moment_2"), momentum.moment_2)
+            .await;
+    }
+
+    fn serialize(self) -> anyhow::Result<Vec<u8>> {
+        let views = self
+            .tensors
+            .iter()
+            .map(|(name, data)| {
+                let view = TensorView::new(Dtype::F32, data.shape.clone(), &data.bytes)?;
+                Ok((name.clone(), view))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(safetensors::serialize(views, &Some(self.metadata))?)
+    }
+}
+
+fn load_adam_state<B: AutodiffBackend, const D: usize>(
+    tensors: &SafeTensors,
+    metadata: &HashMap<String, String>,
+    record: &mut OptimRecord<B>,
+    name: &str,
+    id: ParamId,
+    device: &B::Device,
+) -> anyhow::Result<()> {
+    let Some(time) = metadata.get(&format!("adam.{name}.time")) else {
+        return Ok(());
+    };
+    let moment_1 = safetensor_to_burn::<B::InnerBackend, D>(
+        tensors.tensor(&format!("adam.{name}.moment_1"))?,
+        device,
+    );
+    let moment_2 = safetensor_to_burn::<B::InnerBackend, D>(
+        tensors.tensor(&format!("adam.{name}.moment_2"))?,
+        device,
+    );
+    let state = AdamState {
+        momentum: AdaptiveMomentumState {
+            time: time.parse()?,
+            moment_1,
+            moment_2,
+        },
+    };
+    record.insert(id, AdaptorRecord::from_state(state));
+    Ok(())
+}
+
+impl<B: AutodiffBackend> SplatTrainer<B>
+where
+    B::InnerBackend: Backend,
+{
+    /// Serialize the splats and the full trainer state to a safetensors checkpoint.
+    pub async fn save_checkpoint(&self, splats: &Splats<B>) -> anyhow::Result<Vec<u8>> {
+        let mut writer = CheckpointWriter {
+            tensors: vec![],
+            metadata: HashMap::from([
+                ("iter".to_owned(), self.iter.to_string()),
+                ("config".to_owned(), serde_json::to_string(&self.config)?),
+                ("start_count".to_owned(), self.start_count.to_string()),
+            ]),
+        };
+
+        writer.add("means", splats.means.val()).await;
+        writer.add("quats", splats.rotation.val()).await;
+        writer.add("scales", splats.log_scales.val()).await;
+        writer.add("coeffs", splats.sh_coeffs.val()).await;
+        writer.add("opacities", splats.raw_opacity.val()).await;
+
+        writer
+            .add("grad_2d_accum", self.grad_2d_accum.clone())
+            .await;
+        writer
+            .add("xy_grad_counts", self.xy_grad_counts.clone().float())
+            .await;
+        writer
+            .add("coverage_accum", self.coverage_accum.clone())
+            .await;
+
+        if let (Some(filter_3d), Some(iter)) = (&splats.filter_3d, self.filter_3d_iter) {
+            writer
+                .metadata
+                .insert("filter_3d_iter".to_owned(), iter.to_string());
+            writer.add("filter_3d", filter_3d.clone()).await;
+        }
+
+        let mut record = self.optim.to_record();
+        writer
+            .add_adam_state::<B, 2>(&mut record, "means", splats.means.id)
+            .await;
+        writer
+            .add_adam_state::<B, 2>(&mut record, "quats", splats.rotation.id)
+            .await;
+        writer
+            .add_adam_state::<B, 2>(&mut record, "scales", splats.log_scales.id)
+            .await;
+        writer
+            .add_adam_state::<B, 3>(&mut record, "coeffs", splats.sh_coeffs.id)
+            .await;
+        writer
+            .add_adam_state::<B, 1>(&mut record, "opacities", splats.raw_opacity.id)
+            .await;
+
+        if let Some(appearance) = &self.appearance {
+            writer.add("appearance", appearance.transforms.val()).await;
+            let mut record = self.appearance_optim.to_record();
+            writer
+                .add_adam_state::<B, 3>(&mut record, "appearance", appearance.transforms.id)
+                .await;
+        }
+
+        if let Some(poses) = &self.poses {
+            writer.add("pose_rotations", poses.rotations.val()).await;
+            writer
+                .add("pose_translations", poses.translations.val())
+                .await;
+            writer
+                .add("pose_focal_scales", poses.log_focal_scales.val())
+                .await;
+            let mut record = self.pose_optim.to_record();
+            writer
+                .add_adam_state::<B, 2>(&mut record, "pose_rotations", poses.rotations.id)
+                .await;
+            writer
+                .add_adam_state::<B, 2>(&mut record, "pose_translations", poses.translations.id)
+                .await;
+            writer
+                .add_adam_state::<B, 1>(&mut record, "pose_focal_scales", poses.log_focal_scales.id)
+                .await;
+        }
+
+        writer.serialize()
+    }
+
+    /// Restore a trainer and its splats from a checkpoint written by [`SplatTrainer::save_checkpoint`].
+    ///
+    /// Training continues from the step the checkpoint was saved at. To continue exactly
+    /// like an uninterrupted run, the data loader should also skip to this step.
+    ///
+    /// Fails if the checkpoint was saved with a different config.
+    pub fn load_checkpoint(
+        data: &[u8],
+        config: &TrainConfig,
+        device: &B::Device,
+    ) -> anyhow::Result<(Self, Splats<B>)> {
+        let (_, header) = SafeTensors::read_metadata(data)?;
+        let metadata = header.metadata().clone().unwrap_or_default();
+        let tensors = SafeTensors::deserialize(data)?;
+
+        let saved_config: serde_json::Map<String, serde_json::Value> = serde_json::from_str(
+            metadata
+                .get("config")
+                .context("Checkpoint is missing the training config")?,
+        )?;
+        let current_config: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_value(serde_json::to_value(config)?)?;
+        let mut changed: Vec<_> = saved_config
+            .keys()
+            .chain(current_config.keys())
+            .filter(|key| saved_config.get(*key) != current_config.get(*key))
+            .collect();
+        changed.sort();
+        changed.dedup();
+        anyhow::ensure!(
+            changed.is_empty(),
+            "Checkpoint was saved with a different training config, changed: {changed:?}"
+        );
+
+        let mut splats = Splats::<B>::from_safetensors(&tensors, device)?;
+        let start_count = metadata
+            .get("start_count")
+            .context("Checkpoint is missing the starting splat count")?
+            .parse()?;
+        let mut trainer = Self::new(start_count, config, device);
+
+        trainer.iter = metadata
+            .get("iter")
+            .context("Checkpoint is missing the training step")?
+            .parse()?;
+
+        trainer.grad_2d_accum = safetensor_to_burn(tensors.tensor("grad_2d_accum")?, device);
+        trainer.xy_grad_counts =
+            safetensor_to_burn::<B, 1>(tensors.tensor("xy_grad_counts")?, device).int();
+        trainer.coverage_accum = safetensor_to_burn(tensors.tensor("coverage_accum")?, device);
+
+        if let Some(iter) = metadata.get("filter_3d_iter") {
+            splats.filter_3d = Some(safetensor_to_burn(tensors.tensor("filter_3d")?, device));
+            trainer.filter_3d_iter = Some(iter.parse()?);
+        }
+
+        let mut record = OptimRecord::<B>::new();
+        load_adam_state::<B, 2>(
+            &tensors,
+            &metadata,
+            &mut record,
+            "means",
+            splats.means.id,
+            device,
+        )?;
+        load_adam_state::<B, 2>(
+            &tensors,
+            &metadata,
+            &mut record,
+            "quats",
+            splats.rotation.id,
+            device,
+        )?;
+        load_adam_state::<B, 2>(
+            &tensors,
+            &metadata,
+            &mut record,
+            "scales",
+            splats.log_scales.id,
+            device,
+        )?;
+        load_adam_state::<B, 3>(
+            &tensors,
+            &metadata,
+            &mut record,
+            "coeffs",
+            splats.sh_coeffs.id,
+            device,
+        )?;
+        load_adam_state::<B, 1>(
+            &tensors,
+            &metadata,
+            &mut record,
+            "opacities",
+            splats.raw_opacity.id,
+            device,
+        )?;
+        trainer.optim = trainer.opt_config.init().load_record(record);
+
+        if let Ok(transforms) = tensors.tensor("appearance") {
+            let appearance =
+                ViewAppearance::from_transforms(safetensor_to_burn::<B, 3>(transforms, device));
+            let mut record = OptimRecord::<B>::new();
+            load_adam_state::<B, 3>(
+                &tensors,
+                &metadata,
+                &mut record,
+                "appearance",
+                appearance.transforms.id,
+                device,
+            )?;
+            trainer.appearance_optim = trainer.opt_config.init().load_record(record);
+            trainer.appearance = Some(appearance);
+        }
+
+        if let Ok(rotations) = tensors.tensor("pose_rotations") {
+            let poses = ViewPoses::from_tensors(
+                safetensor_to_burn::<B, 2>(rotations, device),
+                safetensor_to_burn::<B, 2>(tensors.tensor("pose_translations")?, device),
+                safetensor_to_burn::<B, 1>(tensors.tensor("pose_focal_scales")?, device),
+            );
+            let mut record = OptimRecord::<B>::new();
+            load_adam_state::<B, 2>(
+                &tensors,
+                &metadata,
+                &mut record,
+                "pose_rotations",
+                poses.rotations.id,
+                device,
+            )?;
+            load_adam_state::<B, 2>(
+                &tensors,
+                &metadata,
+                &mut record,
+                "pose_translations",
+                poses.translations.id,
+                device,
+            )?;
+            load_adam_state::<B, 1>(
+                &tensors,
+                &metadata,
+                &mut record,
+                "pose_focal_scales",
+                poses.log_focal_scales.id,
+                device,
+            )?;
+            trainer.pose_optim = trainer.opt_config.init().load_record(record);
+            trainer.poses = Some(poses);
+        }
+
+        Ok((trainer, splats))
+    }
+}
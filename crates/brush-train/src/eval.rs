@@ -1,9 +1,10 @@
 use brush_render::RenderAux;
 use brush_render::{gaussian_splats::Splats, Backend};
-use burn::tensor::{ElementConversion, Tensor};
+use burn::tensor::{ElementConversion, Tensor, TensorData};
 use image::DynamicImage;
 use rand::seq::IteratorRandom;
 
+use crate::appearance::{apply_affine, fit_affine};
 use crate::image::image_to_tensor;
 use crate::scene::{Scene, SceneView};
 use crate::ssim::Ssim;
@@ -24,10 +25,32 @@ pub struct EvalStats<B: Backend> {
     pub samples: Vec<EvalView<B>>,
 }
 
+// Size of the checkerboard squares used to fit the appearance of eval views. Large enough that
+// neighbouring pixels, which look alike, mostly end up in the same half.
+const CHECKER_SIZE: u32 = 16;
+
+/// A [h, w, 1] checkerboard mask, one on the white squares.
+fn checkerboard<B: Backend>(res: glam::UVec2, device: &B::Device) -> Tensor<B, 3> {
+    let mask: Vec<f32> = (0..res.y)
+        .flat_map(|y| (0..res.x).map(move |x| ((x / CHECKER_SIZE + y / CHECKER_SIZE) % 2) as f32))
+        .collect();
+    Tensor::from_data(
+        TensorData::new(mask, [res.y as usize, res.x as usize, 1]),
+        device,
+    )
+}
+
+/// Render views of the evaluation scene and compare them to the ground truth.
+///
+/// Evaluation views don't have a learned appearance transform. When training with
+/// per view appearance transforms, `fit_appearance` fits the best colour transform for each
+/// view first, so the metrics don't penalize the exposure of the view. The transform is fitted
+/// on one half of a checkerboard and applied to the other, so it can't fit away actual errors.
 pub async fn eval_stats<B: Backend>(
     splats: Splats<B>,
     eval_scene: &Scene,
     num_frames: Option<usize>,
+    fit_appearance: bool,
     rng: &mut impl rand::Rng,
     device: &B::Device,
 ) -> EvalStats<B> {
@@ -50,9 +73,20 @@ pub async fn eval_stats<B: Backend>(
         let res = glam::uvec2(ground_truth.width(), ground_truth.height());
 
         let gt_tensor = image_to_tensor::<B>(&ground_truth, device);
-        let (rendered, aux) = splats.render(&view.camera, res, false);
+        let (rendered, aux) = splats.render(&view.camera, res, glam::Vec3::ZERO, false);
 
         let render_rgb = rendered.slice([0..res.y as usize, 0..res.x as usize, 0..3]);
+        let render_rgb = if fit_appearance {
+            // Each half of the checkerboard gets the transform fitted on the other half.
+            let white = checkerboard::<B>(res, device);
+            let black = white.clone().neg() + 1.0;
+            let from_white = fit_affine(render_rgb.clone(), gt_tensor.clone(), white.clone()).await;
+            let from_black = fit_affine(render_rgb.clone(), gt_tensor.clone(), black.clone()).await;
+            apply_affine(render_rgb.clone(), from_black) * white
+                + apply_affine(render_rgb, from_white) * black
+        } else {
+            render_rgb
+        };
         let mse = (render_rgb.clone() - gt_tensor.clone())
             .powf_scalar(2.0)
             .mean();
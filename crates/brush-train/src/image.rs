@@ -2,7 +2,9 @@ use burn::{
     prelude::Backend,
     tensor::{DType, Tensor, TensorData},
 };
-use image::{DynamicImage, Rgb32FImage, Rgba32FImage};
+use image::{DynamicImage, GrayImage, Rgb32FImage, Rgba32FImage};
+
+use crate::scene::DepthImage;
 
 // Converts an image to a tensor. The tensor will be a floating point image with a [0, 1] image.
 pub fn image_to_tensor<B: Backend>(image: &DynamicImage, device: &B::Device) -> Tensor<B, 3> {
@@ -17,6 +19,22 @@ pub fn image_to_tensor<B: Backend>(image: &DynamicImage, device: &B::Device) ->
     Tensor::from_data(tensor_data, device)
 }
 
+// Converts a mask to a [h, w] tensor with values in [0, 1].
+pub fn mask_to_tensor<B: Backend>(mask: &GrayImage, device: &B::Device) -> Tensor<B, 2> {
+    let (w, h) = (mask.width(), mask.height());
+    let values = mask.as_raw().iter().map(|&m| m as f32 / 255.0).collect();
+    Tensor::from_data(TensorData::new(values, [h as usize, w as usize]), device)
+}
+
+// Converts a depth map to a [h, w] tensor.
+pub fn depth_to_tensor<B: Backend>(depth: &DepthImage, device: &B::Device) -> Tensor<B, 2> {
+    let (w, h) = (depth.width(), depth.height());
+    Tensor::from_data(
+        TensorData::new(depth.as_raw().clone(), [h as usize, w as usize]),
+        device,
+    )
+}
+
 pub trait TensorDataToImage {
     fn into_image(self) -> DynamicImage;
 }
@@ -1,4 +1,10 @@
+pub mod appearance;
+pub mod checkpoint;
 pub mod eval;
+pub mod lr_schedule;
+pub mod mcmc;
+pub mod pose;
+pub mod refine;
 pub mod ssim;
 pub mod train;
 
@@ -0,0 +1,118 @@
+// Learning rate schedules for the splat parameters. A schedule scales the base learning rate of a
+// parameter group depending on the training step. Schedules are a pure function of the step, so
+// resuming from a checkpoint doesn't need any extra state.
+use std::f64::consts::PI;
+
+/// How a learning rate changes over the course of training.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum LrSchedule {
+    /// The same learning rate at every step.
+    Constant,
+    /// Decay exponentially, to `final_scale` times the base rate at `steps`. Keeps decaying after.
+    Exponential { final_scale: f64, steps: u32 },
+    /// Follow half a cosine from the base rate to `final_scale` times the base rate at `steps`,
+    /// and stay there after.
+    Cosine { final_scale: f64, steps: u32 },
+    /// Ramp up linearly from zero to the base rate over `warmup_steps`, then decay exponentially
+    /// to `final_scale` times the base rate at `steps`.
+    WarmupDecay {
+        warmup_steps: u32,
+        final_scale: f64,
+        steps: u32,
+    },
+}
+
+impl LrSchedule {
+    /// The factor the base learning rate is multiplied by at step `iter`.
+    pub fn scale(&self, iter: u32) -> f64 {
+        match *self {
+            Self::Constant => 1.0,
+            Self::Exponential { final_scale, steps } => {
+                final_scale.powf(iter as f64 / steps.max(1) as f64)
+            }
+            Self::Cosine { final_scale, steps } => {
+                let t = (iter as f64 / steps.max(1) as f64).min(1.0);
+                final_scale + (1.0 - final_scale) * 0.5 * (1.0 + (PI * t).cos())
+            }
+            Self::WarmupDecay {
+                warmup_steps,
+                final_scale,
+                steps,
+            } => {
+                if iter < warmup_steps {
+                    // Start just above zero, a step with a learning rate of zero does nothing.
+                    (iter + 1) as f64 / warmup_steps as f64
+                } else {
+                    let decay_steps = steps.saturating_sub(warmup_steps).max(1);
+                    final_scale.powf((iter - warmup_steps) as f64 / decay_steps as f64)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LrSchedule;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    #[test]
+    fn constant() {
+        assert_eq!(LrSchedule::Constant.scale(0), 1.0);
+        assert_eq!(LrSchedule::Constant.scale(u32::MAX), 1.0);
+    }
+
+    #[test]
+    fn exponential_endpoints() {
+        let schedule = LrSchedule::Exponential {
+            final_scale: 0.01,
+            steps: 1000,
+        };
+        assert_close(schedule.scale(0), 1.0);
+        assert_close(schedule.scale(500), 0.1);
+        assert_close(schedule.scale(1000), 0.01);
+        // Exponential decay keeps going past the end.
+        assert_close(schedule.scale(2000), 1e-4);
+    }
+
+    #[test]
+    fn cosine_clamps_after_end() {
+        let schedule = LrSchedule::Cosine {
+            final_scale: 0.2,
+            steps: 1000,
+        };
+        assert_close(schedule.scale(0), 1.0);
+        assert_close(schedule.scale(500), 0.6);
+        assert_close(schedule.scale(1000), 0.2);
+        assert_close(schedule.scale(5000), 0.2);
+    }
+
+    #[test]
+    fn warmup_then_decay() {
+        let schedule = LrSchedule::WarmupDecay {
+            warmup_steps: 100,
+            final_scale: 0.1,
+            steps: 1100,
+        };
+        // The first step already has a small learning rate, and the ramp ends at the base rate.
+        assert_close(schedule.scale(0), 0.01);
+        assert_close(schedule.scale(49), 0.5);
+        assert_close(schedule.scale(99), 1.0);
+        assert_close(schedule.scale(100), 1.0);
+        assert_close(schedule.scale(1100), 0.1);
+        assert_close(schedule.scale(2100), 0.01);
+
+        // A warmup longer than the schedule still ends up at the base rate.
+        let schedule = LrSchedule::WarmupDecay {
+            warmup_steps: 100,
+            final_scale: 0.1,
+            steps: 50,
+        };
+        assert_close(schedule.scale(99), 1.0);
+        assert_close(schedule.scale(100), 1.0);
+        assert_close(schedule.scale(101), 0.1);
+    }
+}
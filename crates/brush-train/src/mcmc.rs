@@ -0,0 +1,343 @@
+// Densification as in "3D Gaussian Splatting as Markov Chain Monte Carlo".
+//
+// Instead of splitting, cloning and pruning splats by heuristics, splats are treated as samples.
+// Noise is added to the means of transparent splats so they explore the scene, dead splats are
+// moved to where live splats are, and new splats are added as copies of existing ones up to a
+// fixed maximum. Copies get their opacity and scale adjusted so the rendered image stays
+// (approximately) the same.
+use brush_render::{
+    gaussian_splats::{inverse_sigmoid, Splats},
+    AutodiffBackend,
+};
+use burn::{
+    config::Config,
+    prelude::Backend,
+    tensor::{Bool, Int, Tensor, TensorData},
+};
+use rand::Rng;
+
+use crate::train::{map_adam_state, quaternion_vec_multiply, OptimRecord, RefineStats};
+
+#[derive(Config)]
+pub struct McmcConfig {
+    /// Maximum number of splats. Training never grows past this, so memory use is bounded.
+    #[config(default = 1000000)]
+    pub max_splats: usize,
+    /// Fraction of splats added at every refinement, until the maximum is reached.
+    #[config(default = 0.05)]
+    pub growth_rate: f32,
+    /// Splats below this opacity are dead, and are moved to where live splats are.
+    #[config(default = 0.005)]
+    pub min_opacity: f32,
+    /// Scale of the noise added to the means, relative to the learning rate of the means.
+    #[config(default = 5e5)]
+    pub noise_lr: f64,
+}
+
+// Copies beyond this many don't change the relocated opacity and scale meaningfully.
+const MAX_COPIES: u32 = 51;
+
+/// Sample `count` indices with replacement, with probabilities proportional to the weights.
+fn sample_weighted(weights: &[f32], count: usize, rng: &mut impl Rng) -> Vec<usize> {
+    let cumulative: Vec<f64> = weights
+        .iter()
+        .scan(0.0, |sum, &w| {
+            *sum += w.max(0.0) as f64;
+            Some(*sum)
+        })
+        .collect();
+
+    let total = cumulative.last().copied().unwrap_or(0.0);
+    if total <= 0.0 {
+        return vec![];
+    }
+
+    (0..count)
+        .map(|_| {
+            let x = rng.gen_range(0.0..total);
+            cumulative.partition_point(|&c| c <= x)
+        })
+        .collect()
+}
+
+/// The opacity and scale factor for each of `count` copies of a splat, such that the copies
+/// rendered on top of each other look like the original splat.
+fn relocation(opacity: f32, count: u32, min_opacity: f32) -> (f32, f32) {
+    let count = count.min(MAX_COPIES);
+    let opacity = opacity as f64;
+    let new_opacity =
+        (1.0 - (1.0 - opacity).powf(1.0 / count as f64)).clamp(min_opacity as f64, 1.0 - 1e-6);
+
+    let mut denom = 0.0;
+    for i in 1..=count {
+        // Binomial coefficients of row i - 1.
+        let mut binom = 1.0;
+        for k in 0..i {
+            let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+            denom += binom * sign / ((k + 1) as f64).sqrt() * new_opacity.powi(k as i32 + 1);
+            binom = binom * (i - 1 - k) as f64 / (k + 1) as f64;
+        }
+    }
+
+    (new_opacity as f32, (opacity / denom) as f32)
+}
+
+/// Move dead splats onto live ones, and add new copies of live splats up to the maximum count.
+///
+/// Splats are picked with a probability proportional to their opacity. The optimizer state of
+/// every splat that was copied is reset.
+pub(crate) async fn relocate_and_add<B: AutodiffBackend>(
+    splats: Splats<B>,
+    record: &mut OptimRecord<B>,
+    config: &McmcConfig,
+    rng: &mut impl Rng,
+) -> (Splats<B>, RefineStats) {
+    let device = splats.means.device();
+
+    let opacities: Vec<f32> = splats
+        .opacity()
+        .into_data_async()
+        .await
+        .to_vec()
+        .expect("Opacities must be f32");
+    let num_splats = opacities.len();
+
+    // Only live splats can be copied. NaN opacities count as dead.
+    let weights: Vec<f32> = opacities
+        .iter()
+        .map(|&o| if o > config.min_opacity { o } else { 0.0 })
+        .collect();
+
+    // For each splat after refining, which splat it's a copy of.
+    let mut sources: Vec<usize> = (0..num_splats).collect();
+    // How many splats end up as a copy of each of the current splats.
+    let mut copies = vec![1; num_splats];
+
+    let dead: Vec<usize> = (0..num_splats).filter(|&i| weights[i] == 0.0).collect();
+    let picked = sample_weighted(&weights, dead.len(), rng);
+    for (&index, &source) in dead.iter().zip(&picked) {
+        sources[index] = source;
+        copies[source] += 1;
+    }
+    let num_relocated = picked.len();
+
+    let target = ((num_splats as f32 * (1.0 + config.growth_rate)) as usize).min(config.max_splats);
+    let picked = sample_weighted(&weights, target.saturating_sub(num_splats), rng);
+    for &source in &picked {
+        sources.push(source);
+        copies[source] += 1;
+    }
+    let num_added = picked.len();
+
+    if num_relocated == 0 && num_added == 0 {
+        let stats = RefineStats {
+            num_split: 0,
+            num_cloned: 0,
+            num_relocated: 0,
+            num_rejected: 0,
+            num_transparent_pruned: 0,
+            num_scale_pruned: 0,
+        };
+        return (splats, stats);
+    }
+
+    // The opacity of copied splats is replaced. Other splats keep their raw opacity as is, going
+    // through the sigmoid and back isn't exact, and gives infinities for opaque splats.
+    let mut copied = Vec::with_capacity(sources.len());
+    let mut raw_opacities = Vec::with_capacity(sources.len());
+    let mut log_scale_offsets = Vec::with_capacity(sources.len());
+    let mut keep_state = Vec::with_capacity(sources.len());
+    for &source in &sources {
+        if copies[source] > 1 {
+            let (opacity, scale) =
+                relocation(opacities[source], copies[source], config.min_opacity);
+            copied.push(true);
+            raw_opacities.push(inverse_sigmoid(opacity));
+            log_scale_offsets.push(scale.ln());
+            keep_state.push(0.0);
+        } else {
+            copied.push(false);
+            raw_opacities.push(0.0);
+            log_scale_offsets.push(0.0);
+            keep_state.push(1.0);
+        }
+    }
+
+    let count = sources.len();
+    let sources: Vec<i32> = sources.into_iter().map(|s| s as i32).collect();
+    let sources = Tensor::<B, 1, Int>::from_data(TensorData::new(sources, [count]), &device);
+    let copied = Tensor::<B, 1, Bool>::from_data(TensorData::new(copied, [count]), &device);
+    let raw_opacities = Tensor::<B, 1>::from_data(TensorData::new(raw_opacities, [count]), &device);
+    let log_scale_offsets =
+        Tensor::<B, 1>::from_data(TensorData::new(log_scale_offsets, [count]), &device);
+
+    let mut splats = splats;
+    Splats::map_param(&mut splats.means, |m| m.select(0, sources.clone()));
+    Splats::map_param(&mut splats.rotation, |m| m.select(0, sources.clone()));
+    Splats::map_param(&mut splats.sh_coeffs, |m| m.select(0, sources.clone()));
+    Splats::map_param(&mut splats.raw_opacity, |m| {
+        m.select(0, sources.clone())
+            .mask_where(copied.clone(), raw_opacities.clone())
+    });
+    Splats::map_param(&mut splats.log_scales, |m| {
+        m.select(0, sources.clone()) + log_scale_offsets.clone().unsqueeze_dim(1)
+    });
+
+    // Copied splats start over with a fresh optimizer state.
+    let sources = sources.inner();
+    let keep_state =
+        Tensor::<B::InnerBackend, 1>::from_data(TensorData::new(keep_state, [count]), &device);
+    map_adam_state::<B, 2>(record, splats.means.id, |m| {
+        relocate_moment(m, sources.clone(), keep_state.clone())
+    });
+    map_adam_state::<B, 2>(record, splats.rotation.id, |m| {
+        relocate_moment(m, sources.clone(), keep_state.clone())
+    });
+    map_adam_state::<B, 3>(record, splats.sh_coeffs.id, |m| {
+        relocate_moment(m, sources.clone(), keep_state.clone())
+    });
+    map_adam_state::<B, 1>(record, splats.raw_opacity.id, |m| {
+        relocate_moment(m, sources.clone(), keep_state.clone())
+    });
+    map_adam_state::<B, 2>(record, splats.log_scales.id, |m| {
+        relocate_moment(m, sources.clone(), keep_state.clone())
+    });
+
+    let stats = RefineStats {
+        num_split: 0,
+        num_cloned: num_added,
+        num_relocated,
+        num_rejected: 0,
+        num_transparent_pruned: 0,
+        num_scale_pruned: 0,
+    };
+
+    (splats, stats)
+}
+
+fn relocate_moment<B: Backend, const D: usize>(
+    m: Tensor<B, D>,
+    sources: Tensor<B, 1, Int>,
+    keep: Tensor<B, 1>,
+) -> Tensor<B, D> {
+    let mut shape = [1; D];
+    shape[0] = keep.dims()[0];
+    m.select(0, sources) * keep.reshape(shape)
+}
+
+/// Samples of a standard normal distribution, with the Box-Muller transform.
+fn normal_samples(count: usize, rng: &mut impl Rng) -> Vec<f32> {
+    let mut samples = Vec::with_capacity(count + 1);
+    while samples.len() < count {
+        // Stay away from zero, which has an infinite log.
+        let u1: f32 = rng.gen_range(f32::EPSILON..1.0);
+        let u2: f32 = rng.gen();
+        let radius = (-2.0 * u1.ln()).sqrt();
+        let (sin, cos) = (std::f32::consts::TAU * u2).sin_cos();
+        samples.extend([radius * cos, radius * sin]);
+    }
+    samples.truncate(count);
+    samples
+}
+
+/// Add noise to the means, shaped like the covariance of each splat. Nearly only transparent
+/// splats move, so they can explore the scene without disturbing the splats that matter.
+///
+/// The noise is drawn from `rng` rather than the random generator of the backend, so it
+/// doesn't depend on what else the backend generated before.
+pub(crate) fn inject_noise<B: AutodiffBackend>(
+    splats: Splats<B>,
+    config: &McmcConfig,
+    lr_mean: f64,
+    rng: &mut impl Rng,
+) -> Splats<B> {
+    let device = splats.means.device();
+    let num_splats = splats.num_splats();
+
+    // A steep sigmoid, which is ~1 for opacities below 0.005, and ~0 above.
+    let opacity = splats.opacity().detach();
+    let gate = sigmoid_steep(opacity.neg() + 1.0 - 0.995);
+
+    let noise = normal_samples(num_splats * 3, rng);
+    let noise = Tensor::<B, 2>::from_data(TensorData::new(noise, [num_splats, 3]), &device)
+        * gate.unsqueeze_dim(1)
+        * (lr_mean * config.noise_lr);
+
+    // Multiply by the covariance, R S^2 R^T.
+    let rotation = splats.rotation.val().detach();
+    let conjugate = Tensor::<B, 2>::from_floats([[1.0, -1.0, -1.0, -1.0]], &device);
+    let local = quaternion_vec_multiply(rotation.clone() * conjugate, noise);
+    let local = local * (splats.log_scales.val().detach() * 2.0).exp();
+    let noise = quaternion_vec_multiply(rotation, local);
+
+    let mut splats = splats;
+    Splats::map_param(&mut splats.means, |m| m + noise.clone());
+    splats
+}
+
+fn sigmoid_steep<B: Backend>(x: Tensor<B, 1>) -> Tensor<B, 1> {
+    ((x * -100.0).exp() + 1.0).recip()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{relocate_and_add, relocation, McmcConfig};
+    use brush_render::gaussian_splats::Splats;
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+    use glam::Vec3;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    type Back = Autodiff<NdArray>;
+
+    #[test]
+    fn relocation_keeps_total_opacity() {
+        // A single copy is the splat itself.
+        let (opacity, scale) = relocation(0.7, 1, 0.005);
+        assert!((opacity - 0.7).abs() < 1e-6, "{opacity}");
+        assert!((scale - 1.0).abs() < 1e-5, "{scale}");
+
+        for count in [2, 3, 10] {
+            let (opacity, scale) = relocation(0.7, count, 0.005);
+            // The copies blended on top of each other are as opaque as the original.
+            let total = 1.0 - (1.0 - opacity).powi(count as i32);
+            assert!((total - 0.7).abs() < 1e-4, "{count}: {total}");
+            // And each copy is smaller, so they cover about the same area.
+            assert!(scale > 0.0 && scale < 1.0, "{count}: {scale}");
+        }
+
+        // The opacity stays a valid probability, even for fully opaque splats.
+        let (opacity, _) = relocation(1.0, 2, 0.005);
+        assert!(opacity < 1.0, "{opacity}");
+        let (opacity, _) = relocation(1e-4, 100, 0.005);
+        assert_eq!(opacity, 0.005);
+    }
+
+    #[tokio::test]
+    async fn relocate_keeps_raw_opacity_of_untouched_splats() {
+        let device = NdArrayDevice::Cpu;
+        // Two opaque splats, the sigmoid of which rounds to exactly one, and a dead splat.
+        let raw_opacities = vec![30.0, 25.0, -20.0];
+        let splats = Splats::<Back>::from_raw(
+            vec![Vec3::ZERO, Vec3::X, Vec3::Y],
+            None,
+            None,
+            None,
+            Some(raw_opacities.clone()),
+            &device,
+        );
+        let config = McmcConfig::new().with_growth_rate(0.0);
+        let mut rng = StdRng::seed_from_u64(0);
+        let (splats, stats) =
+            relocate_and_add(splats, &mut Default::default(), &config, &mut rng).await;
+        assert_eq!(stats.num_relocated, 1);
+        assert_eq!(stats.num_cloned, 0);
+
+        let raw: Vec<f32> = splats.raw_opacity.val().into_data().to_vec().unwrap();
+        assert!(raw.iter().all(|o| o.is_finite()), "{raw:?}");
+        // The dead splat became a copy of one of the opaque splats, the other one is untouched.
+        let copied = if raw[0] == raw_opacities[0] { 1 } else { 0 };
+        assert_eq!(raw[1 - copied], raw_opacities[1 - copied]);
+        assert_eq!(raw[copied], raw[2]);
+        assert!(raw[copied] < raw_opacities[copied]);
+    }
+}
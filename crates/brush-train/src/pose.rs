@@ -0,0 +1,277 @@
+// Refinement of the camera poses of the training views. Poses from structure from motion are
+// often slightly off, which the splats can only make up for by getting blurry. Instead, each
+// training view learns a small correction of its rotation and translation, and optionally of its
+// focal length, along with the splats.
+//
+// The corrections are applied in camera space, see `Camera::perturbed`. The renderer calculates
+// the gradient of such a perturbation through the camera dummy of the splats.
+use brush_render::camera::Camera;
+use burn::{
+    module::{Module, Param, ParamId},
+    prelude::Backend,
+    tensor::{Tensor, TensorData},
+};
+use glam::{Vec2, Vec3};
+
+/// The correction of the camera of a single view.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PoseDelta {
+    /// Rotation in camera space, as a scaled axis.
+    pub rotation: Vec3,
+    /// Translation in camera space.
+    pub translation: Vec3,
+    /// Log of the factor the focal length is scaled by.
+    pub log_focal_scale: f32,
+}
+
+impl PoseDelta {
+    /// The refined camera.
+    pub fn apply(&self, camera: &Camera) -> Camera {
+        camera
+            .perturbed(self.rotation, self.translation)
+            .with_focal_scale((self.log_focal_scale as f64).exp())
+    }
+
+    /// Convert the camera gradient of a render with the refined camera, as calculated by
+    /// `Backend::render_splats`, to the gradient of this delta. `focal` is the focal length of
+    /// the refined camera in pixels.
+    ///
+    /// Returns the gradients of the rotation, translation and log focal scale.
+    pub fn gradient(&self, v_camera: &[f32], focal: Vec2) -> (Vec3, Vec3, f32) {
+        let v_rotation = Vec3::from_slice(&v_camera[0..3]);
+        let v_translation = Vec3::from_slice(&v_camera[3..6]);
+        // Rotating the refined camera also rotates the translation of the delta, while changing
+        // the rotation of the delta doesn't. This ignores the curvature of the rotations, which
+        // is fine for small corrections.
+        let v_rotation = v_rotation + v_translation.cross(self.translation);
+        let v_log_focal = v_camera[6] * focal.x + v_camera[7] * focal.y;
+        (v_rotation, v_translation, v_log_focal)
+    }
+}
+
+/// Learned corrections of the cameras of the training views.
+#[derive(Module, Debug)]
+pub struct ViewPoses<B: Backend> {
+    /// Rotation of each view as a [num_views, 3] scaled axis.
+    pub rotations: Param<Tensor<B, 2>>,
+    /// Translation of each view, [num_views, 3].
+    pub translations: Param<Tensor<B, 2>>,
+    /// Log of the focal length scale of each view, [num_views].
+    pub log_focal_scales: Param<Tensor<B, 1>>,
+}
+
+impl<B: Backend> ViewPoses<B> {
+    /// Poses which don't change the cameras yet.
+    pub fn new(num_views: usize, device: &B::Device) -> Self {
+        Self::from_tensors(
+            Tensor::zeros([num_views, 3], device),
+            Tensor::zeros([num_views, 3], device),
+            Tensor::zeros([num_views], device),
+        )
+    }
+
+    pub fn from_tensors(
+        rotations: Tensor<B, 2>,
+        translations: Tensor<B, 2>,
+        log_focal_scales: Tensor<B, 1>,
+    ) -> Self {
+        Self {
+            rotations: Param::initialized(ParamId::new(), rotations.detach().require_grad()),
+            translations: Param::initialized(ParamId::new(), translations.detach().require_grad()),
+            log_focal_scales: Param::initialized(
+                ParamId::new(),
+                log_focal_scales.detach().require_grad(),
+            ),
+        }
+    }
+
+    pub fn num_views(&self) -> usize {
+        self.rotations.dims()[0]
+    }
+
+    /// Read back the correction of every view.
+    pub async fn deltas(&self) -> Vec<PoseDelta> {
+        let read = |t: Tensor<B, 1>| async move {
+            t.into_data_async()
+                .await
+                .to_vec::<f32>()
+                .expect("Poses must be f32")
+        };
+        let num_views = self.num_views();
+        let rotations = read(self.rotations.val().reshape([num_views * 3])).await;
+        let translations = read(self.translations.val().reshape([num_views * 3])).await;
+        let log_focal_scales = read(self.log_focal_scales.val()).await;
+
+        (0..num_views)
+            .map(|i| PoseDelta {
+                rotation: Vec3::from_slice(&rotations[i * 3..i * 3 + 3]),
+                translation: Vec3::from_slice(&translations[i * 3..i * 3 + 3]),
+                log_focal_scale: log_focal_scales[i],
+            })
+            .collect()
+    }
+}
+
+/// The gradients of the rotations, translations and focal scales of all views, from the
+/// gradients of some of the views. Views that appear multiple times have their gradients summed.
+pub fn pose_gradients<B: Backend>(
+    num_views: usize,
+    view_grads: &[(usize, (Vec3, Vec3, f32))],
+    device: &B::Device,
+) -> (Tensor<B, 2>, Tensor<B, 2>, Tensor<B, 1>) {
+    let mut v_rotations = vec![0.0; num_views * 3];
+    let mut v_translations = vec![0.0; num_views * 3];
+    let mut v_log_focal_scales = vec![0.0; num_views];
+
+    for &(view, (v_rotation, v_translation, v_log_focal)) in view_grads {
+        for i in 0..3 {
+            v_rotations[view * 3 + i] += v_rotation[i];
+            v_translations[view * 3 + i] += v_translation[i];
+        }
+        v_log_focal_scales[view] += v_log_focal;
+    }
+
+    (
+        Tensor::from_data(TensorData::new(v_rotations, [num_views, 3]), device),
+        Tensor::from_data(TensorData::new(v_translations, [num_views, 3]), device),
+        Tensor::from_data(TensorData::new(v_log_focal_scales, [num_views]), device),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pose_gradients, PoseDelta, ViewPoses};
+    use brush_render::camera::Camera;
+    use burn::backend::{ndarray::NdArrayDevice, Autodiff, NdArray};
+    use burn::optim::{AdamConfig, GradientsParams, Optimizer};
+    use burn::tensor::Tensor;
+    use glam::{vec2, vec3, Quat, Vec3};
+
+    type Back = Autodiff<NdArray>;
+
+    fn camera() -> Camera {
+        Camera::new(
+            vec3(1.0, 2.0, -3.0),
+            Quat::from_rotation_y(0.3),
+            0.8,
+            0.6,
+            vec2(0.5, 0.5),
+        )
+    }
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-4), "{a} != {b}");
+    }
+
+    #[test]
+    fn delta_moves_camera_space_points() {
+        let camera = camera();
+        let point = vec3(0.5, -1.0, 4.0);
+        let local = camera.world_to_local().transform_point3(point);
+
+        let delta = PoseDelta {
+            rotation: vec3(0.0, 0.0, 0.1),
+            translation: vec3(0.2, 0.0, -0.1),
+            log_focal_scale: 2.0f32.ln(),
+        };
+        let refined = delta.apply(&camera);
+        let expected = Quat::from_scaled_axis(delta.rotation) * local + delta.translation;
+        assert_close(refined.world_to_local().transform_point3(point), expected);
+
+        let img_size = glam::uvec2(64, 48);
+        let focal = refined.focal(img_size) / camera.focal(img_size);
+        assert!(focal.abs_diff_eq(vec2(2.0, 2.0), 1e-4), "{focal}");
+
+        // The default delta leaves the camera alone.
+        let same = PoseDelta::default().apply(&camera);
+        assert_close(same.position, camera.position);
+        assert!(same.rotation.abs_diff_eq(camera.rotation, 1e-5));
+        assert!((same.fov_x - camera.fov_x).abs() < 1e-9);
+    }
+
+    #[test]
+    fn delta_gradient() {
+        let v_camera = [1.0, 2.0, 3.0, 0.5, -0.5, 0.25, 0.1, 0.2];
+        let (v_rotation, v_translation, v_log_focal) =
+            PoseDelta::default().gradient(&v_camera, vec2(100.0, 50.0));
+        assert_eq!(v_rotation, vec3(1.0, 2.0, 3.0));
+        assert_eq!(v_translation, vec3(0.5, -0.5, 0.25));
+        assert!((v_log_focal - 20.0).abs() < 1e-4, "{v_log_focal}");
+
+        // With a translation, the rotation also gets the gradient of rotating the translation.
+        let delta = PoseDelta {
+            translation: vec3(0.0, 0.0, 1.0),
+            ..Default::default()
+        };
+        let (v_rotation, _, _) = delta.gradient(&v_camera, vec2(100.0, 50.0));
+        assert_close(v_rotation, vec3(1.0 - 0.5, 2.0 - 0.5, 3.0));
+    }
+
+    #[tokio::test]
+    async fn view_poses_round_trip() {
+        let device = NdArrayDevice::Cpu;
+        let poses = ViewPoses::<Back>::new(3, &device);
+        assert_eq!(poses.num_views(), 3);
+        let deltas = poses.deltas().await;
+        assert_eq!(deltas.len(), 3);
+        assert!(deltas
+            .iter()
+            .all(|d| d.rotation == Vec3::ZERO && d.translation == Vec3::ZERO));
+
+        let poses = ViewPoses::<Back>::from_tensors(
+            Tensor::from_floats([[0.0, 0.0, 0.0], [0.1, 0.2, 0.3]], &device),
+            Tensor::from_floats([[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]], &device),
+            Tensor::from_floats([0.0, 0.5], &device),
+        );
+        let deltas = poses.deltas().await;
+        assert_eq!(deltas[1].rotation, vec3(0.1, 0.2, 0.3));
+        assert_eq!(deltas[1].translation, vec3(4.0, 5.0, 6.0));
+        assert_eq!(deltas[1].log_focal_scale, 0.5);
+        assert_eq!(deltas[0].translation, vec3(1.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn gradients_sum_per_view() {
+        let grad = (vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0), 1.0);
+        let (v_rotations, v_translations, v_focals) =
+            pose_gradients::<NdArray>(3, &[(2, grad), (0, grad), (2, grad)], &NdArrayDevice::Cpu);
+        let v_rotations: Vec<f32> = v_rotations.into_data().to_vec().unwrap();
+        let v_translations: Vec<f32> = v_translations.into_data().to_vec().unwrap();
+        let v_focals: Vec<f32> = v_focals.into_data().to_vec().unwrap();
+        assert_eq!(v_rotations, [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0]);
+        assert_eq!(
+            v_translations,
+            [0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0]
+        );
+        assert_eq!(v_focals, [1.0, 0.0, 2.0]);
+    }
+
+    #[tokio::test]
+    async fn optimizer_step_moves_against_the_gradient() {
+        // The same way the trainer steps the poses, one parameter at a time.
+        let device = NdArrayDevice::Cpu;
+        let mut optim = AdamConfig::new()
+            .with_epsilon(1e-15)
+            .init::<Back, ViewPoses<Back>>();
+        let poses = ViewPoses::<Back>::new(2, &device);
+
+        let grad = (vec3(1.0, -1.0, 0.0), vec3(0.0, 0.0, 2.0), 1.0);
+        let (v_rotations, v_translations, _) = pose_gradients::<NdArray>(2, &[(1, grad)], &device);
+
+        let mut grads = GradientsParams::new();
+        grads.register(poses.rotations.id, v_rotations);
+        let poses = optim.step(0.01, poses, grads);
+        let mut grads = GradientsParams::new();
+        grads.register(poses.translations.id, v_translations);
+        let poses = optim.step(0.1, poses, grads);
+
+        let deltas = poses.deltas().await;
+        // Adam steps by about the learning rate, against the sign of the gradient.
+        assert_close(deltas[1].rotation, vec3(-0.01, 0.01, 0.0));
+        assert_close(deltas[1].translation, vec3(0.0, 0.0, -0.1));
+        // Views without a gradient and parameters that weren't stepped don't move.
+        assert_close(deltas[0].rotation, Vec3::ZERO);
+        assert_close(deltas[0].translation, Vec3::ZERO);
+        assert_eq!(deltas[1].log_focal_scale, 0.0);
+    }
+}
@@ -0,0 +1,459 @@
+// Refinement adds and removes splats during training. How that's done is up to a
+// [`RefineStrategy`], so densification can be experimented with without changing the trainer.
+use std::{future::Future, pin::Pin};
+
+use brush_render::{
+    gaussian_splats::{inverse_sigmoid, Splats},
+    AutodiffBackend,
+};
+use burn::{
+    prelude::Backend,
+    tensor::{Bool, Distribution, Int, Tensor, TensorData},
+};
+
+use crate::{
+    mcmc::{self, McmcConfig},
+    train::{
+        concat_splats, map_adam_state, prune_points, quaternion_vec_multiply, step_rng,
+        OptimRecord, RandomStream, RefineStats,
+    },
+};
+
+/// Everything a [`RefineStrategy`] gets to see when refining.
+pub struct RefineContext<'a, B: AutodiffBackend> {
+    /// The current iteration.
+    pub iter: u32,
+    /// The splats before the optimizer step of this iteration.
+    pub pre_step: Splats<B>,
+    /// The splats after the optimizer step of this iteration.
+    pub post_step: Splats<B>,
+    /// The screenspace gradient norm of each splat, summed over the views it was visible in
+    /// since the last refinement.
+    pub grad_2d_accum: Tensor<B, 1>,
+    /// The number of views each splat was visible in since the last refinement.
+    pub visible_counts: Tensor<B, 1, Int>,
+    /// The fraction of the screen each splat covered, summed over the views it was visible in
+    /// since the last refinement.
+    pub coverage_accum: Tensor<B, 1>,
+    /// The state of the optimizer. This has to be kept in line with the splats, eg. by
+    /// using [`prune_points`] and [`concat_splats`].
+    pub record: &'a mut OptimRecord<B>,
+}
+
+pub type RefineFuture<'a, B> = Pin<Box<dyn Future<Output = (Splats<B>, RefineStats)> + Send + 'a>>;
+
+/// Decides which splats to add and remove during training.
+///
+/// The trainer calls `refine` every `refine_every` steps, between `warmup_steps` and
+/// `max_refine_step`. Afterwards, the gradient statistics are reset, and the optimizer
+/// continues with the returned splats and optimizer state.
+pub trait RefineStrategy<B: AutodiffBackend>: Send {
+    /// Refine the splats. This returns a boxed future so the strategy can be swapped at
+    /// runtime, implementations can just return `Box::pin(async move { ... })`.
+    fn refine<'a>(&'a mut self, ctx: RefineContext<'a, B>) -> RefineFuture<'a, B>;
+
+    /// Called after every optimizer step, with the learning rate of the means.
+    fn after_step(&mut self, splats: Splats<B>, _iter: u32, _lr_mean: f64) -> Splats<B> {
+        splats
+    }
+}
+
+/// Set the opacity of all splats to the same value.
+pub fn reset_opacity<B: AutodiffBackend>(
+    splats: &mut Splats<B>,
+    record: &mut OptimRecord<B>,
+    opacity: f32,
+) {
+    Splats::map_param(&mut splats.raw_opacity, |op| {
+        Tensor::zeros_like(&op) + inverse_sigmoid(opacity)
+    });
+    // Old momentum would just push the opacities back to where they were.
+    map_adam_state::<B, 1>(record, splats.raw_opacity.id, |m| Tensor::zeros_like(&m));
+}
+
+/// A limit on the number of splats, which grows over the course of training.
+#[derive(Debug, Clone)]
+pub struct SplatBudget {
+    /// The number of splats at the start of training.
+    pub start_count: usize,
+    /// The number of splats training never grows past.
+    pub max_splats: usize,
+    /// The step where the budget starts to grow from `start_count`.
+    pub growth_start: u32,
+    /// The step where the budget reaches `max_splats`.
+    pub growth_end: u32,
+}
+
+impl SplatBudget {
+    /// The maximum number of splats at the given step.
+    pub fn max_at(&self, iter: u32) -> usize {
+        let start = self.start_count.min(self.max_splats);
+        let duration = self.growth_end.saturating_sub(self.growth_start).max(1);
+        let t = (iter.saturating_sub(self.growth_start) as f64 / duration as f64).min(1.0);
+        start + ((self.max_splats - start) as f64 * t) as usize
+    }
+}
+
+/// Keep only the `room` candidates with the highest importance. Returns the new candidates,
+/// and how many were rejected.
+async fn limit_candidates<B: Backend>(
+    candidates: Tensor<B, 1, Bool>,
+    importance: Tensor<B, 1>,
+    room: usize,
+) -> (Tensor<B, 1, Bool>, usize) {
+    let device = candidates.device();
+    let is_candidate: Vec<bool> = candidates
+        .clone()
+        .into_data_async()
+        .await
+        .to_vec()
+        .expect("Mask must be bool");
+
+    let mut inds: Vec<usize> = (0..is_candidate.len())
+        .filter(|&i| is_candidate[i])
+        .collect();
+    if inds.len() <= room {
+        return (candidates, 0);
+    }
+
+    let importance: Vec<f32> = importance
+        .into_data_async()
+        .await
+        .to_vec()
+        .expect("Importance must be f32");
+    inds.sort_unstable_by(|&a, &b| importance[b].total_cmp(&importance[a]));
+
+    let mut keep = vec![false; is_candidate.len()];
+    for &i in &inds[..room] {
+        keep[i] = true;
+    }
+    let count = keep.len();
+    let keep = Tensor::from_data(TensorData::new(keep, [count]), &device);
+    (keep, inds.len() - room)
+}
+
+/// The refinement of the original 3DGS. Splats with a large screenspace gradient are cloned
+/// when small and split when large. Transparent and huge splats are pruned, and the opacity
+/// of all splats is reset periodically.
+#[derive(Debug, Clone)]
+pub struct HeuristicRefine {
+    /// Threshold of the average screenspace gradient norm for densifying splats.
+    pub grad_thresh: f32,
+    /// Below this size splats are cloned, otherwise split.
+    pub size_thresh: f32,
+    /// Splats below this opacity are pruned.
+    pub cull_alpha_thresh: f32,
+    /// Splats larger than this are pruned.
+    pub cull_scale_thresh: f32,
+    /// The opacity all splats are reset to.
+    pub reset_alpha_value: f32,
+    /// Reset the opacity every this many refinements.
+    pub reset_alpha_every_refine: u32,
+    /// Steps between refinements, to count the refinements.
+    pub refine_every: u32,
+    /// Split splats are scaled down by this factor.
+    pub split_divisor: f32,
+    /// Standard deviation of the offset of split splats, relative to their scale.
+    pub split_sigma: f64,
+    /// When set, candidates are ranked by importance once the budget is reached, and only as
+    /// many are densified as fit in the budget.
+    pub budget: Option<SplatBudget>,
+}
+
+impl<B: AutodiffBackend> RefineStrategy<B> for HeuristicRefine {
+    fn refine<'a>(&'a mut self, ctx: RefineContext<'a, B>) -> RefineFuture<'a, B> {
+        Box::pin(async move {
+            let device = ctx.pre_step.means.device();
+
+            let mut splats_pre_step = ctx.pre_step;
+            let mut splats_post_step = ctx.post_step.clone();
+
+            // Otherwise, do refinement, but do the split/clone on gaussians with no grads applied.
+            let counts = ctx.visible_counts.clamp_min(1).float();
+            let grads = ctx.grad_2d_accum / counts.clone();
+
+            let big_grad_mask = grads.clone().greater_equal_elem(self.grad_thresh);
+
+            let mut num_rejected = 0;
+            let big_grad_mask = if let Some(budget) = &self.budget {
+                let room = budget
+                    .max_at(ctx.iter)
+                    .saturating_sub(splats_post_step.num_splats());
+                // Splats which matter most for the image: ones with a large gradient, which
+                // are opaque, and cover a large part of the screen.
+                let importance = grads * splats_post_step.opacity() * (ctx.coverage_accum / counts);
+                let (mask, rejected) = limit_candidates(big_grad_mask, importance, room).await;
+                num_rejected = rejected;
+                mask
+            } else {
+                big_grad_mask
+            };
+            let split_clone_size_mask = splats_post_step
+                .scales()
+                .max_dim(1)
+                .squeeze(1)
+                .lower_elem(self.size_thresh);
+
+            let mut append_means = vec![];
+            let mut append_rots = vec![];
+            let mut append_coeffs = vec![];
+            let mut append_opac = vec![];
+            let mut append_scales = vec![];
+
+            let clone_inds = Tensor::stack::<2>(
+                vec![split_clone_size_mask.clone(), big_grad_mask.clone()],
+                1,
+            )
+            .all_dim(1)
+            .squeeze::<1>(1)
+            .argwhere_async()
+            .await;
+
+            // Clone splats
+            let clone_count = clone_inds.dims()[0];
+            if clone_count > 0 {
+                let clone_inds = clone_inds.squeeze(1);
+                append_means.push(splats_pre_step.means.val().select(0, clone_inds.clone()));
+                append_rots.push(splats_pre_step.rotation.val().select(0, clone_inds.clone()));
+                append_coeffs.push(
+                    splats_pre_step
+                        .sh_coeffs
+                        .val()
+                        .select(0, clone_inds.clone()),
+                );
+                append_opac.push(
+                    splats_pre_step
+                        .raw_opacity
+                        .val()
+                        .select(0, clone_inds.clone()),
+                );
+                append_scales.push(
+                    splats_pre_step
+                        .log_scales
+                        .val()
+                        .select(0, clone_inds.clone()),
+                );
+            }
+
+            // Split splats.
+            let split_mask =
+                Tensor::stack::<2>(vec![split_clone_size_mask.bool_not(), big_grad_mask], 1)
+                    .all_dim(1);
+            let split_inds = split_mask.clone().squeeze::<1>(1).argwhere_async().await;
+            let split_count = split_inds.dims()[0];
+            if split_count > 0 {
+                let split_inds = split_inds.squeeze(1);
+
+                // Some parts can be straightforwardly copied to the new splats.
+                let cur_coeff = splats_post_step
+                    .sh_coeffs
+                    .val()
+                    .select(0, split_inds.clone());
+                let cur_raw_opac = splats_post_step
+                    .raw_opacity
+                    .val()
+                    .select(0, split_inds.clone());
+                let cur_rots = splats_post_step
+                    .rotation
+                    .val()
+                    .select(0, split_inds.clone());
+                append_rots.push(cur_rots.clone());
+                append_coeffs.push(cur_coeff.clone());
+                append_opac.push(cur_raw_opac);
+
+                // Change current scale to be lower.
+                let cur_scale = splats_post_step.scales().select(0, split_inds.clone());
+                Splats::map_param(&mut splats_post_step.log_scales, |m| {
+                    let div_scales = Tensor::zeros_like(&m).select_assign(
+                        0,
+                        split_inds.clone(),
+                        (cur_scale.clone() / self.split_divisor).log(),
+                    );
+                    m.mask_where(split_mask.clone(), div_scales)
+                });
+                // Append newer smaller scales.
+                append_scales.push((cur_scale.clone() / self.split_divisor).log());
+
+                // Sample new position for splits.
+                let cur_means = splats_pre_step.means.val().select(0, split_inds.clone());
+                let samples = quaternion_vec_multiply(
+                    cur_rots.clone(),
+                    Tensor::random(
+                        [split_count, 3],
+                        Distribution::Normal(0.0, self.split_sigma),
+                        &device,
+                    ) * cur_scale.clone(),
+                );
+                // Assign new means to current values.
+                Splats::map_param(&mut splats_pre_step.means, |m| {
+                    let offset_means = Tensor::zeros_like(&m).select_assign(
+                        0,
+                        split_inds.clone(),
+                        cur_means.clone() - samples.clone(),
+                    );
+                    m.mask_where(split_mask.clone(), offset_means)
+                });
+
+                // Append new means with offset sample.
+                let samples_new = quaternion_vec_multiply(
+                    cur_rots.clone(),
+                    Tensor::random(
+                        [split_count, 3],
+                        Distribution::Normal(0.0, self.split_sigma),
+                        &device,
+                    ) * cur_scale,
+                );
+                append_means.push(cur_means.clone() + samples_new);
+            }
+
+            // Do processing on splat post step.
+            let mut splats = ctx.post_step;
+            let record = ctx.record;
+
+            if !append_means.is_empty() {
+                let append_means = Tensor::cat(append_means, 0);
+                let append_rots = Tensor::cat(append_rots, 0);
+                let append_coeffs = Tensor::cat(append_coeffs, 0);
+                let append_opac = Tensor::cat(append_opac, 0);
+                let append_scales = Tensor::cat(append_scales, 0);
+
+                concat_splats(
+                    &mut splats,
+                    record,
+                    append_means,
+                    append_rots,
+                    append_coeffs,
+                    append_opac,
+                    append_scales,
+                );
+            }
+
+            // Do some more processing. Important to do this last as otherwise you might mess up the correspondence
+            // of gradient <-> splat.
+            let start_count = splats.num_splats();
+
+            // Remove barely visible gaussians.
+            let alpha_mask = splats.opacity().lower_elem(self.cull_alpha_thresh);
+            prune_points(&mut splats, record, alpha_mask).await;
+
+            let alpha_pruned = start_count - splats.num_splats();
+
+            // Delete Gaussians with too large of a radius in world-units.
+            let scale_mask = splats
+                .scales()
+                .max_dim(1)
+                .squeeze(1)
+                .greater_elem(self.cull_scale_thresh);
+            prune_points(&mut splats, record, scale_mask).await;
+
+            let scale_pruned = start_count - splats.num_splats();
+
+            let refine_step = ctx.iter / self.refine_every;
+            if refine_step % self.reset_alpha_every_refine == 0 {
+                reset_opacity(&mut splats, record, self.reset_alpha_value);
+            }
+
+            let stats = RefineStats {
+                num_split: split_count,
+                num_cloned: clone_count,
+                num_relocated: 0,
+                num_rejected,
+                num_transparent_pruned: alpha_pruned,
+                num_scale_pruned: scale_pruned,
+            };
+
+            (splats, stats)
+        })
+    }
+}
+
+/// The densification of 3DGS-MCMC, see the [`mcmc`] module.
+#[derive(Clone)]
+pub struct McmcRefine {
+    pub config: McmcConfig,
+    /// Seed for the noise and for picking the splats to copy, combined with the iteration.
+    pub seed: u64,
+}
+
+impl<B: AutodiffBackend> RefineStrategy<B> for McmcRefine {
+    fn refine<'a>(&'a mut self, ctx: RefineContext<'a, B>) -> RefineFuture<'a, B> {
+        Box::pin(async move {
+            let mut rng = step_rng(self.seed, ctx.iter, RandomStream::McmcRelocation);
+            mcmc::relocate_and_add(ctx.post_step, ctx.record, &self.config, &mut rng).await
+        })
+    }
+
+    fn after_step(&mut self, splats: Splats<B>, iter: u32, lr_mean: f64) -> Splats<B> {
+        let mut rng = step_rng(self.seed, iter, RandomStream::McmcNoise);
+        mcmc::inject_noise(splats, &self.config, lr_mean, &mut rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{limit_candidates, SplatBudget};
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+    use burn::tensor::{Bool, Tensor, TensorData};
+
+    #[test]
+    fn budget_grows_linearly() {
+        let budget = SplatBudget {
+            start_count: 100,
+            max_splats: 1100,
+            growth_start: 500,
+            growth_end: 1500,
+        };
+        assert_eq!(budget.max_at(0), 100);
+        assert_eq!(budget.max_at(500), 100);
+        assert_eq!(budget.max_at(1000), 600);
+        assert_eq!(budget.max_at(1500), 1100);
+        assert_eq!(budget.max_at(100000), 1100);
+
+        // Starting above the maximum never allows more than the maximum.
+        let budget = SplatBudget {
+            start_count: 2000,
+            ..budget
+        };
+        assert_eq!(budget.max_at(0), 1100);
+
+        // Growing in no time at all jumps straight to the maximum.
+        let budget = SplatBudget {
+            start_count: 100,
+            growth_end: 500,
+            ..budget
+        };
+        assert_eq!(budget.max_at(499), 100);
+        assert_eq!(budget.max_at(501), 1100);
+    }
+
+    async fn limit(candidates: [bool; 5], importance: [f32; 5], room: usize) -> (Vec<bool>, usize) {
+        let device = NdArrayDevice::Cpu;
+        let candidates = Tensor::<NdArray, 1, Bool>::from_data(
+            TensorData::new(candidates.to_vec(), [5]),
+            &device,
+        );
+        let importance = Tensor::<NdArray, 1>::from_floats(importance, &device);
+        let (kept, rejected) = limit_candidates(candidates, importance, room).await;
+        (kept.into_data().to_vec().unwrap(), rejected)
+    }
+
+    #[tokio::test]
+    async fn limit_candidates_keeps_most_important() {
+        let candidates = [true, false, true, true, true];
+        let importance = [0.1, 9.0, 0.4, 0.3, 0.2];
+
+        // Everything fits.
+        let (kept, rejected) = limit(candidates, importance, 4).await;
+        assert_eq!(kept, candidates);
+        assert_eq!(rejected, 0);
+
+        // Only the most important candidates, never splats that weren't candidates.
+        let (kept, rejected) = limit(candidates, importance, 2).await;
+        assert_eq!(kept, [false, false, true, true, false]);
+        assert_eq!(rejected, 2);
+
+        let (kept, rejected) = limit(candidates, importance, 0).await;
+        assert_eq!(kept, [false; 5]);
+        assert_eq!(rejected, 4);
+    }
+}
@@ -1,7 +1,11 @@
 use std::sync::Arc;
 
-use brush_render::{bounding_box::BoundingBox, camera::Camera};
-use glam::Vec3;
+use brush_render::{
+    bounding_box::BoundingBox,
+    camera::{Camera, Projection},
+};
+use burn::tensor::{backend::Backend, Tensor};
+use glam::{Mat3, Vec3};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum ViewType {
@@ -10,11 +14,20 @@ pub enum ViewType {
     Test,
 }
 
+/// A single channel depth map. Depths are distances along the camera axis in world units,
+/// pixels without a known depth are 0.
+pub type DepthImage = image::ImageBuffer<image::Luma<f32>, Vec<f32>>;
+
 #[derive(Debug, Clone)]
 pub struct SceneView {
     pub name: String,
     pub camera: Camera,
     pub image: Arc<image::DynamicImage>,
+    /// Optional depth map, with the same resolution as the image.
+    pub depth: Option<Arc<DepthImage>>,
+    /// Optional mask, with the same resolution as the image. Pixels where the mask is 0
+    /// are ignored in training.
+    pub mask: Option<Arc<image::GrayImage>>,
 }
 
 // Encapsulates a multi-view scene including cameras and the splats.
@@ -65,4 +78,135 @@ impl Scene {
             })
             .map(|(index, _)| index) // We return the index instead of the camera
     }
+
+    /// The highest rate any camera of the scene samples each point at, in pixels per world unit.
+    /// Points that no camera sees get the lowest rate of the points that are seen. If no point
+    /// is seen at all, they get the highest rate any camera could sample at, so they're barely
+    /// filtered.
+    pub fn max_sampling_rates<B: Backend>(&self, points: Tensor<B, 2>) -> Tensor<B, 1> {
+        let device = points.device();
+        let num_points = points.dims()[0];
+        let mut max_rate = Tensor::<B, 1>::zeros([num_points], &device);
+        let mut highest_rate = 0.0f32;
+
+        for view in self.views.iter() {
+            let camera = &view.camera;
+            let img_size = glam::uvec2(view.image.width(), view.image.height());
+            let focal = camera.focal(img_size);
+            let center = camera.center(img_size);
+
+            // Points are rows, so transform them with the transposed rotation.
+            let world_to_local = camera.world_to_local();
+            let rot_t = Mat3::from_mat4(world_to_local).to_cols_array_2d();
+            let rot_t = Tensor::<B, 2>::from_floats(rot_t, &device);
+            let translation = world_to_local.w_axis.truncate().to_array();
+            let translation = Tensor::<B, 2>::from_floats([translation], &device);
+            let points_cam = points.clone().matmul(rot_t) + translation;
+
+            let coord = |i: usize| -> Tensor<B, 1> {
+                points_cam
+                    .clone()
+                    .slice([0..num_points, i..i + 1])
+                    .squeeze(1)
+            };
+            let (x, y, z) = (coord(0), coord(1), coord(2));
+
+            // Whether a point in front of the camera lands on the image. Like Mip-Splatting,
+            // allow some margin around the image.
+            let (w, h) = (img_size.x as f32, img_size.y as f32);
+            let in_view = |px: Tensor<B, 1>, py: Tensor<B, 1>| {
+                let px = px * focal.x + center.x;
+                let py = py * focal.y + center.y;
+                z.clone().greater_elem(0.01).float()
+                    * px.clone().greater_elem(-0.15 * w).float()
+                    * px.lower_elem(1.15 * w).float()
+                    * py.clone().greater_elem(-0.15 * h).float()
+                    * py.lower_elem(1.15 * h).float()
+            };
+
+            // Distances are clamped to 0.01, which bounds the rate of each camera.
+            highest_rate = highest_rate.max(focal.max_element() / 0.01);
+
+            let rate = match camera.projection {
+                Projection::Pinhole => {
+                    let z_safe = z.clone().clamp_min(0.01);
+                    let in_view = in_view(x / z_safe.clone(), y / z_safe.clone());
+                    z_safe.recip() * focal.max_element() * in_view
+                }
+                Projection::Orthographic { .. } => {
+                    let in_view = in_view(x, y);
+                    in_view * focal.max_element()
+                }
+                Projection::Fisheye | Projection::Equirectangular => {
+                    // These see (nearly) all around, so only the distance matters.
+                    let dist = (x.powf_scalar(2.0) + y.powf_scalar(2.0) + z.powf_scalar(2.0))
+                        .sqrt()
+                        .clamp_min(0.01);
+                    dist.recip() * focal.max_element()
+                }
+            };
+            max_rate = max_rate.max_pair(rate);
+        }
+
+        let unseen = max_rate.clone().equal_elem(0.0);
+        let min_seen = max_rate
+            .clone()
+            .mask_fill(unseen.clone(), f32::MAX)
+            .min()
+            .clamp_max(highest_rate);
+        let fill = Tensor::ones_like(&max_rate) * min_seen;
+        max_rate.mask_where(unseen, fill)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use brush_render::camera::Camera;
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+    use burn::tensor::Tensor;
+    use glam::{uvec2, vec2, Quat, Vec3};
+
+    use super::{Scene, SceneView};
+
+    fn scene() -> Scene {
+        Scene::new(vec![SceneView {
+            name: "view.png".to_owned(),
+            camera: Camera::new(Vec3::ZERO, Quat::IDENTITY, 1.0, 1.0, vec2(0.5, 0.5)),
+            image: Arc::new(image::RgbImage::new(16, 16).into()),
+            depth: None,
+            mask: None,
+        }])
+    }
+
+    fn rates(points: &[[f32; 3]]) -> Vec<f32> {
+        let points =
+            Tensor::<NdArray, 1>::from_floats(points.concat().as_slice(), &NdArrayDevice::Cpu)
+                .reshape([points.len(), 3]);
+        scene()
+            .max_sampling_rates(points)
+            .into_data()
+            .to_vec::<f32>()
+            .unwrap()
+    }
+
+    #[test]
+    fn unseen_points_get_the_lowest_seen_rate() {
+        let rates = rates(&[[0.0, 0.0, 1.0], [0.0, 0.0, 2.0], [0.0, 0.0, -1.0]]);
+        // The rate halves with the distance.
+        assert!((rates[0] - 2.0 * rates[1]).abs() < 1e-4, "{rates:?}");
+        // The point behind the camera gets the rate of the furthest point that is seen.
+        assert_eq!(rates[2], rates[1]);
+    }
+
+    #[test]
+    fn unseen_rates_are_bounded_when_nothing_is_seen() {
+        let rates = rates(&[[0.0, 0.0, -1.0], [0.0, 0.0, -2.0]]);
+        // The rate of a point at the closest distance the camera handles.
+        let focal = scene().views[0].camera.focal(uvec2(16, 16)).max_element();
+        for rate in rates {
+            assert!((rate - focal / 0.01).abs() < 1e-2, "{rate}");
+        }
+    }
 }
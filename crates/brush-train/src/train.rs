@@ -1,21 +1,75 @@
 use anyhow::Result;
-use brush_render::gaussian_splats::{inverse_sigmoid, Splats};
+use brush_render::gaussian_splats::Splats;
 use brush_render::{AutodiffBackend, Backend, RenderAux};
-use burn::lr_scheduler::exponential::{ExponentialLrScheduler, ExponentialLrSchedulerConfig};
-use burn::lr_scheduler::LrScheduler;
+use burn::module::ParamId;
 use burn::optim::adaptor::OptimizerAdaptor;
-use burn::optim::Adam;
-use burn::tensor::{Bool, Distribution, Int};
+use burn::optim::record::AdaptorRecord;
+use burn::optim::{Adam, AdamState};
+use burn::tensor::{Bool, Int};
 use burn::{
     config::Config,
     optim::{AdamConfig, GradientsParams, Optimizer},
-    tensor::Tensor,
+    tensor::{Tensor, TensorPrimitive},
 };
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::HashMap;
 use tracing::trace_span;
 
-use crate::scene::SceneView;
+use crate::appearance::ViewAppearance;
+use crate::lr_schedule::LrSchedule;
+use crate::mcmc::McmcConfig;
+use crate::pose::{pose_gradients, ViewPoses};
+use crate::refine::{HeuristicRefine, McmcRefine, RefineContext, RefineStrategy, SplatBudget};
+use crate::scene::{Scene, SceneView};
 use crate::ssim::Ssim;
 
+/// How rendered depths are compared against the depth maps of a view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DepthLoss {
+    /// Compare depths directly. Needs depth maps in the same units as the scene, eg. from LiDAR.
+    Metric,
+    /// Fit a scale and shift to the depth map before comparing. For depths which are only known
+    /// up to scale, like most monocular depth estimates.
+    ScaleShiftInvariant,
+}
+
+/// How views with an alpha channel are compared against the renders.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum TrainBackground {
+    /// Render over black, and compare RGBA including the alpha.
+    Transparent,
+    /// Composite both the render and the ground truth over a fixed color, and compare RGB.
+    Color([f32; 3]),
+    /// Composite both over a new random color every step. This stops the splats from
+    /// faking transparency by matching the background color, which shows up as halos.
+    Random,
+}
+
+/// How splats are added and removed during training.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum RefineMode {
+    /// Split and clone splats with large gradients, and prune transparent and huge splats,
+    /// as in the original 3DGS.
+    Heuristic,
+    /// Relocate dead splats and add new ones up to a fixed maximum, as in 3DGS-MCMC.
+    /// See [`McmcConfig`].
+    Mcmc,
+}
+
+/// Which screenspace gradient decides what splats get densified.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum DensifyMode {
+    /// The norm of the xy gradient summed over all pixels, as in the original 3DGS.
+    Grad,
+    /// The norm of the absolute xy gradients summed over all pixels, as in AbsGS. Gradients of
+    /// different pixels can't cancel out, so large blurry splats over fine detail get split too.
+    AbsGrad,
+}
+
+/// The options of training. Everything beyond the original 3DGS is off by default.
+///
+/// The CLI reads these from the JSON file passed with `--config`, and run files store them in
+/// the `train` table.
 #[derive(Config)]
 pub struct TrainConfig {
     // period of steps where refinement is turned off
@@ -29,6 +83,15 @@ pub struct TrainConfig {
     #[config(default = 15000)]
     max_refine_step: u32,
 
+    // How splats are added and removed. Other schemes can be tried out by implementing
+    // RefineStrategy, and passing it to SplatTrainer::with_refine_strategy.
+    #[config(default = "RefineMode::Heuristic")]
+    refine_mode: RefineMode,
+
+    // Settings for RefineMode::Mcmc.
+    #[config(default = "McmcConfig::new()")]
+    mcmc: McmcConfig,
+
     #[config(default = 0.006)]
     reset_alpha_value: f32,
 
@@ -45,26 +108,112 @@ pub struct TrainConfig {
     reset_alpha_every_refine: u32,
 
     // threshold of positional gradient norm for densifying gaussians
-    // TODO: Abs grad.
     #[config(default = 0.0002)]
     densify_grad_thresh: f32,
 
+    #[config(default = "DensifyMode::Grad")]
+    densify_mode: DensifyMode,
+
+    // threshold of the absolute gradient norm for densifying gaussians, with DensifyMode::AbsGrad.
+    // The absolute gradients are larger, so this needs a higher threshold.
+    #[config(default = 0.0008)]
+    densify_abs_grad_thresh: f32,
+
     // below this size, gaussians are *duplicated*, otherwise split.
     #[config(default = 0.005)]
     densify_size_thresh: f32,
 
+    // Maximum number of splats with RefineMode::Heuristic. Once the budget is reached, only the
+    // most important candidates are split or cloned. Unlimited by default.
+    max_splats: Option<usize>,
+
+    // The splat budget grows linearly from the initial number of splats after the warmup, to
+    // max_splats at this step.
+    #[config(default = 15000)]
+    budget_growth_end: u32,
+
     #[config(default = 0.1)]
     ssim_weight: f32,
 
     #[config(default = 7)]
     ssim_window_size: usize,
 
+    // Weight of the depth loss, for views which have a depth map. Disabled by default.
+    #[config(default = 0.0)]
+    depth_loss_weight: f32,
+
+    #[config(default = "DepthLoss::Metric")]
+    depth_loss: DepthLoss,
+
+    // Weight of an L1 penalty on the opacity of the splats. Splats that aren't needed get pushed
+    // towards transparent, so they're pruned. Disabled by default.
+    #[config(default = 0.0)]
+    opacity_reg_weight: f32,
+
+    // Weight of a penalty on the scale of the splats, relative to the scene extent. Stops large
+    // splats from turning into fog in novel views. Disabled by default.
+    #[config(default = 0.0)]
+    scale_reg_weight: f32,
+
+    // Weight of a penalty on splats whose largest scale is more than max_anisotropy times
+    // their smallest scale, which stops them from turning into needles. Disabled by default.
+    #[config(default = 0.0)]
+    anisotropy_reg_weight: f32,
+
+    // The ratio of the largest and smallest scale of a splat above which anisotropy_reg_weight
+    // penalises it.
+    #[config(default = 10.0)]
+    max_anisotropy: f32,
+
+    // Background used for views with an alpha channel.
+    #[config(default = "TrainBackground::Transparent")]
+    background: TrainBackground,
+
+    // Mip-Splatting 3D smoothing filter. Limits the size of the splats to the highest
+    // frequency the training cameras can capture, which stops them from shrinking into
+    // aliasing when zooming in.
+    #[config(default = false)]
+    mip_filter_3d: bool,
+
+    // Mip-Splatting 2D filter. Compensates the opacity of splats for the screenspace blur,
+    // which stops small splats from turning into bright blobs when zooming out.
+    #[config(default = false)]
+    mip_filter_2d: bool,
+
+    // Learn an affine colour transform for each training view, which is applied to the render
+    // before the loss. This stops changes in exposure and white balance from turning into
+    // floaters. The transforms are only used in training, not in evaluation or export.
+    #[config(default = false)]
+    appearance_transform: bool,
+
+    // Learn a correction of the rotation and translation of each training view, for when the
+    // camera poses aren't quite right. The refined poses can be exported afterwards.
+    #[config(default = false)]
+    pose_refine: bool,
+
+    // With pose refinement, also learn a correction of the focal length of each view.
+    #[config(default = false)]
+    pose_refine_focal: bool,
+
+    // Start by only training the base colour, and activate the next SH band every this many
+    // steps, up to the degree of the splats. This stops early training from spending capacity
+    // on view dependent colour before the geometry is there. All bands are trained from the
+    // start by default.
+    sh_degree_interval: Option<u32>,
+
     #[config(default = true)]
     scale_mean_lr_by_extent: bool,
 
-    // Learning rates.
-    lr_mean: ExponentialLrSchedulerConfig,
+    // Learning rates. Each splat parameter has a base rate, and a schedule which scales the base
+    // rate over the course of training, eg. lr_scale_schedule for lr_scale. The rates used at
+    // every step are logged to rerun.
+    #[config(default = 3e-4)]
+    lr_mean: f64,
+
+    #[config(default = "LrSchedule::Exponential { final_scale: 0.1, steps: 30000 }")]
+    lr_mean_schedule: LrSchedule,
 
+    // Decay applied on top of the schedules of all splat parameters, every step.
     #[config(default = 0.9999)]
     lr_global_decay: f64,
 
@@ -72,6 +221,9 @@ pub struct TrainConfig {
     #[config(default = 0.006)]
     lr_coeffs_dc: f64,
 
+    #[config(default = "LrSchedule::Constant")]
+    lr_coeffs_schedule: LrSchedule,
+
     // How much to divide the learning rate by for higher SH orders.
     #[config(default = 15.0)]
     lr_coeffs_sh_scale: f64,
@@ -79,29 +231,68 @@ pub struct TrainConfig {
     #[config(default = 0.1)]
     lr_opac: f64,
 
+    #[config(default = "LrSchedule::Constant")]
+    lr_opac_schedule: LrSchedule,
+
     #[config(default = 0.02)]
     lr_scale: f64,
 
+    #[config(default = "LrSchedule::Constant")]
+    lr_scale_schedule: LrSchedule,
+
     #[config(default = 0.01)]
     lr_rotation: f64,
 
+    #[config(default = "LrSchedule::Constant")]
+    lr_rotation_schedule: LrSchedule,
+
+    #[config(default = 0.001)]
+    lr_appearance: f64,
+
+    #[config(default = 1e-4)]
+    lr_pose_rotation: f64,
+
+    // Learning rate of the pose translations, relative to the scene extent.
+    #[config(default = 1e-4)]
+    lr_pose_translation: f64,
+
+    #[config(default = 1e-4)]
+    lr_pose_focal: f64,
+
     #[config(default = 42)]
     seed: u64,
 }
 
 impl Default for TrainConfig {
     fn default() -> Self {
-        let decay_steps = 30000;
-        let lr_max = 3e-4;
-        let decay = 1e-1f64.powf(1.0 / decay_steps as f64);
-        TrainConfig::new(ExponentialLrSchedulerConfig::new(lr_max, decay))
+        TrainConfig::new()
+    }
+}
+
+impl TrainConfig {
+    /// Whether training learns a colour transform for each view. Evaluation then has to fit
+    /// one too, see [`crate::eval::eval_stats`].
+    pub fn appearance_transform(&self) -> bool {
+        self.appearance_transform
+    }
+
+    /// Seed for the random choices of training, like the background colours and MCMC noise.
+    pub fn seed(&self) -> u64 {
+        self.seed
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct SceneBatch<B: Backend> {
-    pub gt_images: Tensor<B, 4>,
+    // Images can have different resolutions, so these are kept as separate tensors.
+    pub gt_images: Vec<Tensor<B, 3>>,
+    // Depth maps for the views that have them, with the same resolution as the image.
+    pub gt_depths: Vec<Option<Tensor<B, 2>>>,
+    // Masks for the views that have them, pixels where the mask is 0 don't contribute to the loss.
+    pub gt_masks: Vec<Option<Tensor<B, 2>>>,
     pub gt_views: Vec<SceneView>,
+    // Index of each view in the training scene.
+    pub gt_view_indices: Vec<usize>,
     pub scene_extent: f64,
 }
 
@@ -109,17 +300,25 @@ pub struct SceneBatch<B: Backend> {
 pub struct RefineStats {
     pub num_split: usize,
     pub num_cloned: usize,
+    // Dead splats moved onto live ones, with RefineMode::Mcmc.
+    pub num_relocated: usize,
+    // Densification candidates skipped because the splat budget was reached.
+    pub num_rejected: usize,
     pub num_transparent_pruned: usize,
     pub num_scale_pruned: usize,
 }
 
 #[derive(Clone)]
 pub struct TrainStepStats<B: AutodiffBackend> {
-    pub pred_images: Tensor<B, 4>,
-    pub gt_images: Tensor<B, 4>,
+    pub pred_images: Vec<Tensor<B, 3>>,
+    pub gt_images: Vec<Tensor<B, 3>>,
     pub gt_views: Vec<SceneView>,
     pub auxes: Vec<RenderAux<B>>,
     pub loss: Tensor<B, 1>,
+    // The regularisation losses, when they're enabled. These are weighted, and included in loss.
+    pub opacity_reg: Option<Tensor<B, 1>>,
+    pub scale_reg: Option<Tensor<B, 1>>,
+    pub anisotropy_reg: Option<Tensor<B, 1>>,
     pub lr_mean: f64,
     pub lr_rotation: f64,
     pub lr_scale: f64,
@@ -129,27 +328,62 @@ pub struct TrainStepStats<B: AutodiffBackend> {
     pub refine: Option<RefineStats>,
 }
 
+/// The Adam state of all splat parameters, by parameter ID.
+pub type OptimRecord<B> = HashMap<ParamId, AdaptorRecord<Adam, B>>;
+
+/// The random choices made during a training step. Each draws from its own stream, so they
+/// aren't correlated with each other.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum RandomStream {
+    Background,
+    McmcNoise,
+    McmcRelocation,
+}
+
+/// A random generator for one of the random choices of a step. It only depends on the seed and
+/// the step, so training continues the same when resuming from a checkpoint.
+pub(crate) fn step_rng(seed: u64, iter: u32, stream: RandomStream) -> StdRng {
+    let mut key = [0; 32];
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    key[8..12].copy_from_slice(&iter.to_le_bytes());
+    key[12] = stream as u8;
+    StdRng::from_seed(key)
+}
+
 pub struct SplatTrainer<B: AutodiffBackend>
 where
     B::InnerBackend: Backend,
 {
     pub iter: u32,
+    // The number of splats training started with, which the splat budget grows from.
+    pub(crate) start_count: usize,
 
-    config: TrainConfig,
+    pub(crate) config: TrainConfig,
 
-    sched_mean: ExponentialLrScheduler,
-    optim: OptimizerAdaptor<Adam, Splats<B>, B>,
-    opt_config: AdamConfig,
+    pub(crate) optim: OptimizerAdaptor<Adam, Splats<B>, B>,
+    pub(crate) opt_config: AdamConfig,
 
     // Helper tensors for accumulating the viewspace_xy gradients and the number
     // of observations per gaussian. Used in pruning and densification.
-    grad_2d_accum: Tensor<B, 1>,
-    xy_grad_counts: Tensor<B, 1, Int>,
+    pub(crate) grad_2d_accum: Tensor<B, 1>,
+    pub(crate) xy_grad_counts: Tensor<B, 1, Int>,
+    // The fraction of the screen covered by each gaussian, summed over the observations.
+    pub(crate) coverage_accum: Tensor<B, 1>,
+    // The step the Mip-Splatting 3D filter was last computed at.
+    pub(crate) filter_3d_iter: Option<u32>,
+
+    refine: Box<dyn RefineStrategy<B>>,
+
+    pub(crate) appearance: Option<ViewAppearance<B>>,
+    pub(crate) appearance_optim: OptimizerAdaptor<Adam, ViewAppearance<B>, B>,
+
+    pub(crate) poses: Option<ViewPoses<B>>,
+    pub(crate) pose_optim: OptimizerAdaptor<Adam, ViewPoses<B>, B>,
 
     ssim: Ssim<B>,
 }
 
-fn quaternion_vec_multiply<B: Backend>(
+pub(crate) fn quaternion_vec_multiply<B: Backend>(
     quaternions: Tensor<B, 2>,
     vectors: Tensor<B, 2>,
 ) -> Tensor<B, 2> {
@@ -188,6 +422,67 @@ fn quaternion_vec_multiply<B: Backend>(
     Tensor::cat(vec![rx, ry, rz], 1)
 }
 
+/// The refine strategy picked by the config.
+fn default_refine_strategy<B: AutodiffBackend>(
+    config: &TrainConfig,
+    num_points: usize,
+) -> Box<dyn RefineStrategy<B>> {
+    match config.refine_mode {
+        RefineMode::Heuristic => Box::new(HeuristicRefine {
+            grad_thresh: match config.densify_mode {
+                DensifyMode::Grad => config.densify_grad_thresh,
+                DensifyMode::AbsGrad => config.densify_abs_grad_thresh,
+            },
+            size_thresh: config.densify_size_thresh,
+            cull_alpha_thresh: config.cull_alpha_thresh,
+            cull_scale_thresh: config.cull_scale_thresh,
+            reset_alpha_value: config.reset_alpha_value,
+            reset_alpha_every_refine: config.reset_alpha_every_refine,
+            refine_every: config.refine_every,
+            split_divisor: 1.6,
+            split_sigma: 0.5,
+            budget: config.max_splats.map(|max_splats| SplatBudget {
+                start_count: num_points,
+                max_splats,
+                growth_start: config.warmup_steps,
+                growth_end: config.budget_growth_end,
+            }),
+        }),
+        RefineMode::Mcmc => Box::new(McmcRefine {
+            config: config.mcmc.clone(),
+            seed: config.seed,
+        }),
+    }
+}
+
+/// Mean absolute error between a rendered and a ground truth depth map, over the pixels
+/// with a known depth.
+fn depth_loss<B: Backend>(pred: Tensor<B, 2>, gt: Tensor<B, 2>, mode: DepthLoss) -> Tensor<B, 1> {
+    let mask = gt.clone().greater_elem(0.0).float();
+    let count = mask.clone().sum().clamp_min(1.0);
+
+    let pred = match mode {
+        DepthLoss::Metric => pred,
+        DepthLoss::ScaleShiftInvariant => {
+            // Least squares fit of gt = scale * pred + shift. The fit itself isn't
+            // differentiated, so the gradient just moves the depths towards the aligned targets.
+            let p = pred.clone().detach() * mask.clone();
+            let g = gt.clone() * mask.clone();
+            let sum_p = p.clone().sum();
+            let sum_g = g.clone().sum();
+            let sum_pp = (p.clone() * p.clone()).sum();
+            let sum_pg = (p * g).sum();
+
+            let det = (count.clone() * sum_pp - sum_p.clone() * sum_p.clone()).clamp_min(1e-8);
+            let scale = (count.clone() * sum_pg - sum_p.clone() * sum_g.clone()) / det;
+            let shift = (sum_g - scale.clone() * sum_p) / count.clone();
+            pred * scale.unsqueeze() + shift.unsqueeze()
+        }
+    };
+
+    ((pred - gt).abs() * mask).sum() / count
+}
+
 impl<B: AutodiffBackend> SplatTrainer<B>
 where
     B::InnerBackend: Backend,
@@ -195,29 +490,67 @@ where
     pub fn new(num_points: usize, config: &TrainConfig, device: &B::Device) -> Self {
         let opt_config = AdamConfig::new().with_epsilon(1e-15);
         let optim = opt_config.init::<B, Splats<B>>();
+        let appearance_optim = opt_config.init::<B, ViewAppearance<B>>();
+        let pose_optim = opt_config.init::<B, ViewPoses<B>>();
 
         let ssim = Ssim::new(config.ssim_window_size, 3, device);
         Self {
             config: config.clone(),
             iter: 0,
-            sched_mean: config.lr_mean.init().expect("Lr schedule must be valid."),
+            start_count: num_points,
             optim,
             opt_config,
             grad_2d_accum: Tensor::zeros([num_points], device),
             xy_grad_counts: Tensor::zeros([num_points], device),
+            coverage_accum: Tensor::zeros([num_points], device),
+            filter_3d_iter: None,
+            refine: default_refine_strategy(config, num_points),
+            appearance: None,
+            appearance_optim,
+            poses: None,
+            pose_optim,
             ssim,
         }
     }
 
+    /// Use a different strategy to refine the splats than the one from the config.
+    pub fn with_refine_strategy(mut self, strategy: impl RefineStrategy<B> + 'static) -> Self {
+        self.refine = Box::new(strategy);
+        self
+    }
+
+    /// Set the number of views in the training scene. This is needed to learn the per view
+    /// appearance transforms and poses, when they're enabled in the config.
+    pub fn with_train_views(mut self, num_views: usize, device: &B::Device) -> Self {
+        if self.config.appearance_transform && self.appearance.is_none() {
+            self.appearance = Some(ViewAppearance::new(num_views, device));
+        }
+        if self.config.pose_refine && self.poses.is_none() {
+            self.poses = Some(ViewPoses::new(num_views, device));
+        }
+        self
+    }
+
+    /// The training scene with the refined camera of every view, when pose refinement
+    /// is enabled.
+    pub async fn refined_scene(&self, scene: &Scene) -> Option<Scene> {
+        let deltas = self.poses.as_ref()?.deltas().await;
+        let views = scene
+            .views
+            .iter()
+            .zip(&deltas)
+            .map(|(view, delta)| SceneView {
+                camera: delta.apply(&view.camera),
+                ..view.clone()
+            })
+            .collect();
+        Some(Scene::new(views))
+    }
+
     fn reset_stats(&mut self, num_points: usize, device: &B::Device) {
         self.grad_2d_accum = Tensor::zeros([num_points], device);
         self.xy_grad_counts = Tensor::zeros([num_points], device);
-    }
-
-    pub(crate) fn reset_opacity(&self, splats: &mut Splats<B>) {
-        Splats::map_param(&mut splats.raw_opacity, |op| {
-            Tensor::zeros_like(&op) + inverse_sigmoid(self.config.reset_alpha_value)
-        });
+        self.coverage_accum = Tensor::zeros([num_points], device);
     }
 
     pub async fn step(
@@ -225,116 +558,254 @@ where
         batch: SceneBatch<B>,
         splats: Splats<B>,
     ) -> Result<(Splats<B>, TrainStepStats<B>), anyhow::Error> {
-        assert!(
-            batch.gt_views.len() == 1,
-            "Bigger batches aren't yet supported"
-        );
-
         let mut splats = splats;
+        splats.mip_filter_2d = self.config.mip_filter_2d;
+        splats.render_sh_degree = self
+            .config
+            .sh_degree_interval
+            .map(|interval| self.iter / interval.max(1));
+        let device = splats.means.device();
+        let batch_size = batch.gt_views.len();
+
+        let background = match self.config.background {
+            TrainBackground::Transparent => None,
+            TrainBackground::Color(color) => Some(glam::Vec3::from(color)),
+            TrainBackground::Random => {
+                let mut rng = step_rng(self.config.seed, self.iter, RandomStream::Background);
+                Some(glam::vec3(rng.gen(), rng.gen(), rng.gen()))
+            }
+        };
 
-        let [batch_size, img_h, img_w, _] = batch.gt_images.dims();
+        // The current pose corrections, cameras are built on the CPU so these have to be read.
+        let pose_deltas = match &self.poses {
+            Some(poses) => Some(poses.deltas().await),
+            None => None,
+        };
 
-        let (pred_images, auxes, loss) = {
+        let (pred_images, auxes, xys_dummies, camera_dummies, loss) = {
             let mut renders = vec![];
             let mut auxes = vec![];
-
-            for i in 0..batch.gt_views.len() {
-                let camera = &batch.gt_views[i].camera;
-
-                let (pred_image, aux) =
-                    splats.render(camera, glam::uvec2(img_w as u32, img_h as u32), false);
+            let mut xys_dummies = vec![];
+            let mut camera_dummies = vec![];
+            let mut losses = vec![];
+
+            for ((((view, gt_image), gt_depth), gt_mask), &view_index) in batch
+                .gt_views
+                .iter()
+                .zip(&batch.gt_images)
+                .zip(&batch.gt_depths)
+                .zip(&batch.gt_masks)
+                .zip(&batch.gt_view_indices)
+            {
+                let [img_h, img_w, _] = gt_image.dims();
+                let img_size = glam::uvec2(img_w as u32, img_h as u32);
+
+                // Each view gets its own dummy tensor, so the screenspace gradients of each
+                // view can be tracked separately.
+                let mut view_splats = splats.clone();
+                let xys_dummy = Tensor::zeros([splats.num_splats(), 2], &device).require_grad();
+                match self.config.densify_mode {
+                    DensifyMode::Grad => view_splats.xys_dummy = xys_dummy.clone(),
+                    DensifyMode::AbsGrad => view_splats.xys_abs_dummy = xys_dummy.clone(),
+                }
+
+                // Render from the refined camera, and track its gradient.
+                let camera = if let Some(deltas) = &pose_deltas {
+                    let camera = deltas[view_index].apply(&view.camera);
+                    let camera_dummy = Tensor::zeros([8], &device).require_grad();
+                    view_splats.camera_dummy = camera_dummy.clone();
+                    camera_dummies.push((view_index, camera_dummy, camera.focal(img_size)));
+                    camera
+                } else {
+                    view.camera.clone()
+                };
+
+                // Opaque views are always rendered over black.
+                let has_alpha = view.image.color().has_alpha();
+                let background = background.filter(|_| has_alpha);
+                let render_background = background.unwrap_or(glam::Vec3::ZERO);
+
+                // Only render depth when it's supervised, it makes the rasterization slower.
+                let gt_depth = gt_depth
+                    .as_ref()
+                    .filter(|_| self.config.depth_loss_weight > 0.0);
+
+                let (pred_image, pred_depth, aux) = if gt_depth.is_some() {
+                    let (pred_image, pred_depth, _, aux) =
+                        view_splats.render_with_depth(&camera, img_size, render_background);
+                    (pred_image, Some(pred_depth), aux)
+                } else {
+                    let (pred_image, aux) =
+                        view_splats.render(&camera, img_size, render_background, false);
+                    (pred_image, None, aux)
+                };
+
+                // Compensate for the exposure and white balance of this view.
+                let pred_image = match &self.appearance {
+                    Some(appearance) => appearance.apply(view_index, pred_image),
+                    None => pred_image,
+                };
+
+                let _span = trace_span!("Calculate losses", sync_burn = true).entered();
+
+                let pred_rgb = pred_image.clone().slice([0..img_h, 0..img_w, 0..3]);
+
+                let (pred_compare, gt_image) = if let Some(background) = background {
+                    // The render is already composited, do the same for the ground truth.
+                    let gt_rgb = gt_image.clone().slice([0..img_h, 0..img_w, 0..3]);
+                    let gt_alpha = gt_image.clone().slice([0..img_h, 0..img_w, 3..4]);
+                    let background = Tensor::<B, 1>::from_floats(background.to_array(), &device)
+                        .reshape([1, 1, 3]);
+                    let gt_image = gt_rgb * gt_alpha.clone() + background * (gt_alpha.neg() + 1.0);
+                    (pred_rgb.clone(), gt_image)
+                } else if has_alpha {
+                    (pred_image.clone(), gt_image.clone())
+                } else {
+                    (pred_rgb.clone(), gt_image.clone())
+                };
+
+                // Masks are [h, w, 1] so they broadcast over the channels.
+                let gt_mask: Option<Tensor<B, 3>> =
+                    gt_mask.as_ref().map(|m| m.clone().unsqueeze_dim(2));
+
+                let loss = if let Some(mask) = &gt_mask {
+                    let channels = pred_compare.dims()[2] as f32;
+                    let num_pixels = mask.clone().sum().clamp_min(1.0) * channels;
+                    ((pred_compare - gt_image.clone()).abs() * mask.clone()).sum() / num_pixels
+                } else {
+                    (pred_compare - gt_image.clone()).abs().mean()
+                };
+
+                // Disabled on WASM for now. On WebGPU + Metal this unfortunately has glitches.
+                let loss = if self.config.ssim_weight > 0.0 && !cfg!(target_family = "wasm") {
+                    let gt_rgb = gt_image.clone().slice([0..img_h, 0..img_w, 0..3]);
+                    // SSIM works on windows, so can't just drop pixels. Instead, fill in the
+                    // masked pixels with the ground truth, which leaves no gradient there.
+                    let pred_rgb = if let Some(mask) = &gt_mask {
+                        pred_rgb * mask.clone() + gt_rgb.clone() * (mask.clone().neg() + 1.0)
+                    } else {
+                        pred_rgb
+                    };
+                    let ssim_loss = self.ssim.ssim(pred_rgb.unsqueeze(), gt_rgb.unsqueeze());
+                    loss * (1.0 - self.config.ssim_weight) - ssim_loss * self.config.ssim_weight
+                } else {
+                    loss
+                };
+
+                let loss = if let (Some(pred_depth), Some(gt_depth)) = (pred_depth, gt_depth) {
+                    // Masked pixels are treated as having an unknown depth.
+                    let gt_depth = if let Some(mask) = &gt_mask {
+                        gt_depth.clone() * mask.clone().squeeze(2).greater_elem(0.0).float()
+                    } else {
+                        gt_depth.clone()
+                    };
+                    let loss_depth = depth_loss(pred_depth, gt_depth, self.config.depth_loss);
+                    loss + loss_depth * self.config.depth_loss_weight
+                } else {
+                    loss
+                };
 
                 renders.push(pred_image);
                 auxes.push(aux);
+                xys_dummies.push(xys_dummy);
+                losses.push(loss);
             }
 
-            let pred_images = Tensor::stack(renders, 0);
-
-            let _span = trace_span!("Calculate losses", sync_burn = true).entered();
+            // Average the loss over all views in the batch.
+            let loss = Tensor::cat(losses, 0).mean();
 
-            let pred_rgb = pred_images
-                .clone()
-                .slice([0..batch_size, 0..img_h, 0..img_w, 0..3]);
-
-            // This is wrong if the batch has mixed transparent and non-transparent images,
-            // but that's ok for now.
-            let pred_compare = if batch.gt_views[0].image.color().has_alpha() {
-                pred_images.clone()
-            } else {
-                pred_rgb.clone()
-            };
-
-            let loss = (pred_compare - batch.gt_images.clone()).abs().mean();
-
-            // Disabled on WASM for now. On WebGPU + Metal this unfortunately has glitches.
-            let loss = if self.config.ssim_weight > 0.0 && !cfg!(target_family = "wasm") {
-                let gt_rgb =
-                    batch
-                        .gt_images
-                        .clone()
-                        .slice([0..batch_size, 0..img_h, 0..img_w, 0..3]);
-
-                let ssim_loss = self.ssim.ssim(pred_rgb, gt_rgb);
-                loss * (1.0 - self.config.ssim_weight) - ssim_loss * self.config.ssim_weight
-            } else {
-                loss
-            };
-
-            (pred_images, auxes, loss)
+            (renders, auxes, xys_dummies, camera_dummies, loss)
         };
 
-        let mut grads = trace_span!("Backward pass", sync_burn = true).in_scope(|| loss.backward());
+        // Regularisation of the splats themselves, which doesn't depend on the views.
+        let opacity_reg = (self.config.opacity_reg_weight > 0.0)
+            .then(|| splats.opacity().mean() * self.config.opacity_reg_weight);
+        let scale_reg = (self.config.scale_reg_weight > 0.0).then(|| {
+            splats.scales().mean() * (self.config.scale_reg_weight / batch.scene_extent as f32)
+        });
+        let anisotropy_reg = (self.config.anisotropy_reg_weight > 0.0).then(|| {
+            let scales = splats.scales();
+            let ratio = scales.clone().max_dim(1) / scales.min_dim(1);
+            (ratio - self.config.max_anisotropy).clamp_min(0.0).mean()
+                * self.config.anisotropy_reg_weight
+        });
+        let loss = [&opacity_reg, &scale_reg, &anisotropy_reg]
+            .into_iter()
+            .flatten()
+            .fold(loss, |loss, reg| loss + reg.clone());
 
-        // TODO: Should scale lr be scales by scene scale as well?
-        let (lr_mean, lr_rotation, lr_scale, lr_coeffs, lr_opac) = (
-            self.sched_mean.step() * batch.scene_extent,
-            self.config.lr_rotation,
-            self.config.lr_scale,
-            self.config.lr_coeffs_dc,
-            self.config.lr_opac,
-        );
+        let mut grads = trace_span!("Backward pass", sync_burn = true).in_scope(|| loss.backward());
 
         let global_decay = self.config.lr_global_decay.powf(self.iter as f64);
+        let lr = |base: f64, schedule: LrSchedule| base * schedule.scale(self.iter) * global_decay;
 
+        // TODO: Should scale lr be scales by scene scale as well?
         let (lr_mean, lr_rotation, lr_scale, lr_coeffs, lr_opac) = (
-            lr_mean * global_decay,
-            lr_rotation * global_decay,
-            lr_scale * global_decay,
-            lr_coeffs * global_decay,
-            lr_opac * global_decay,
+            lr(self.config.lr_mean, self.config.lr_mean_schedule) * batch.scene_extent,
+            lr(self.config.lr_rotation, self.config.lr_rotation_schedule),
+            lr(self.config.lr_scale, self.config.lr_scale_schedule),
+            lr(self.config.lr_coeffs_dc, self.config.lr_coeffs_schedule),
+            lr(self.config.lr_opac, self.config.lr_opac_schedule),
         );
 
         trace_span!("Housekeeping", sync_burn = true).in_scope(|| {
             // TODO: Burn really should implement +=
             if self.iter > self.config.warmup_steps {
-                // Get the xy gradient norm from the dummy tensor.
-                let xys_grad = Tensor::from_inner(
-                    splats
-                        .xys_dummy
-                        .grad_remove(&mut grads)
-                        .expect("XY gradients need to be calculated."),
-                );
-
-                let aux = &auxes[0];
-                let gs_ids = Tensor::from_primitive(aux.global_from_compact_gid.clone());
-
-                let [_, h, w, _] = pred_images.dims();
-                let device = batch.gt_images.device();
-                let xys_grad = xys_grad
-                    * Tensor::<_, 1>::from_floats([w as f32 / 2.0, h as f32 / 2.0], &device)
+                for ((xys_dummy, aux), pred_image) in
+                    xys_dummies.iter().zip(&auxes).zip(&pred_images)
+                {
+                    // Get the xy gradient norm from the dummy tensor.
+                    let xys_grad = Tensor::from_inner(
+                        xys_dummy
+                            .grad_remove(&mut grads)
+                            .expect("XY gradients need to be calculated."),
+                    );
+
+                    let gs_ids = Tensor::from_primitive(aux.global_from_compact_gid.clone());
+
+                    // The loss is averaged over the batch, scale the gradients back up so
+                    // the statistics don't depend on the batch size.
+                    let [h, w, _] = pred_image.dims();
+                    let xys_grad = xys_grad
+                        * Tensor::<_, 1>::from_floats(
+                            [
+                                w as f32 / 2.0 * batch_size as f32,
+                                h as f32 / 2.0 * batch_size as f32,
+                            ],
+                            &device,
+                        )
                         .reshape([1, 2]);
 
-                let xys_grad_norm = xys_grad.powi_scalar(2).sum_dim(1).squeeze(1).sqrt();
-
-                let num_vis = Tensor::from_primitive(aux.num_visible.clone());
-                let valid = Tensor::arange(0..splats.num_splats() as i64, &device).lower(num_vis);
-
-                self.xy_grad_counts =
-                    self.xy_grad_counts
+                    let xys_grad_norm = xys_grad.powi_scalar(2).sum_dim(1).squeeze(1).sqrt();
+
+                    let num_vis = Tensor::from_primitive(aux.num_visible.clone());
+                    let valid =
+                        Tensor::arange(0..splats.num_splats() as i64, &device).lower(num_vis);
+
+                    self.xy_grad_counts = self.xy_grad_counts.clone().select_assign(
+                        0,
+                        gs_ids.clone(),
+                        valid.clone().int(),
+                    );
+
+                    self.grad_2d_accum = self.grad_2d_accum.clone() + xys_grad_norm;
+
+                    // The area of the splat ellipse is pi / sqrt(det(conic)).
+                    let projected: Tensor<B, 2> = Tensor::from_primitive(TensorPrimitive::Float(
+                        aux.projected_splats.clone(),
+                    ));
+                    let conic =
+                        |i: usize| projected.clone().slice([0..splats.num_splats(), i..i + 1]);
+                    let det = conic(2) * conic(4) - conic(3).powi_scalar(2);
+                    let coverage = (det.clamp_min(1e-12).sqrt().recip()
+                        * (std::f32::consts::PI / (w * h) as f32))
+                        .squeeze(1)
+                        .mask_fill(valid.bool_not(), 0.0);
+                    self.coverage_accum = self
+                        .coverage_accum
                         .clone()
-                        .select_assign(0, gs_ids.clone(), valid.int());
-
-                self.grad_2d_accum = self.grad_2d_accum.clone() + xys_grad_norm;
+                        .select_assign(0, gs_ids, coverage);
+                }
             }
         });
 
@@ -383,6 +854,58 @@ where
             splats
         });
 
+        if let Some(appearance) = self.appearance.take() {
+            let grad_appearance =
+                GradientsParams::from_params(&mut grads, &appearance, &[appearance.transforms.id]);
+            self.appearance = Some(self.appearance_optim.step(
+                self.config.lr_appearance,
+                appearance,
+                grad_appearance,
+            ));
+        }
+
+        if let (Some(poses), Some(deltas)) = (self.poses.take(), &pose_deltas) {
+            let mut view_grads = vec![];
+            for (view_index, camera_dummy, focal) in camera_dummies {
+                let v_camera: Vec<f32> = camera_dummy
+                    .grad_remove(&mut grads)
+                    .expect("Camera gradients need to be calculated.")
+                    .into_data_async()
+                    .await
+                    .to_vec()
+                    .expect("Camera gradients must be f32");
+                view_grads.push((view_index, deltas[view_index].gradient(&v_camera, focal)));
+            }
+
+            let (v_rotations, v_translations, v_log_focal_scales) =
+                pose_gradients::<B::InnerBackend>(poses.num_views(), &view_grads, &device);
+
+            let mut poses = poses;
+            let mut grad = GradientsParams::new();
+            grad.register(poses.rotations.id, v_rotations);
+            poses = self
+                .pose_optim
+                .step(self.config.lr_pose_rotation, poses, grad);
+
+            let mut grad = GradientsParams::new();
+            grad.register(poses.translations.id, v_translations);
+            poses = self.pose_optim.step(
+                self.config.lr_pose_translation * batch.scene_extent,
+                poses,
+                grad,
+            );
+
+            if self.config.pose_refine_focal {
+                let mut grad = GradientsParams::new();
+                grad.register(poses.log_focal_scales.id, v_log_focal_scales);
+                poses = self.pose_optim.step(self.config.lr_pose_focal, poses, grad);
+            }
+
+            self.poses = Some(poses);
+        }
+
+        let post_step_splat = self.refine.after_step(post_step_splat, self.iter, lr_mean);
+
         let mut refine_stats = None;
 
         let do_refine = self.iter < self.config.max_refine_step
@@ -393,7 +916,31 @@ where
             // If not refining, update splat to step with gradients applied.
             post_step_splat
         } else {
-            let (splats, refine) = self.refine_splats(splats, post_step_splat).await;
+            // Seed based on the current step, so refinement is reproducible when resuming
+            // from a checkpoint.
+            <B as burn::tensor::backend::Backend>::seed(self.config.seed + self.iter as u64);
+
+            let mut record = self.optim.to_record();
+            let ctx = RefineContext {
+                iter: self.iter,
+                pre_step: splats,
+                post_step: post_step_splat,
+                grad_2d_accum: self.grad_2d_accum.clone(),
+                visible_counts: self.xy_grad_counts.clone(),
+                coverage_accum: self.coverage_accum.clone(),
+                record: &mut record,
+            };
+            let (mut splats, refine) = self.refine.refine(ctx).await;
+
+            // Stats don't line up anymore so have to reset them.
+            self.reset_stats(splats.num_splats(), &device);
+
+            // The optimizer state now lines up with the new splats again.
+            self.optim = self.opt_config.init().load_record(record);
+
+            // The 3D filter doesn't line up anymore either, it's recomputed by update_filter_3d.
+            splats.filter_3d = None;
+
             refine_stats = Some(refine);
             splats
         };
@@ -406,6 +953,9 @@ where
             gt_views: batch.gt_views,
             auxes,
             loss,
+            opacity_reg,
+            scale_reg,
+            anisotropy_reg,
             lr_mean,
             lr_rotation,
             lr_scale,
@@ -417,197 +967,56 @@ where
         Ok((splats, stats))
     }
 
-    async fn refine_splats(
-        &mut self,
-        splats: Splats<B>,
-        post_step_splat: Splats<B>,
-    ) -> (Splats<B>, RefineStats) {
-        let device = splats.means.device();
-        let mut splats_pre_step = splats;
-        let mut splats_post_step = post_step_splat.clone();
-
-        // Otherwise, do refinement, but do the split/clone on gaussians with no grads applied.
-        let grads = self.grad_2d_accum.clone() / self.xy_grad_counts.clone().clamp_min(1).float();
-
-        let big_grad_mask = grads.greater_equal_elem(self.config.densify_grad_thresh);
-        let split_clone_size_mask = splats_post_step
-            .scales()
-            .max_dim(1)
-            .squeeze(1)
-            .lower_elem(self.config.densify_size_thresh);
-
-        let mut append_means = vec![];
-        let mut append_rots = vec![];
-        let mut append_coeffs = vec![];
-        let mut append_opac = vec![];
-        let mut append_scales = vec![];
-
-        let clone_inds = Tensor::stack::<2>(
-            vec![split_clone_size_mask.clone(), big_grad_mask.clone()],
-            1,
-        )
-        .all_dim(1)
-        .squeeze::<1>(1)
-        .argwhere_async()
-        .await;
-
-        // Clone splats
-        let clone_count = clone_inds.dims()[0];
-        if clone_count > 0 {
-            let clone_inds = clone_inds.squeeze(1);
-            append_means.push(splats_pre_step.means.val().select(0, clone_inds.clone()));
-            append_rots.push(splats_pre_step.rotation.val().select(0, clone_inds.clone()));
-            append_coeffs.push(
-                splats_pre_step
-                    .sh_coeffs
-                    .val()
-                    .select(0, clone_inds.clone()),
-            );
-            append_opac.push(
-                splats_pre_step
-                    .raw_opacity
-                    .val()
-                    .select(0, clone_inds.clone()),
-            );
-            append_scales.push(
-                splats_pre_step
-                    .log_scales
-                    .val()
-                    .select(0, clone_inds.clone()),
-            );
+    /// Recompute the Mip-Splatting 3D filter from the cameras of the training scene, when it's
+    /// enabled. The filter depends on where the splats are, so it's refreshed every
+    /// `refine_every` steps, and whenever the splats changed.
+    pub fn update_filter_3d(&mut self, splats: Splats<B>, scene: &Scene) -> Splats<B> {
+        if !self.config.mip_filter_3d {
+            return splats;
         }
 
-        // Split splats.
-        let split_mask =
-            Tensor::stack::<2>(vec![split_clone_size_mask.bool_not(), big_grad_mask], 1).all_dim(1);
-        let split_inds = split_mask.clone().squeeze::<1>(1).argwhere_async().await;
-        let split_count = split_inds.dims()[0];
-        if split_count > 0 {
-            let split_inds = split_inds.squeeze(1);
-
-            // Some parts can be straightforwardly copied to the new splats.
-            let cur_coeff = splats_post_step
-                .sh_coeffs
-                .val()
-                .select(0, split_inds.clone());
-            let cur_raw_opac = splats_post_step
-                .raw_opacity
-                .val()
-                .select(0, split_inds.clone());
-            let cur_rots = splats_post_step
-                .rotation
-                .val()
-                .select(0, split_inds.clone());
-            append_rots.push(cur_rots.clone());
-            append_coeffs.push(cur_coeff.clone());
-            append_opac.push(cur_raw_opac);
-
-            // Change current scale to be lower.
-            let cur_scale = splats_post_step.scales().select(0, split_inds.clone());
-            Splats::map_param(&mut splats_post_step.log_scales, |m| {
-                let div_scales = Tensor::zeros_like(&m).select_assign(
-                    0,
-                    split_inds.clone(),
-                    (cur_scale.clone() / 1.6).log(),
-                );
-                m.mask_where(split_mask.clone(), div_scales)
-            });
-            // Append newer smaller scales.
-            append_scales.push((cur_scale.clone() / 1.6).log());
-
-            // Sample new position for splits.
-            let cur_means = splats_pre_step.means.val().select(0, split_inds.clone());
-            let samples = quaternion_vec_multiply(
-                cur_rots.clone(),
-                Tensor::random([split_count, 3], Distribution::Normal(0.0, 0.5), &device)
-                    * cur_scale.clone(),
-            );
-            // Assign new means to current values.
-            Splats::map_param(&mut splats_pre_step.means, |m| {
-                let offset_means = Tensor::zeros_like(&m).select_assign(
-                    0,
-                    split_inds.clone(),
-                    cur_means.clone() - samples.clone(),
-                );
-                m.mask_where(split_mask.clone(), offset_means)
-            });
-
-            // Append new means with offset sample.
-            let samples_new = quaternion_vec_multiply(
-                cur_rots.clone(),
-                Tensor::random([split_count, 3], Distribution::Normal(0.0, 0.5), &device)
-                    * cur_scale,
-            );
-            append_means.push(cur_means.clone() + samples_new);
-        }
+        let stale = splats
+            .filter_3d
+            .as_ref()
+            .is_none_or(|f| f.dims()[0] != splats.num_splats());
+        let outdated = self
+            .filter_3d_iter
+            .is_none_or(|iter| self.iter >= iter + self.config.refine_every);
 
-        // Do processing on splat post step.
-        let mut splats = post_step_splat;
-
-        if !append_means.is_empty() {
-            let append_means = Tensor::cat(append_means, 0);
-            let append_rots = Tensor::cat(append_rots, 0);
-            let append_coeffs = Tensor::cat(append_coeffs, 0);
-            let append_opac = Tensor::cat(append_opac, 0);
-            let append_scales = Tensor::cat(append_scales, 0);
-
-            concat_splats(
-                &mut splats,
-                append_means,
-                append_rots,
-                append_coeffs,
-                append_opac,
-                append_scales,
-            );
+        if !stale && !outdated {
+            return splats;
         }
 
-        // Do some more processing. Important to do this last as otherwise you might mess up the correspondence
-        // of gradient <-> splat.
-        let start_count = splats.num_splats();
-
-        // Remove barely visible gaussians.
-        let alpha_mask = splats.opacity().lower_elem(self.config.cull_alpha_thresh);
-        prune_points(&mut splats, alpha_mask).await;
-
-        let alpha_pruned = start_count - splats.num_splats();
-
-        // Delete Gaussians with too large of a radius in world-units.
-        let scale_mask = splats
-            .scales()
-            .max_dim(1)
-            .squeeze(1)
-            .greater_elem(self.config.cull_scale_thresh);
-        prune_points(&mut splats, scale_mask).await;
-
-        let scale_pruned = start_count - splats.num_splats();
-
-        let refine_step = self.iter / self.config.refine_every;
-        if refine_step % self.config.reset_alpha_every_refine == 0 {
-            self.reset_opacity(&mut splats);
-        }
-
-        // Stats don't line up anymore so have to reset them.
-        self.reset_stats(splats.num_splats(), &device);
-
-        // TODO: Want to do state surgery and keep momenta for splats.
-        self.optim = self.opt_config.init();
-
-        let stats = RefineStats {
-            num_split: split_count,
-            num_cloned: clone_count,
-            num_transparent_pruned: alpha_pruned,
-            num_scale_pruned: scale_pruned,
-        };
+        self.filter_3d_iter = Some(self.iter);
+        let rates = scene.max_sampling_rates(splats.means.val().inner());
+        splats.with_filter_3d(Tensor::from_inner(rates))
+    }
+}
 
-        (splats, stats)
+/// Apply a function to both Adam moments of a parameter, if the parameter has any state yet.
+pub(crate) fn map_adam_state<B: AutodiffBackend, const D: usize>(
+    record: &mut OptimRecord<B>,
+    id: ParamId,
+    f: impl Fn(Tensor<B::InnerBackend, D>) -> Tensor<B::InnerBackend, D>,
+) {
+    if let Some(state) = record.remove(&id) {
+        let mut state: AdamState<B::InnerBackend, D> = state.into_state();
+        state.momentum.moment_1 = f(state.momentum.moment_1);
+        state.momentum.moment_2 = f(state.momentum.moment_2);
+        record.insert(id, AdaptorRecord::from_state(state));
     }
 }
 
-// Prunes points based on the given mask.
+// Prunes points based on the given mask. The optimizer state of the
+// remaining points is kept.
 //
 // Args:
 //   mask: bool[n]. If True, prune this Gaussian.
-pub async fn prune_points<B: AutodiffBackend>(splats: &mut Splats<B>, prune: Tensor<B, 1, Bool>) {
+pub async fn prune_points<B: AutodiffBackend>(
+    splats: &mut Splats<B>,
+    record: &mut OptimRecord<B>,
+    prune: Tensor<B, 1, Bool>,
+) {
     // bool[n]. If True, delete these Gaussians.
     let prune_count = prune.dims()[0];
 
@@ -640,11 +1049,39 @@ pub async fn prune_points<B: AutodiffBackend>(splats: &mut Splats<B>, prune: Ten
             .log_scales
             .clone()
             .map(|x| Tensor::from_inner(x.select(0, valid_inds.clone()).inner()).require_grad());
+
+        let valid_inds = valid_inds.inner();
+        map_adam_state::<B, 2>(record, splats.means.id, |m| m.select(0, valid_inds.clone()));
+        map_adam_state::<B, 3>(record, splats.sh_coeffs.id, |m| {
+            m.select(0, valid_inds.clone())
+        });
+        map_adam_state::<B, 2>(record, splats.rotation.id, |m| {
+            m.select(0, valid_inds.clone())
+        });
+        map_adam_state::<B, 1>(record, splats.raw_opacity.id, |m| {
+            m.select(0, valid_inds.clone())
+        });
+        map_adam_state::<B, 2>(record, splats.log_scales.id, |m| {
+            m.select(0, valid_inds.clone())
+        });
     }
 }
 
+/// Append zeros for `count` new splats to a moment tensor.
+fn extend_with_zeros<B: burn::prelude::Backend, const D: usize>(
+    m: Tensor<B, D>,
+    count: usize,
+) -> Tensor<B, D> {
+    let mut shape = m.dims();
+    shape[0] = count;
+    let zeros = Tensor::zeros(shape, &m.device());
+    Tensor::cat(vec![m, zeros], 0)
+}
+
+// Appends new splats. The new splats start with a fresh (zero) optimizer state.
 pub fn concat_splats<B: AutodiffBackend>(
     splats: &mut Splats<B>,
+    record: &mut OptimRecord<B>,
     means: Tensor<B, 2>,
     rotations: Tensor<B, 2>,
     sh_coeffs: Tensor<B, 3>,
@@ -666,4 +1103,62 @@ pub fn concat_splats<B: AutodiffBackend>(
     Splats::map_param(&mut splats.log_scales, |x| {
         Tensor::cat(vec![x, log_scales.clone()], 0)
     });
+
+    let count = means.dims()[0];
+    map_adam_state::<B, 2>(record, splats.means.id, |m| extend_with_zeros(m, count));
+    map_adam_state::<B, 3>(record, splats.sh_coeffs.id, |m| extend_with_zeros(m, count));
+    map_adam_state::<B, 2>(record, splats.rotation.id, |m| extend_with_zeros(m, count));
+    map_adam_state::<B, 1>(record, splats.raw_opacity.id, |m| {
+        extend_with_zeros(m, count)
+    });
+    map_adam_state::<B, 2>(record, splats.log_scales.id, |m| {
+        extend_with_zeros(m, count)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{depth_loss, DepthLoss};
+    use burn::backend::{ndarray::NdArrayDevice, NdArray};
+    use burn::tensor::Tensor;
+
+    fn depth(values: [[f32; 3]; 2]) -> Tensor<NdArray, 2> {
+        Tensor::from_floats(values, &NdArrayDevice::Cpu)
+    }
+
+    fn loss(pred: [[f32; 3]; 2], gt: [[f32; 3]; 2], mode: DepthLoss) -> f32 {
+        depth_loss(depth(pred), depth(gt), mode).into_scalar()
+    }
+
+    #[test]
+    fn metric_depth_loss_ignores_unknown_depths() {
+        let gt = [[1.0, 2.0, 0.0], [4.0, 0.0, 6.0]];
+        assert_eq!(loss(gt, gt, DepthLoss::Metric), 0.0);
+        // Unknown depths don't count, no matter what's rendered there.
+        assert_eq!(
+            loss([[1.0, 2.0, 9.0], [4.0, 9.0, 6.0]], gt, DepthLoss::Metric),
+            0.0
+        );
+        // The mean absolute error over the known depths.
+        let off = loss([[2.0, 2.0, 0.0], [4.0, 0.0, 8.0]], gt, DepthLoss::Metric);
+        assert!((off - 0.75).abs() < 1e-6, "{off}");
+    }
+
+    #[test]
+    fn scale_shift_invariant_depth_loss_aligns() {
+        let gt = [[1.0, 2.0, 0.0], [4.0, 0.0, 6.0]];
+        // Relative depths, eg. from a monocular depth network, in another scale and offset.
+        let pred = gt.map(|row| row.map(|d| (d - 0.5) / 3.0));
+        let aligned = loss(pred, gt, DepthLoss::ScaleShiftInvariant);
+        assert!(aligned.abs() < 1e-5, "{aligned}");
+        assert!(loss(pred, gt, DepthLoss::Metric) > 1.0);
+
+        // Depths that aren't an affine function of the ground truth can't be aligned.
+        let wrong = loss(
+            [[1.0, 2.0, 0.0], [1.0, 0.0, 6.0]],
+            gt,
+            DepthLoss::ScaleShiftInvariant,
+        );
+        assert!(wrong > 0.1, "{wrong}");
+    }
 }
@@ -107,6 +107,15 @@ impl OrbitControls {
             || self.dirty
     }
 
+    /// Look at the focus from the direction given by `rotation`, at the current radius.
+    pub fn look_from(&mut self, rotation: Quat) {
+        self.pan_momentum = Vec2::ZERO;
+        self.rotate_momentum = Vec2::ZERO;
+        self.rotation = rotation;
+        self.position = self.focus + rotation * Vec3A::new(0.0, 0.0, -self.radius());
+        self.dirty = true;
+    }
+
     pub(crate) fn transform(&self) -> Affine3A {
         Affine3A::from_rotation_translation(self.rotation, self.position.into())
     }
@@ -1,7 +1,8 @@
 use crate::{viewer::ViewerContext, ViewerPanel};
-use brush_dataset::{LoadDatasetArgs, LoadInitArgs};
+use brush_dataset::{run_config::RunConfig, LoadDatasetArgs, LoadInitArgs};
 use brush_train::train::TrainConfig;
 use egui::Slider;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
 
 enum Quality {
     Low,
@@ -14,10 +15,16 @@ pub(crate) struct LoadDataPanel {
     sh_degree: u32,
     quality: Quality,
     url: String,
+
+    // A loaded run file, which replaces the training settings below.
+    run: Option<RunConfig>,
+    run_send: UnboundedSender<RunConfig>,
+    run_receive: UnboundedReceiver<RunConfig>,
 }
 
 impl LoadDataPanel {
     pub(crate) fn new() -> Self {
+        let (run_send, run_receive) = tokio::sync::mpsc::unbounded_channel();
         Self {
             // Super high resolutions are a bit sketchy. Limit to at least
             // some size.
@@ -31,7 +38,38 @@ impl LoadDataPanel {
             sh_degree: 3,
             quality: Quality::Normal,
             url: "splat.com/example.ply".to_owned(),
+            run: None,
+            run_send,
+            run_receive,
+        }
+    }
+
+    fn run_config(&self) -> RunConfig {
+        let load_init_args = LoadInitArgs {
+            sh_degree: self.sh_degree,
+        };
+
+        // The dataset settings can still be changed after loading a run file.
+        if let Some(run) = &self.run {
+            return run
+                .clone()
+                .with_load_args(self.load_args.clone())
+                .with_init_args(load_init_args);
         }
+
+        let mut config = TrainConfig::default();
+        if matches!(self.quality, Quality::Low) {
+            config = config
+                .with_densify_grad_thresh(0.0003)
+                .with_refine_every(150)
+                .with_ssim_weight(0.0)
+                .with_cull_alpha_thresh(0.01);
+        }
+
+        RunConfig::new()
+            .with_train(config)
+            .with_load_args(self.load_args.clone())
+            .with_init_args(load_init_args)
     }
 }
 
@@ -42,10 +80,17 @@ impl ViewerPanel for LoadDataPanel {
 
     fn ui(&mut self, ui: &mut egui::Ui, context: &mut ViewerContext) {
         egui::ScrollArea::vertical().show(ui, |ui| {
-            ui.label("Select a .ply to visualize, or a .zip with training data.");
+            ui.label("Select a .ply to visualize, or a .zip or directory with training data.");
 
             let file = ui.button("Load file").clicked();
 
+            // Browsers and Android don't give access to directories.
+            let dir = if cfg!(not(any(target_family = "wasm", target_os = "android"))) {
+                ui.button("Load directory").clicked()
+            } else {
+                false
+            };
+
             ui.add_space(10.0);
             ui.text_edit_singleline(&mut self.url);
 
@@ -53,50 +98,85 @@ impl ViewerPanel for LoadDataPanel {
 
             ui.add_space(10.0);
 
-            if file || url {
-                let load_init_args = LoadInitArgs {
-                    sh_degree: self.sh_degree,
-                };
-
-                let mut config = TrainConfig::default();
-                if matches!(self.quality, Quality::Low) {
-                    config = config
-                        .with_densify_grad_thresh(0.0003)
-                        .with_refine_every(150)
-                        .with_ssim_weight(0.0)
-                        .with_cull_alpha_thresh(0.01);
-                }
-
+            if file || dir || url {
                 let source = if file {
                     crate::viewer::DataSource::PickFile
+                } else if dir {
+                    crate::viewer::DataSource::PickDirectory
                 } else {
                     crate::viewer::DataSource::Url(self.url.to_string())
                 };
-                context.start_data_load(source, self.load_args.clone(), load_init_args, config);
+                context.start_data_load(source, self.run_config());
+            }
+
+            while let Ok(run) = self.run_receive.try_recv() {
+                self.load_args = run.load_args.clone();
+                self.sh_degree = run.init_args.sh_degree;
+                self.run = Some(run);
             }
 
             ui.add_space(10.0);
             ui.heading("Train settings");
 
-            ui.label("Spherical Harmonics Degree:");
-            ui.add(Slider::new(&mut self.sh_degree, 0..=4));
-
             ui.horizontal(|ui| {
-                ui.label("Quality:");
-                if ui
-                    .selectable_label(matches!(self.quality, Quality::Low), "Low")
-                    .clicked()
-                {
-                    self.quality = Quality::Low;
+                if ui.button("Load run file").clicked() {
+                    let send = self.run_send.clone();
+                    let ctx = ui.ctx().clone();
+                    tokio::task::spawn(async move {
+                        let file = match rrfd::pick_file().await {
+                            Ok(file) => file,
+                            Err(e) => {
+                                log::error!("Failed to pick run file: {e}");
+                                return;
+                            }
+                        };
+                        let data = file.read().await;
+                        let run = String::from_utf8(data)
+                            .map_err(anyhow::Error::from)
+                            .and_then(|text| RunConfig::parse(&text));
+                        match run {
+                            Ok(run) => {
+                                let _ = send.send(run);
+                                ctx.request_repaint();
+                            }
+                            Err(e) => log::error!("Failed to read run file: {e}"),
+                        }
+                    });
                 }
-                if ui
-                    .selectable_label(matches!(self.quality, Quality::Normal), "Normal")
-                    .clicked()
-                {
-                    self.quality = Quality::Normal;
+
+                if self.run.is_some() && ui.button("Clear").clicked() {
+                    self.run = None;
                 }
             });
 
+            if let Some(run) = &self.run {
+                ui.label(match run.total_steps {
+                    Some(steps) => format!("Training with the loaded run file, for {steps} steps."),
+                    None => "Training with the loaded run file.".to_owned(),
+                });
+            }
+
+            ui.label("Spherical Harmonics Degree:");
+            ui.add(Slider::new(&mut self.sh_degree, 0..=4));
+
+            ui.add_enabled_ui(self.run.is_none(), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Quality:");
+                    if ui
+                        .selectable_label(matches!(self.quality, Quality::Low), "Low")
+                        .clicked()
+                    {
+                        self.quality = Quality::Low;
+                    }
+                    if ui
+                        .selectable_label(matches!(self.quality, Quality::Normal), "Normal")
+                        .clicked()
+                    {
+                        self.quality = Quality::Normal;
+                    }
+                });
+            });
+
             let mut limit_res = self.load_args.max_resolution.is_some();
             if ui
                 .checkbox(&mut limit_res, "Limit training resolution")
@@ -12,7 +12,7 @@ use burn_wgpu::WgpuDevice;
 use brush_render::{gaussian_splats::Splats, AutodiffBackend, Backend};
 use brush_train::{image::tensor_into_image, scene::Scene};
 use brush_train::{ssim::Ssim, train::TrainStepStats};
-use burn::tensor::{activation::sigmoid, ElementConversion};
+use burn::tensor::{activation::sigmoid, ElementConversion, Tensor};
 use rerun::{Color, FillMode, RecordingStream};
 use tokio::{sync::mpsc::UnboundedSender, task};
 
@@ -266,32 +266,47 @@ impl VisualizeTools {
             rec.log("lr/coeffs", &rerun::Scalar::new(stats.lr_coeffs))?;
             rec.log("lr/opac", &rerun::Scalar::new(stats.lr_opac))?;
 
-            let [batch_size, img_h, img_w, _] = stats.pred_images.dims();
-            let pred_rgb =
-                stats
-                    .pred_images
-                    .clone()
-                    .slice([0..batch_size, 0..img_h, 0..img_w, 0..3]);
-            let gt_rgb = stats
-                .gt_images
-                .clone()
-                .slice([0..batch_size, 0..img_h, 0..img_w, 0..3]);
-            let mse = (pred_rgb.clone() - gt_rgb.clone()).powf_scalar(2.0).mean();
-            let psnr = mse.recip().log() * 10.0 / std::f32::consts::LN_10;
             rec.log(
                 "losses/main",
                 &rerun::Scalar::new(stats.loss.clone().into_scalar_async().await.elem::<f64>()),
             )?;
+
+            for (name, reg) in [
+                ("losses/opacity_reg", &stats.opacity_reg),
+                ("losses/scale_reg", &stats.scale_reg),
+                ("losses/anisotropy_reg", &stats.anisotropy_reg),
+            ] {
+                if let Some(reg) = reg {
+                    let reg = reg.clone().into_scalar_async().await.elem::<f64>();
+                    rec.log(name, &rerun::Scalar::new(reg))?;
+                }
+            }
+
+            // Views in a batch can have different sizes, so average the metrics per view.
+            let mut psnrs = vec![];
+            let mut ssims = vec![];
+
+            for (pred_image, gt_image) in stats.pred_images.iter().zip(&stats.gt_images) {
+                let [img_h, img_w, _] = pred_image.dims();
+                let pred_rgb = pred_image.clone().slice([0..img_h, 0..img_w, 0..3]);
+                let gt_rgb = gt_image.clone().slice([0..img_h, 0..img_w, 0..3]);
+                let mse = (pred_rgb.clone() - gt_rgb.clone()).powf_scalar(2.0).mean();
+                psnrs.push(mse.recip().log() * 10.0 / std::f32::consts::LN_10);
+
+                // TODO: Bit annoyingly expensive to recalculate this here. Idk if train stats should be split into
+                // "very cheap" and somewhat more expensive stats.
+                let device = gt_rgb.device();
+                let ssim_measure = Ssim::new(11, 3, &device);
+                ssims.push(ssim_measure.ssim(pred_rgb.unsqueeze(), gt_rgb.unsqueeze()));
+            }
+
+            let psnr = Tensor::cat(psnrs, 0).mean();
+            let ssim = Tensor::cat(ssims, 0).mean();
+
             rec.log(
                 "psnr/train",
                 &rerun::Scalar::new(psnr.into_scalar_async().await.elem::<f64>()),
             )?;
-            let device = gt_rgb.device();
-
-            // TODO: Bit annoyingly expensive to recalculate this here. Idk if train stats should be split into
-            // "very cheap" and somewhat more expensive stats.
-            let ssim_measure = Ssim::new(11, 3, &device);
-            let ssim = ssim_measure.ssim(pred_rgb.clone().unsqueeze(), gt_rgb.unsqueeze());
             rec.log(
                 "ssim/train",
                 &rerun::Scalar::new(ssim.into_scalar_async().await.elem::<f64>()),
@@ -319,6 +334,14 @@ impl VisualizeTools {
                     "refine/num_cloned",
                     &rerun::Scalar::new(refine.num_cloned as f64),
                 )?;
+                rec.log(
+                    "refine/num_relocated",
+                    &rerun::Scalar::new(refine.num_relocated as f64),
+                )?;
+                rec.log(
+                    "refine/num_rejected",
+                    &rerun::Scalar::new(refine.num_rejected as f64),
+                )?;
                 rec.log(
                     "refine/num_transparent_pruned",
                     &rerun::Scalar::new(refine.num_transparent_pruned as f64),
@@ -1,4 +1,4 @@
-use brush_dataset::splat_export;
+use brush_dataset::{run_config::run_file_path, splat_export};
 use brush_ui::burn_texture::BurnTexture;
 use burn_wgpu::Wgpu;
 use core::f32;
@@ -6,7 +6,7 @@ use egui::epaint::mutex::RwLock as EguiRwLock;
 use std::{sync::Arc, time::Duration};
 
 use brush_render::{
-    camera::{focal_to_fov, fov_to_focal},
+    camera::{focal_to_fov, fov_to_focal, Camera, Projection},
     gaussian_splats::Splats,
 };
 use eframe::egui_wgpu::Renderer;
@@ -41,6 +41,11 @@ pub(crate) struct ScenePanel {
     last_size: glam::UVec2,
     dirty: bool,
 
+    // Whether to render with an orthographic camera, eg. for top down views.
+    ortho: bool,
+    // A fixed scale of the orthographic camera. Follows the zoom of the orbit camera if not set.
+    ortho_units_per_pixel: Option<f64>,
+
     queue: Arc<wgpu::Queue>,
     device: Arc<wgpu::Device>,
     renderer: Arc<EguiRwLock<Renderer>>,
@@ -64,6 +69,8 @@ impl ScenePanel {
             live_update: true,
             paused: false,
             dirty: true,
+            ortho: false,
+            ortho_units_per_pixel: None,
             last_size: glam::UVec2::ZERO,
             is_loading: false,
             is_training: false,
@@ -75,6 +82,24 @@ impl ScenePanel {
         }
     }
 
+    // An orthographic camera showing the same area at the orbit focus as the perspective camera,
+    // unless a fixed scale is set.
+    fn ortho_camera(&self, context: &ViewerContext, size: glam::UVec2) -> Camera {
+        let size = size.as_dvec2();
+        let extent = match self.ortho_units_per_pixel {
+            Some(units_per_pixel) => size * units_per_pixel,
+            None => {
+                let height =
+                    2.0 * context.controls.radius() as f64 * (context.camera.fov_y / 2.0).tan();
+                glam::dvec2(height * size.x / size.y, height)
+            }
+        };
+        context
+            .camera
+            .clone()
+            .with_projection(Projection::Orthographic { extent })
+    }
+
     pub(crate) fn draw_splats(
         &mut self,
         ui: &mut egui::Ui,
@@ -146,7 +171,12 @@ impl ScenePanel {
         // If this viewport is re-rendering.
         if ui.ctx().has_requested_repaint() && size.x > 0 && size.y > 0 && self.dirty {
             let _span = trace_span!("Render splats").entered();
-            let (img, _) = splats.render(&context.camera, size, true);
+            let camera = if self.ortho {
+                self.ortho_camera(context, size)
+            } else {
+                context.camera.clone()
+            };
+            let (img, _) = splats.render(&camera, size, glam::Vec3::ZERO, true);
             self.backbuffer.update_texture(img, self.renderer.clone());
             self.dirty = false;
             self.last_size = size;
@@ -301,6 +331,56 @@ For bigger training runs consider using the native app."#,
 
             self.draw_splats(ui, context, &splats, delta_time);
 
+            ui.horizontal(|ui| {
+                if ui.selectable_label(self.ortho, "⬜ Orthographic").clicked() {
+                    self.ortho = !self.ortho;
+                    self.dirty = true;
+                }
+
+                if self.ortho && self.last_size.y > 0 {
+                    let camera = self.ortho_camera(context, self.last_size);
+                    if let Projection::Orthographic { extent } = camera.projection {
+                        let mut units_per_pixel = extent.y / self.last_size.y as f64;
+                        let response = ui
+                            .add(
+                                egui::DragValue::new(&mut units_per_pixel)
+                                    .speed(units_per_pixel * 0.01)
+                                    .range(1e-6..=f64::MAX)
+                                    .suffix(" units / pixel"),
+                            )
+                            .on_hover_text("Scene units per pixel, metres for metric scenes.");
+                        if response.changed() {
+                            self.ortho_units_per_pixel = Some(units_per_pixel);
+                            self.dirty = true;
+                        }
+                    }
+
+                    if self.ortho_units_per_pixel.is_some()
+                        && ui
+                            .button("Auto")
+                            .on_hover_text("Follow the zoom of the camera again")
+                            .clicked()
+                    {
+                        self.ortho_units_per_pixel = None;
+                        self.dirty = true;
+                    }
+                }
+
+                ui.separator();
+
+                // Views along the vertical axis of the scene, and level with it. The orbit
+                // controls have their vertical axis along Y, with up being -Y like the cameras.
+                for (label, rotation) in [
+                    ("Top", Quat::from_rotation_x(-f32::consts::FRAC_PI_2)),
+                    ("Front", Quat::IDENTITY),
+                    ("Side", Quat::from_rotation_y(f32::consts::FRAC_PI_2)),
+                ] {
+                    if ui.button(label).clicked() {
+                        context.controls.look_from(rotation);
+                    }
+                }
+            });
+
             if self.is_loading {
                 ui.horizontal(|ui| {
                     ui.label("Loading... Please wait.");
@@ -359,6 +439,8 @@ For bigger training runs consider using the native app."#,
 
                     if ui.button("⬆ Export").clicked() {
                         let splats = splats.clone();
+                        // Trained splats get the run file to reproduce them saved next to them.
+                        let run = context.run.clone().filter(|_| self.is_training);
 
                         let fut = async move {
                             let file = rrfd::save_file("export.ply").await;
@@ -382,6 +464,17 @@ For bigger training runs consider using the native app."#,
                                     if let Err(e) = file.write(&data).await {
                                         log::error!("Failed to write file: {e}");
                                     }
+
+                                    // Only possible where the file has a path to save next to.
+                                    if let (Some(run), Some(path)) = (run, file.path()) {
+                                        let path = run_file_path(&path);
+                                        let written = run
+                                            .to_toml()
+                                            .and_then(|text| Ok(std::fs::write(&path, text)?));
+                                        if let Err(e) = written {
+                                            log::error!("Failed to write run file: {e}");
+                                        }
+                                    }
                                 }
                             }
                         };
@@ -1,19 +1,13 @@
 use async_fn_stream::try_fn_stream;
 
-use brush_dataset::{
-    scene_loader::SceneLoader, zip::DatasetZip, Dataset, LoadDatasetArgs, LoadInitArgs,
-};
+use brush_dataset::{fs::DatasetFs, run_config::RunConfig, scene_loader::SceneLoader, Dataset};
 use brush_render::gaussian_splats::{RandomSplatsConfig, Splats};
-use brush_train::train::{SplatTrainer, TrainConfig};
+use brush_train::train::SplatTrainer;
 use burn::module::AutodiffModule;
 use burn_jit::cubecl::Runtime;
 use burn_wgpu::{Wgpu, WgpuDevice, WgpuRuntime};
 use rand::SeedableRng;
-use tokio::io::AsyncReadExt;
-use tokio::{
-    io::AsyncRead,
-    sync::mpsc::{error::TryRecvError, Receiver},
-};
+use tokio::sync::mpsc::{error::TryRecvError, Receiver};
 use tokio_stream::{Stream, StreamExt};
 use tracing::{trace_span, Instrument};
 use web_time::Instant;
@@ -28,24 +22,16 @@ pub enum TrainMessage {
     Eval { view_count: Option<usize> },
 }
 
-pub(crate) fn train_loop<T: AsyncRead + Unpin + 'static>(
-    mut data: T,
+pub(crate) fn train_loop<F: DatasetFs>(
+    dataset_fs: F,
     device: WgpuDevice,
     mut receiver: Receiver<TrainMessage>,
-    load_data_args: LoadDatasetArgs,
-    load_init_args: LoadInitArgs,
-    config: TrainConfig,
+    run: RunConfig,
 ) -> impl Stream<Item = anyhow::Result<ProcessMessage>> {
     try_fn_stream(|emitter| async move {
-        let mut bytes = vec![];
-        data.read_to_end(&mut bytes).await?;
-        // TODO: async zip ideally.
-        let zip_data = DatasetZip::from_data(bytes)?;
-
-        let batch_size = 1;
-
-        // Maybe good if the seed would be configurable.
-        let seed = 42;
+        let seed = run.seed();
+        let config = run.train.clone();
+        let load_init_args = &run.init_args;
         <Wgpu as burn::prelude::Backend>::seed(seed);
         let mut rng = rand::rngs::StdRng::from_seed([seed as u8; 32]);
 
@@ -54,7 +40,7 @@ pub(crate) fn train_loop<T: AsyncRead + Unpin + 'static>(
 
         let mut dataset = Dataset::empty();
         let (mut splat_stream, mut data_stream) =
-            brush_dataset::load_dataset(zip_data.clone(), &load_data_args, &device)?;
+            brush_dataset::load_dataset(dataset_fs, &run.load_args, &device)?;
 
         // Read initial splats if any.
         while let Some(message) = splat_stream.next().await {
@@ -101,8 +87,9 @@ pub(crate) fn train_loop<T: AsyncRead + Unpin + 'static>(
         let train_scene = dataset.train.clone();
         let eval_scene = dataset.eval.clone();
 
-        let mut dataloader = SceneLoader::new(&train_scene, batch_size, seed, &device);
-        let mut trainer = SplatTrainer::new(splats.num_splats(), &config, &device);
+        let mut dataloader = SceneLoader::new(&train_scene, run.batch_size, seed, 0, &device);
+        let mut trainer = SplatTrainer::new(splats.num_splats(), &config, &device)
+            .with_train_views(train_scene.views.len(), &device);
 
         let mut is_paused = false;
 
@@ -130,6 +117,7 @@ pub(crate) fn train_loop<T: AsyncRead + Unpin + 'static>(
                             splats.valid(),
                             eval_scene,
                             view_count,
+                            config.appearance_transform(),
                             &mut rng,
                             &device,
                         )
@@ -143,6 +131,10 @@ pub(crate) fn train_loop<T: AsyncRead + Unpin + 'static>(
                             .await;
                     }
                 }
+                // Training is done when a run file sets the number of steps, wait for new messages.
+                None if run.total_steps.is_some_and(|steps| trainer.iter >= steps) => {
+                    is_paused = true;
+                }
                 // By default, continue training.
                 None => {
                     let batch = dataloader
@@ -150,6 +142,7 @@ pub(crate) fn train_loop<T: AsyncRead + Unpin + 'static>(
                         .instrument(trace_span!("Get batch"))
                         .await;
 
+                    splats = trainer.update_filter_3d(splats, &train_scene);
                     let (new_splats, stats) = trainer
                         .step(batch, splats)
                         .instrument(trace_span!("Train step"))
@@ -4,11 +4,14 @@ use std::{pin::Pin, sync::Arc};
 
 use async_fn_stream::try_fn_stream;
 
-use brush_dataset::{self, splat_import, Dataset, LoadDatasetArgs, LoadInitArgs};
+use brush_dataset::directory::DatasetDirectory;
+use brush_dataset::run_config::RunConfig;
+use brush_dataset::zip::DatasetZip;
+use brush_dataset::{self, splat_import, Dataset};
 use brush_render::camera::Camera;
 use brush_render::gaussian_splats::Splats;
+use brush_train::eval::EvalStats;
 use brush_train::train::TrainStepStats;
-use brush_train::{eval::EvalStats, train::TrainConfig};
 use burn::backend::Autodiff;
 use burn_wgpu::{Wgpu, WgpuDevice};
 use eframe::egui;
@@ -115,6 +118,9 @@ pub(crate) struct ViewerContext {
 
     pub model_transform: Affine3A,
 
+    // The run description of the current training, saved along with exports.
+    pub run: Option<RunConfig>,
+
     device: WgpuDevice,
     ctx: egui::Context,
 
@@ -128,20 +134,36 @@ fn process_loop(
     source: DataSource,
     device: WgpuDevice,
     train_receiver: Receiver<TrainMessage>,
-    load_data_args: LoadDatasetArgs,
-    load_init_args: LoadInitArgs,
-    train_config: TrainConfig,
+    run: RunConfig,
 ) -> Pin<Box<impl Stream<Item = anyhow::Result<ProcessMessage>>>> {
     let stream = try_fn_stream(|emitter| async move {
         let _ = emitter.emit(ProcessMessage::NewSource).await;
 
+        // Directories are read in place, rather than read as a single blob of data.
+        if let DataSource::PickDirectory = source {
+            let path = rrfd::pick_directory().await?;
+            log::info!("Attempting to load data from directory {path:?}");
+
+            let _ = emitter
+                .emit(ProcessMessage::StartLoading { training: true })
+                .await;
+
+            let stream =
+                train_loop::train_loop(DatasetDirectory::new(path)?, device, train_receiver, run);
+            let mut stream = std::pin::pin!(stream);
+            while let Some(message) = stream.next().await {
+                emitter.emit(message?).await;
+            }
+            return Ok(());
+        }
+
         // Small hack to peek some bytes: Read them
         // and add them at the start again.
         let data = source.read().await?;
         let mut data = BufReader::new(data);
         let mut peek = [0; 128];
         data.read_exact(&mut peek).await?;
-        let data = std::io::Cursor::new(peek).chain(data);
+        let mut data = std::io::Cursor::new(peek).chain(data);
 
         log::info!("{:?}", String::from_utf8(peek.to_vec()));
 
@@ -179,14 +201,12 @@ fn process_loop(
                 .emit(ProcessMessage::StartLoading { training: true })
                 .await;
 
-            let stream = train_loop::train_loop(
-                data,
-                device,
-                train_receiver,
-                load_data_args,
-                load_init_args,
-                train_config,
-            );
+            let mut bytes = vec![];
+            data.read_to_end(&mut bytes).await?;
+            // TODO: async zip ideally.
+            let zip_data = DatasetZip::from_data(bytes)?;
+
+            let stream = train_loop::train_loop(zip_data, device, train_receiver, run);
             let mut stream = std::pin::pin!(stream);
             while let Some(message) = stream.next().await {
                 emitter.emit(message?).await;
@@ -206,6 +226,7 @@ fn process_loop(
 #[derive(Debug)]
 pub enum DataSource {
     PickFile,
+    PickDirectory,
     Url(String),
 }
 
@@ -223,6 +244,9 @@ impl DataSource {
                 let data = picked.read().await;
                 Ok(Box::pin(std::io::Cursor::new(data)))
             }
+            DataSource::PickDirectory => {
+                anyhow::bail!("A directory can't be read as a single file.")
+            }
             DataSource::Url(url) => {
                 let mut url = url.to_owned();
                 if !url.starts_with("http://") && !url.starts_with("https://") {
@@ -298,6 +322,7 @@ impl ViewerContext {
             device,
             ctx,
             dataset: Dataset::empty(),
+            run: None,
             rec_process_msg: None,
             send_train_msg: None,
             rec_ui_control_msg: inner_control,
@@ -328,13 +353,7 @@ impl ViewerContext {
         self.camera = cam.clone();
     }
 
-    pub(crate) fn start_data_load(
-        &mut self,
-        source: DataSource,
-        load_data_args: LoadDatasetArgs,
-        load_init_args: LoadInitArgs,
-        train_config: TrainConfig,
-    ) {
+    pub(crate) fn start_data_load(&mut self, source: DataSource, run: RunConfig) {
         let device = self.device.clone();
         log::info!("Start data load {source:?}");
 
@@ -349,6 +368,7 @@ impl ViewerContext {
         self.send_train_msg = Some(train_sender);
 
         self.dataset = Dataset::empty();
+        self.run = Some(run.clone());
         let ctx = self.ctx.clone();
         ctx.request_repaint();
 
@@ -356,15 +376,8 @@ impl ViewerContext {
             ctx.request_repaint();
 
             // Map errors to a viewer message containing thee error.
-            let mut stream = process_loop(
-                source,
-                device,
-                train_receiver,
-                load_data_args,
-                load_init_args,
-                train_config,
-            )
-            .map(|m| m.unwrap_or_else(|e| ProcessMessage::Error(Arc::new(e))));
+            let mut stream = process_loop(source, device, train_receiver, run)
+                .map(|m| m.unwrap_or_else(|e| ProcessMessage::Error(Arc::new(e))));
 
             // Loop until there are no more messages, processing is done.
             while let Some(m) = stream.next().await {
@@ -400,12 +413,7 @@ impl ViewerContext {
         while let Ok(m) = self.rec_ui_control_msg.try_recv() {
             match m {
                 UiControlMessage::LoadData(url) => {
-                    self.start_data_load(
-                        DataSource::Url(url.to_owned()),
-                        LoadDatasetArgs::default(),
-                        LoadInitArgs::default(),
-                        TrainConfig::default(),
-                    );
+                    self.start_data_load(DataSource::Url(url.to_owned()), RunConfig::new());
                 }
             }
         }
@@ -539,12 +547,9 @@ impl Viewer {
 
         let url = search_params.get("url");
         if let Some(url) = url {
-            tree_ctx.context.start_data_load(
-                DataSource::Url(url.to_owned()),
-                LoadDatasetArgs::default(),
-                LoadInitArgs::default(),
-                TrainConfig::default(),
-            );
+            tree_ctx
+                .context
+                .start_data_load(DataSource::Url(url.to_owned()), RunConfig::new());
         }
 
         Viewer {
@@ -4,8 +4,8 @@ use byteorder::{LittleEndian, ReadBytesExt};
 use std::collections::HashMap;
 use std::io::{self, BufRead, Read};
 
-// TODO: Really these should each hold their respective params but bit of an annoying refactor. We just need
-// basic params.
+// The parameters of each model are kept in Camera::params, see Camera::focal, Camera::principal_point
+// and Camera::distortion to interpret them.
 #[derive(Debug, Clone)]
 pub enum CameraModel {
     SimplePinhole,
@@ -56,6 +56,122 @@ impl CameraModel {
     }
 }
 
+/// Lens distortion, as a mapping from ideal pinhole coordinates to distorted coordinates.
+///
+/// Coordinates are normalized image coordinates, ie. (x / z, y / z) in camera space. The formulas
+/// follow COLMAP, see `src/colmap/sensor/models.h` there.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distortion {
+    None,
+    /// Brown-Conrady distortion as used by OpenCV. The radial factor is
+    /// `(1 + k1 r² + k2 r⁴ + k3 r⁶ + k4 r⁸) / (1 + k5 r² + k6 r⁴ + k7 r⁶)`,
+    /// where k4 is only used by nerfstudio, and k5..k7 only by FULL_OPENCV.
+    OpenCv {
+        radial: [f64; 4],
+        radial_denominator: [f64; 3],
+        tangential: [f64; 2],
+    },
+    /// Equidistant fisheye distortion, with the tangential and thin prism terms of the
+    /// THIN_PRISM_FISHEYE model.
+    Fisheye {
+        radial: [f64; 4],
+        tangential: [f64; 2],
+        thin_prism: [f64; 2],
+    },
+    /// The FOV model of Devernay and Faugeras.
+    Fov {
+        omega: f64,
+    },
+}
+
+impl Distortion {
+    pub fn is_none(&self) -> bool {
+        matches!(self, Distortion::None)
+    }
+
+    /// Whether this is a fisheye lens, which is better described by an equidistant projection
+    /// than by a pinhole camera.
+    pub fn is_fisheye(&self) -> bool {
+        matches!(self, Distortion::Fisheye { .. })
+    }
+
+    /// Apply the distortion to equidistant image coordinates, ie. the direction to a point scaled
+    /// by its angle with the optical axis. Unlike normalized image coordinates, these can describe
+    /// points 90 degrees or more from the optical axis. Returns NaN for those with lenses that
+    /// can't see them.
+    pub fn distort_equidistant(&self, e: glam::DVec2) -> glam::DVec2 {
+        match *self {
+            Distortion::Fisheye {
+                radial: [k1, k2, k3, k4],
+                tangential: [p1, p2],
+                thin_prism: [sx1, sy1],
+            } => {
+                let (u, v) = (e.x, e.y);
+                let t2 = u * u + v * v;
+                let radial = 1.0 + t2 * (k1 + t2 * (k2 + t2 * (k3 + t2 * k4)));
+                glam::dvec2(
+                    u * radial + 2.0 * p1 * u * v + p2 * (t2 + 2.0 * u * u) + sx1 * t2,
+                    v * radial + 2.0 * p2 * u * v + p1 * (t2 + 2.0 * v * v) + sy1 * t2,
+                )
+            }
+            _ => {
+                let theta = e.length();
+                if theta >= std::f64::consts::FRAC_PI_2 {
+                    glam::DVec2::NAN
+                } else if theta > f64::EPSILON {
+                    self.distort(e * theta.tan() / theta)
+                } else {
+                    self.distort(e)
+                }
+            }
+        }
+    }
+
+    /// Apply the distortion to normalized image coordinates.
+    pub fn distort(&self, p: glam::DVec2) -> glam::DVec2 {
+        match *self {
+            Distortion::None => p,
+            Distortion::OpenCv {
+                radial: [k1, k2, k3, k4],
+                radial_denominator: [k5, k6, k7],
+                tangential: [p1, p2],
+            } => {
+                let (u, v) = (p.x, p.y);
+                let r2 = u * u + v * v;
+                let numerator = 1.0 + r2 * (k1 + r2 * (k2 + r2 * (k3 + r2 * k4)));
+                let denominator = 1.0 + r2 * (k5 + r2 * (k6 + r2 * k7));
+                let radial = numerator / denominator;
+                glam::dvec2(
+                    u * radial + 2.0 * p1 * u * v + p2 * (r2 + 2.0 * u * u),
+                    v * radial + 2.0 * p2 * u * v + p1 * (r2 + 2.0 * v * v),
+                )
+            }
+            Distortion::Fisheye { .. } => {
+                // Map to the equidistant projection first.
+                let r = p.length();
+                if r > f64::EPSILON {
+                    self.distort_equidistant(p * r.atan() / r)
+                } else {
+                    self.distort_equidistant(p)
+                }
+            }
+            Distortion::Fov { omega } => {
+                let r2 = p.length_squared();
+                let factor = if omega * omega < f64::EPSILON {
+                    // Taylor expansion around omega = 0.
+                    1.0 + omega * omega / 12.0 - r2 * omega * omega / 3.0
+                } else if r2 < f64::EPSILON {
+                    2.0 * (omega / 2.0).tan() / omega
+                } else {
+                    let r = r2.sqrt();
+                    (r * 2.0 * (omega / 2.0).tan()).atan() / (r * omega)
+                };
+                p * factor
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Camera {
     pub id: i32,
@@ -132,6 +248,54 @@ impl Camera {
         }] as f32;
         glam::vec2(x, y)
     }
+
+    pub fn distortion(&self) -> Distortion {
+        let p = &self.params;
+        match self.model {
+            CameraModel::SimplePinhole | CameraModel::Pinhole => Distortion::None,
+            CameraModel::SimpleRadial => Distortion::OpenCv {
+                radial: [p[3], 0.0, 0.0, 0.0],
+                radial_denominator: [0.0; 3],
+                tangential: [0.0; 2],
+            },
+            CameraModel::Radial => Distortion::OpenCv {
+                radial: [p[3], p[4], 0.0, 0.0],
+                radial_denominator: [0.0; 3],
+                tangential: [0.0; 2],
+            },
+            CameraModel::OpenCV => Distortion::OpenCv {
+                radial: [p[4], p[5], 0.0, 0.0],
+                radial_denominator: [0.0; 3],
+                tangential: [p[6], p[7]],
+            },
+            CameraModel::FullOpenCV => Distortion::OpenCv {
+                radial: [p[4], p[5], p[8], 0.0],
+                radial_denominator: [p[9], p[10], p[11]],
+                tangential: [p[6], p[7]],
+            },
+            CameraModel::OpenCvFishEye => Distortion::Fisheye {
+                radial: [p[4], p[5], p[6], p[7]],
+                tangential: [0.0; 2],
+                thin_prism: [0.0; 2],
+            },
+            CameraModel::SimpleRadialFisheye => Distortion::Fisheye {
+                radial: [p[3], 0.0, 0.0, 0.0],
+                tangential: [0.0; 2],
+                thin_prism: [0.0; 2],
+            },
+            CameraModel::RadialFisheye => Distortion::Fisheye {
+                radial: [p[3], p[4], 0.0, 0.0],
+                tangential: [0.0; 2],
+                thin_prism: [0.0; 2],
+            },
+            CameraModel::ThinPrismFisheye => Distortion::Fisheye {
+                radial: [p[4], p[5], p[8], p[9]],
+                tangential: [p[6], p[7]],
+                thin_prism: [p[10], p[11]],
+            },
+            CameraModel::Fov => Distortion::Fov { omega: p[4] },
+        }
+    }
 }
 
 fn parse<T: std::str::FromStr>(s: &str) -> io::Result<T> {
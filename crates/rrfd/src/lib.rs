@@ -25,6 +25,16 @@ impl FileHandle {
         }
     }
 
+    /// The path of the file, on platforms where files have one.
+    pub fn path(&self) -> Option<std::path::PathBuf> {
+        match self {
+            #[cfg(not(any(target_os = "android", target_family = "wasm")))]
+            FileHandle::Rfd(file_handle) => Some(file_handle.path().to_owned()),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
     pub async fn read(mut self) -> Vec<u8> {
         match &mut self {
             #[cfg(not(target_os = "android"))]
@@ -59,6 +69,25 @@ pub async fn pick_file() -> Result<FileHandle> {
     }
 }
 
+/// Pick a directory and return its path.
+///
+/// Nb: Only works on desktop platforms, browsers and Android don't expose directory paths.
+pub async fn pick_directory() -> Result<std::path::PathBuf> {
+    #[cfg(not(any(target_os = "android", target_family = "wasm")))]
+    {
+        let dir = rfd::AsyncFileDialog::new()
+            .pick_folder()
+            .await
+            .context("No folder selected")?;
+        Ok(dir.path().to_owned())
+    }
+
+    #[cfg(any(target_os = "android", target_family = "wasm"))]
+    {
+        anyhow::bail!("Picking a directory isn't supported on this platform.")
+    }
+}
+
 /// Saves data to a file and returns the filename the data was saved too.
 ///
 /// Nb: Does not work on Android currently.
@@ -9,13 +9,13 @@ use brush_render::{
 };
 use brush_train::{
     image::image_to_tensor,
+    lr_schedule::LrSchedule,
     scene::SceneView,
     train::{SceneBatch, SplatTrainer, TrainConfig},
 };
 use brush_ui::burn_texture::BurnTexture;
 use burn::{
     backend::{wgpu::WgpuDevice, Autodiff, Wgpu},
-    lr_scheduler::exponential::ExponentialLrSchedulerConfig,
     module::AutodiffModule,
 };
 use egui::{load::SizedTexture, ImageSource, TextureHandle, TextureOptions};
@@ -59,8 +59,11 @@ fn spawn_train_loop(
 
         // One batch of training data, it's the same every step so can just cosntruct it once.
         let batch = SceneBatch {
-            gt_images: image_to_tensor(&view.image, &device).unsqueeze(),
+            gt_images: vec![image_to_tensor(&view.image, &device)],
+            gt_depths: vec![None],
+            gt_masks: vec![None],
             gt_views: vec![view],
+            gt_view_indices: vec![0],
             scene_extent: 1.0,
         };
 
@@ -101,9 +104,6 @@ impl App {
             state.queue.clone(),
         );
 
-        let lr_max = 1.5e-4;
-        let decay = 1.0;
-
         let image = image::open("./crab.jpg").unwrap();
 
         let fov_x = 0.5 * std::f64::consts::PI;
@@ -123,6 +123,8 @@ impl App {
             name: "crabby".to_owned(),
             camera,
             image: Arc::new(image),
+            depth: None,
+            mask: None,
         };
         let (sender, receiver) = tokio::sync::mpsc::channel(32);
 
@@ -134,7 +136,9 @@ impl App {
             cc.egui_ctx
                 .load_texture("nearest_view_tex", color_img, TextureOptions::default());
 
-        let config = TrainConfig::new(ExponentialLrSchedulerConfig::new(lr_max, decay))
+        let config = TrainConfig::new()
+            .with_lr_mean(1.5e-4)
+            .with_lr_mean_schedule(LrSchedule::Constant)
             .with_max_refine_step(u32::MAX) // Just keep refining
             .with_warmup_steps(100) // Don't really need a warmup for simple 2D
             .with_reset_alpha_every_refine(u32::MAX); // Don't use alpha reset.
@@ -167,6 +171,7 @@ impl eframe::App for App {
             let (img, _) = msg.splats.render(
                 &self.view.camera,
                 glam::uvec2(image.width(), image.height()),
+                glam::Vec3::ZERO,
                 true,
             );
 